@@ -0,0 +1,104 @@
+//! Measures how many heap allocations parsing typical `PRIVMSG`s and cloning a parsed `PRIVMSG`
+//! (e.g. when the same message is handed out to many subscribers) take, and how long they take.
+//!
+//! Run with `cargo run --release --example allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use twitch_irc::message::{IRCMessage, ServerMessage};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const CORPUS: &[&str] = &[
+    "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam",
+    "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=1902:6-10,29-33,35-39/499:45-46,48-49/490:51-52/25:0-4,12-16,18-22;flags=;id=f9c5774b-faa7-4378-b1af-c4e08b532dc2;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594556065407;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa Keepo Kappa Kappa test Keepo Keepo 123 :) :) :P",
+    "@badge-info=subscriber/5;badges=subscriber/3,premium/1;color=#FF4500;display-name=Zonianer;emotes=25:0-4;flags=;id=1c3a0b54-6cc0-4e1b-93b1-ad1b1b5e1e2a;mod=0;room-id=11148817;subscriber=1;tmi-sent-ts=1594556065407;turbo=0;user-id=11111111;user-type= :zonianer!zonianer@zonianer.tmi.twitch.tv PRIVMSG #pajlada :Kappa xD",
+    "@badge-info=;badges=glhf-pledge/1;color=;display-name=someone;emotes=;flags=;id=4b5b6a1e-2a3b-4c5d-8e9f-0a1b2c3d4e5f;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594556065407;turbo=0;user-id=22222222;user-type= :someone!someone@someone.tmi.twitch.tv PRIVMSG #pajlada :hello chat, how is everyone doing today?",
+];
+
+const PARSE_ROUNDS: usize = 100_000;
+const CLONE_ROUNDS: usize = 1_000_000;
+
+fn main() {
+    measure_parsing();
+    measure_cloning();
+}
+
+fn measure_parsing() {
+    let irc_messages = CORPUS
+        .iter()
+        .map(|line| IRCMessage::parse(line).unwrap())
+        .collect::<Vec<_>>();
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started_at = Instant::now();
+    for _ in 0..PARSE_ROUNDS {
+        for irc_message in &irc_messages {
+            let message = ServerMessage::try_from(irc_message.clone()).unwrap();
+            std::hint::black_box(message);
+        }
+    }
+    let elapsed = started_at.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+
+    let parsed = PARSE_ROUNDS * irc_messages.len();
+    // cloning the IRCMessage is included in the measurement, subtract its allocations
+    let clone_allocations = {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for irc_message in &irc_messages {
+            std::hint::black_box(irc_message.clone());
+        }
+        (ALLOCATIONS.load(Ordering::Relaxed) - before) * PARSE_ROUNDS
+    };
+    println!(
+        "parsed {} messages in {:?} ({:?} per message)",
+        parsed,
+        elapsed,
+        elapsed / parsed as u32
+    );
+    println!(
+        "{:.2} allocations per message (excluding cloning the IRCMessage)",
+        (allocations - clone_allocations) as f64 / parsed as f64
+    );
+}
+
+fn measure_cloning() {
+    let message = ServerMessage::parse(CORPUS[1]).unwrap();
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started_at = Instant::now();
+    for _ in 0..CLONE_ROUNDS {
+        std::hint::black_box(message.clone());
+    }
+    let elapsed = started_at.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+
+    println!(
+        "cloned a message {} times in {:?} ({:?} per clone)",
+        CLONE_ROUNDS,
+        elapsed,
+        elapsed / CLONE_ROUNDS as u32
+    );
+    println!(
+        "{:.2} allocations per clone",
+        allocations as f64 / CLONE_ROUNDS as f64
+    );
+}
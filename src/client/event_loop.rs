@@ -1,18 +1,50 @@
-use crate::client::pool_connection::PoolConnection;
-#[cfg(feature = "metrics-collection")]
-use crate::client::pool_connection::ReportedConnectionState;
-use crate::config::ClientConfig;
+use crate::client::incoming::IncomingMessagesSender;
+use crate::client::pool_connection::{
+    ConnectionState, ConnectionStatus, PoolConnection, ReconnectBackoff,
+};
+use crate::config::{ClientConfig, MembershipMode, SendFailure};
 use crate::connection::event_loop::ConnectionLoopCommand;
 use crate::connection::{Connection, ConnectionIncomingMessage};
 use crate::error::Error;
 use crate::irc;
 use crate::login::LoginCredentials;
 use crate::message::commands::ServerMessage;
-use crate::message::{IRCMessage, JoinMessage, PartMessage};
+use crate::message::{IRCMessage, JoinMessage, MessageKind, NoticeMessage, PartMessage, RoomState};
 use crate::transport::Transport;
-use std::collections::{HashSet, VecDeque};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Weak};
-use tokio::sync::{mpsc, oneshot};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+
+/// How many of the most recent connection close reasons are kept by the client loop
+/// (see `TwitchIRCClient::recent_close_reasons()`).
+const MAX_RECENT_CLOSE_REASONS: usize = 32;
+
+/// How long after sending a message a `NOTICE` reporting a failed send is still attributed to
+/// it (see `ClientConfig::on_send_failure`).
+const SEND_FAILURE_CORRELATION_WINDOW: Duration = Duration::from_secs(10);
+
+/// Upper bound on how many message IDs are remembered to detect duplicate incoming messages
+/// (see `ClientConfig::dedup_window`). If more messages than this are received within the window,
+/// the oldest IDs are forgotten early.
+const MAX_DEDUP_MESSAGE_IDS: usize = 10_000;
+
+/// A `TwitchIRCClient::request_capabilities` call waiting for the `CAP ACK`/`CAP NAK` replies
+/// of all connections the capabilities were requested on.
+struct CapabilityRequest<T: Transport, L: LoginCredentials> {
+    /// The requested capabilities, sorted.
+    capabilities: Vec<String>,
+    /// IDs of the connections that have not replied yet.
+    pending_connections: HashSet<usize>,
+    /// `false` once any connection replied with `CAP NAK`.
+    all_acknowledged: bool,
+    return_sender: oneshot::Sender<Result<bool, Error<T, L>>>,
+}
+
+/// Messages to be sent right after each other, each with the sender to report its result to.
+type OrderedMessages<T, L> = Vec<(IRCMessage, oneshot::Sender<Result<(), Error<T, L>>>)>;
 
 #[derive(Debug)]
 pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
@@ -23,6 +55,15 @@ pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
         message: IRCMessage,
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     },
+    SendMessagesOrdered {
+        messages: OrderedMessages<T, L>,
+    },
+    SendMessageWithRetry {
+        message: IRCMessage,
+        retries_left: usize,
+        failed_connection_id: Option<usize>,
+        return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
+    },
     Join {
         channel_login: String,
     },
@@ -36,15 +77,64 @@ pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
     SetWantedChannels {
         channels: HashSet<String>,
     },
+    SendOnConnection {
+        connection_id: usize,
+        message: IRCMessage,
+        return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
+    },
     Ping {
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     },
+    ForceReconnect {
+        connection_id: usize,
+        return_sender: oneshot::Sender<bool>,
+    },
+    GetRecentCloseReasons {
+        return_sender: oneshot::Sender<Vec<(std::time::Instant, Error<T, L>)>>,
+    },
+    GetRoomState {
+        channel_login: String,
+        return_sender: oneshot::Sender<Option<RoomState>>,
+    },
+    GetChannelLoginById {
+        channel_id: String,
+        return_sender: oneshot::Sender<Option<String>>,
+    },
+    GetPoolStatus {
+        return_sender: oneshot::Sender<Vec<ConnectionStatus>>,
+    },
+    GetReconnectBackoff {
+        return_sender: oneshot::Sender<Option<ReconnectBackoff>>,
+    },
+    RequestCapabilities {
+        capabilities: Vec<String>,
+        return_sender: oneshot::Sender<Result<bool, Error<T, L>>>,
+    },
+    Pause,
+    Resume,
+    WaitForMessage {
+        predicate: MessagePredicate,
+        return_sender: oneshot::Sender<ServerMessage>,
+    },
+    SubscribeCommands {
+        kinds: Vec<MessageKind>,
+        sender: broadcast::Sender<ServerMessage>,
+    },
     IncomingMessage {
         source_connection_id: usize,
         message: Box<ConnectionIncomingMessage<T, L>>,
     },
 }
 
+/// See `TwitchIRCClient::next_matching`.
+pub(crate) struct MessagePredicate(pub Box<dyn Fn(&ServerMessage) -> bool + Send>);
+
+impl std::fmt::Debug for MessagePredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessagePredicate").finish()
+    }
+}
+
 pub(crate) struct ClientLoopWorker<T: Transport, L: LoginCredentials> {
     config: Arc<ClientConfig<L>>,
     next_connection_id: usize,
@@ -55,7 +145,63 @@ pub(crate) struct ClientLoopWorker<T: Transport, L: LoginCredentials> {
     client_loop_rx: mpsc::UnboundedReceiver<ClientLoopCommand<T, L>>,
     connections: VecDeque<PoolConnection<T, L>>,
     client_loop_tx: Weak<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
-    client_incoming_messages_tx: mpsc::UnboundedSender<ServerMessage>,
+    client_incoming_messages_tx: IncomingMessagesSender,
+    /// only present if `ClientConfig::subscription_capacity` is set
+    client_broadcast_tx: Option<broadcast::Sender<ServerMessage>>,
+    /// the causes of the most recently closed connections, oldest at the front.
+    /// Bounded to `MAX_RECENT_CLOSE_REASONS` entries.
+    recent_close_reasons: VecDeque<(Instant, Error<T, L>)>,
+    /// how many connections of this client have failed so far.
+    reconnect_count: u64,
+    /// how many connections have failed since a connection was last opened successfully,
+    /// and when the first of these failures happened. Failures caused by a `RECONNECT` or
+    /// `force_reconnect()` are not counted. See `config.max_reconnect_attempts`.
+    consecutive_failures: u32,
+    failing_since: Option<Instant>,
+    /// The delay before replacing the last failed connection, while connections keep failing.
+    /// Reset together with `consecutive_failures`. See `config.reconnect_backoff`.
+    reconnect_backoff: Option<ReconnectBackoff>,
+    /// Set once the client gave up reconnecting, to the `Error::GaveUpReconnecting` that
+    /// is returned for all messages sent afterwards.
+    gave_up: Option<Error<T, L>>,
+    /// Set while the replacement connection(s) for a failed connection are made, to the cause
+    /// of the failure. Used to call the `on_reconnect` hook (see `make_new_connection`).
+    replacing_failed_connection: Option<Error<T, L>>,
+    /// Waits for the reconnect backoff and then for the future returned by the `on_reconnect`
+    /// hook, while a failed connection is being replaced. All replacement connections wait for
+    /// it before connecting.
+    reconnect_hook_future: Option<Shared<BoxFuture<'static, ()>>>,
+    /// The merged `ROOMSTATE` of each channel we are joined to, keyed by channel login.
+    room_states: HashMap<String, RoomState>,
+    /// Chat messages sent within the last `SEND_FAILURE_CORRELATION_WINDOW`, oldest first,
+    /// as `(sent_at, channel_login, message_text)`. Only kept if `config.on_send_failure`
+    /// is set.
+    recent_sends: VecDeque<(Instant, String, String)>,
+    /// The `id` tags of the messages received within the last `config.dedup_window`, oldest
+    /// first, as `(received_at, id)`. Bounded to `MAX_DEDUP_MESSAGE_IDS` entries.
+    /// Only kept if `config.dedup_window` is set.
+    recent_message_ids: VecDeque<(Instant, String)>,
+    /// The same IDs as in `recent_message_ids`, for fast lookup.
+    recent_message_id_set: HashSet<String>,
+    /// Callers of `TwitchIRCClient::next_matching` waiting for a message, in the order they
+    /// started waiting. A waiter is removed once a message matches, or once its receiver
+    /// has been dropped (because it timed out).
+    message_waiters: Vec<(MessagePredicate, oneshot::Sender<ServerMessage>)>,
+    /// Subscribers created with `TwitchIRCClient::subscribe_commands`, each with the kinds of
+    /// messages it receives. A subscriber is removed once its receiver has been dropped.
+    command_subscribers: Vec<(Vec<MessageKind>, broadcast::Sender<ServerMessage>)>,
+    /// Capabilities requested with `TwitchIRCClient::request_capabilities`, in addition to the
+    /// ones every connection requests during init. New connections request these as well.
+    extra_capabilities: Vec<String>,
+    /// Calls to `TwitchIRCClient::request_capabilities` waiting for the server's replies.
+    capability_requests: Vec<CapabilityRequest<T, L>>,
+    /// Set between `TwitchIRCClient::pause()` and `TwitchIRCClient::resume()`.
+    paused: bool,
+    /// Messages received while paused, oldest first, delivered once resumed.
+    /// Bounded to `config.paused_messages_capacity` entries.
+    paused_messages: VecDeque<ServerMessage>,
+    /// prepended to all log messages of the client loop, see `ClientConfig::log_identifier`
+    log_prefix: String,
 }
 
 impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
@@ -63,8 +209,13 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         config: Arc<ClientConfig<L>>,
         client_loop_tx: Weak<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
         client_loop_rx: mpsc::UnboundedReceiver<ClientLoopCommand<T, L>>,
-        client_incoming_messages_tx: mpsc::UnboundedSender<ServerMessage>,
+        client_incoming_messages_tx: IncomingMessagesSender,
+        client_broadcast_tx: Option<broadcast::Sender<ServerMessage>>,
     ) {
+        let log_prefix = match &config.log_identifier {
+            Some(log_identifier) => format!("[{}] ", log_identifier),
+            None => String::new(),
+        };
         let worker = ClientLoopWorker {
             config,
             next_connection_id: 0,
@@ -73,32 +224,126 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             connections: VecDeque::new(),
             client_loop_tx,
             client_incoming_messages_tx,
+            client_broadcast_tx,
+            recent_close_reasons: VecDeque::with_capacity(MAX_RECENT_CLOSE_REASONS),
+            reconnect_count: 0,
+            consecutive_failures: 0,
+            failing_since: None,
+            reconnect_backoff: None,
+            gave_up: None,
+            replacing_failed_connection: None,
+            reconnect_hook_future: None,
+            room_states: HashMap::new(),
+            recent_sends: VecDeque::new(),
+            recent_message_ids: VecDeque::new(),
+            recent_message_id_set: HashSet::new(),
+            message_waiters: Vec::new(),
+            command_subscribers: Vec::new(),
+            extra_capabilities: Vec::new(),
+            capability_requests: Vec::new(),
+            paused: false,
+            paused_messages: VecDeque::new(),
+            log_prefix,
         };
         tokio::spawn(worker.run());
     }
 
     async fn run(mut self) {
-        log::debug!("Spawned client event loop");
+        log::debug!("{}Spawned client event loop", self.log_prefix);
+        self.ensure_warm_connections();
         while let Some(command) = self.client_loop_rx.recv().await {
             self.process_command(command);
+            // with OverflowPolicy::Block, don't process anything else until the library user
+            // has caught up
+            self.client_incoming_messages_tx.wait_for_capacity().await;
+
+            // after resume() or giving up, deliver the messages held back while paused (one by
+            // one, so the incoming messages buffer is not overfilled with OverflowPolicy::Block)
+            while !self.paused {
+                match self.paused_messages.pop_front() {
+                    Some(message) => {
+                        self.deliver_message(message);
+                        self.client_incoming_messages_tx.wait_for_capacity().await;
+                    }
+                    None => break,
+                }
+            }
+
+            if self.gave_up.is_some() {
+                self.close_incoming_messages();
+            }
         }
-        log::debug!("Client event loop ended")
+        log::debug!("{}Client event loop ended", self.log_prefix)
     }
 
     fn process_command(&mut self, command: ClientLoopCommand<T, L>) {
+        if let Some(gave_up) = &self.gave_up {
+            // no connections are made anymore after giving up, so everything that would
+            // need one is rejected (or ignored)
+            match command {
+                ClientLoopCommand::Connect { return_sender } => {
+                    return_sender.send(()).ok();
+                    return;
+                }
+                ClientLoopCommand::SendMessage { return_sender, .. }
+                | ClientLoopCommand::SendMessageWithRetry { return_sender, .. }
+                | ClientLoopCommand::SendOnConnection { return_sender, .. }
+                | ClientLoopCommand::Ping { return_sender } => {
+                    return_sender.send(Err(gave_up.clone())).ok();
+                    return;
+                }
+                ClientLoopCommand::RequestCapabilities { return_sender, .. } => {
+                    return_sender.send(Err(gave_up.clone())).ok();
+                    return;
+                }
+                ClientLoopCommand::SendMessagesOrdered { messages } => {
+                    for (_message, return_sender) in messages {
+                        return_sender.send(Err(gave_up.clone())).ok();
+                    }
+                    return;
+                }
+                // joining would make new connections. Messages from the closed connections
+                // that were still queued up are dropped.
+                ClientLoopCommand::Join { .. }
+                | ClientLoopCommand::SetWantedChannels { .. }
+                | ClientLoopCommand::Part { .. }
+                | ClientLoopCommand::IncomingMessage { .. } => return,
+                // no more messages will arrive, dropping the return_sender ends the wait
+                // (and dropping the sender closes the subscription)
+                ClientLoopCommand::WaitForMessage { .. }
+                | ClientLoopCommand::SubscribeCommands { .. } => return,
+                _ => {}
+            }
+        }
+
         match command {
             ClientLoopCommand::Connect { return_sender } => {
-                if self.connections.is_empty() {
-                    let new_connection = self.make_new_connection();
-                    self.connections.push_back(new_connection);
-                    self.update_metrics();
-                }
+                self.ensure_sending_connection();
                 return_sender.send(()).ok();
             }
             ClientLoopCommand::SendMessage {
                 message,
                 return_sender,
             } => self.send_message(message, return_sender),
+            ClientLoopCommand::SendMessagesOrdered { messages } => {
+                self.send_messages_ordered(messages)
+            }
+            ClientLoopCommand::SendMessageWithRetry {
+                message,
+                retries_left,
+                failed_connection_id,
+                return_sender,
+            } => self.send_message_with_retry(
+                message,
+                retries_left,
+                failed_connection_id,
+                return_sender,
+            ),
+            ClientLoopCommand::SendOnConnection {
+                connection_id,
+                message,
+                return_sender,
+            } => self.send_on_connection(connection_id, message, return_sender),
             ClientLoopCommand::Join { channel_login } => self.join(channel_login),
             ClientLoopCommand::SetWantedChannels { channels } => self.set_wanted_channels(channels),
             ClientLoopCommand::GetChannelStatus {
@@ -111,6 +356,74 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             }
             ClientLoopCommand::Part { channel_login } => self.part(channel_login),
             ClientLoopCommand::Ping { return_sender } => self.ping(return_sender),
+            ClientLoopCommand::ForceReconnect {
+                connection_id,
+                return_sender,
+            } => {
+                return_sender.send(self.force_reconnect(connection_id)).ok();
+            }
+            ClientLoopCommand::GetRecentCloseReasons { return_sender } => {
+                return_sender
+                    .send(
+                        self.recent_close_reasons
+                            .iter()
+                            .map(|(closed_at, cause)| (closed_at.into_std(), cause.clone()))
+                            .collect(),
+                    )
+                    .ok();
+            }
+            ClientLoopCommand::GetRoomState {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender
+                    .send(self.room_states.get(&channel_login).cloned())
+                    .ok();
+            }
+            ClientLoopCommand::GetChannelLoginById {
+                channel_id,
+                return_sender,
+            } => {
+                let channel_login = self
+                    .room_states
+                    .iter()
+                    .find(|(_, room_state)| room_state.channel_id == channel_id)
+                    .map(|(channel_login, _)| channel_login.clone());
+                return_sender.send(channel_login).ok();
+            }
+            ClientLoopCommand::GetPoolStatus { return_sender } => {
+                return_sender
+                    .send(self.connections.iter().map(|c| c.status()).collect())
+                    .ok();
+            }
+            ClientLoopCommand::GetReconnectBackoff { return_sender } => {
+                return_sender.send(self.reconnect_backoff).ok();
+            }
+            ClientLoopCommand::RequestCapabilities {
+                capabilities,
+                return_sender,
+            } => self.request_capabilities(capabilities, return_sender),
+            ClientLoopCommand::Pause => {
+                log::debug!("{}Pausing delivery of incoming messages", self.log_prefix);
+                self.paused = true;
+            }
+            ClientLoopCommand::Resume => {
+                log::debug!(
+                    "{}Resuming delivery of incoming messages, {} messages were held back",
+                    self.log_prefix,
+                    self.paused_messages.len()
+                );
+                self.paused = false;
+            }
+            ClientLoopCommand::WaitForMessage {
+                predicate,
+                return_sender,
+            } => {
+                self.message_waiters.push((predicate, return_sender));
+            }
+            ClientLoopCommand::SubscribeCommands { kinds, sender } => {
+                self.command_subscribers.push((kinds, sender));
+            }
             ClientLoopCommand::IncomingMessage {
                 source_connection_id,
                 message,
@@ -118,11 +431,68 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         }
     }
 
+    /// Whether reading and sending is split onto separate connections
+    /// (see `ClientConfig::read_credentials`).
+    fn reads_split(&self) -> bool {
+        self.config.read_credentials.is_some()
+    }
+
+    /// Makes sure there is at least one connection that is able to send messages. Only
+    /// connections logged in with `login_credentials` receive whispers, so we always
+    /// want to keep at least one of them around.
+    fn ensure_sending_connection(&mut self) {
+        if self.connections.iter().all(|c| c.read_only) {
+            let new_connection = self.make_new_connection(false);
+            self.connections.push_back(new_connection);
+            self.update_metrics();
+        }
+    }
+
+    /// Makes sure there are at least `config.warm_connections` connections that are not joined
+    /// to any channel, so the next join can use one of them right away.
+    fn ensure_warm_connections(&mut self) {
+        let read_only = self.reads_split();
+        let idle_connections = self
+            .connections
+            .iter()
+            .filter(|c| c.read_only == read_only && c.wanted_channels.is_empty())
+            .count();
+        if idle_connections >= self.config.warm_connections {
+            return;
+        }
+
+        for _ in idle_connections..self.config.warm_connections {
+            let new_connection = self.make_new_connection(read_only);
+            self.connections.push_back(new_connection);
+        }
+        self.update_metrics();
+    }
+
     #[must_use]
-    fn make_new_connection(&mut self) -> PoolConnection<T, L> {
-        let (connection_incoming_messages_rx, connection) =
-            Connection::new(Arc::clone(&self.config));
-        let (tx_kill_incoming, rx_kill_incoming) = oneshot::channel();
+    fn make_new_connection(&mut self, read_only: bool) -> PoolConnection<T, L> {
+        // if this connection replaces a failed one, wait for the reconnect backoff and call the
+        // on_reconnect hook (only once for all of the replacement connections, and only if
+        // there is a replacement at all)
+        if let (Some(cause), None) = (
+            &self.replacing_failed_connection,
+            &self.reconnect_hook_future,
+        ) {
+            let delay = self
+                .reconnect_backoff
+                .map_or(Duration::ZERO, |backoff| backoff.delay);
+            let hook_future = self.config.on_reconnect.as_ref().map(|on_reconnect| {
+                (on_reconnect.0)(Arc::new(cause.clone()), self.reconnect_count)
+            });
+            if delay > Duration::ZERO || hook_future.is_some() {
+                let before_connect = async move {
+                    tokio::time::sleep(delay).await;
+                    if let Some(hook_future) = hook_future {
+                        hook_future.await;
+                    }
+                };
+                self.reconnect_hook_future = Some(before_connect.boxed().shared());
+            }
+        }
 
         let connection_id = self.next_connection_id;
         // .0 at the end: the overflowing_add method returns a tuple (u64, bool)
@@ -130,15 +500,41 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         // take the value.
         self.next_connection_id = self.next_connection_id.overflowing_add(1).0;
 
-        log::info!("Making a new pool connection, new ID is {}", connection_id);
+        let (connection_incoming_messages_rx, connection) = Connection::new(
+            Arc::clone(&self.config),
+            connection_id,
+            read_only,
+            self.reconnect_hook_future.clone(),
+        );
+        let (tx_kill_incoming, rx_kill_incoming) = oneshot::channel();
+
+        log::info!(
+            "{}Making a new pool connection, new ID is {} (read-only: {})",
+            self.log_prefix,
+            connection_id,
+            read_only
+        );
 
         let pool_conn = PoolConnection::new(
             Arc::clone(&self.config),
             connection_id,
             connection,
+            read_only,
             tx_kill_incoming,
         );
 
+        if !self.extra_capabilities.is_empty() {
+            // queued up until the connection has logged in
+            pool_conn
+                .connection
+                .connection_loop_tx
+                .send(ConnectionLoopCommand::SendMessage(
+                    irc!["CAP", "REQ", self.extra_capabilities.join(" ")],
+                    None,
+                ))
+                .unwrap();
+        }
+
         // forward messages.
         tokio::spawn(ClientLoopWorker::run_incoming_forward_task(
             connection_incoming_messages_rx,
@@ -190,30 +586,201 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         message: IRCMessage,
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     ) {
-        let mut pool_connection = self
-            .connections
-            .iter()
-            .position(|c| c.not_busy())
-            // take what we found
-            .map(|pos| self.connections.remove(pos).unwrap())
-            // or else make a new one
-            .unwrap_or_else(|| self.make_new_connection());
+        self.send_messages_ordered(vec![(message, return_sender)]);
+    }
 
+    /// Sends all messages over the same connection. They are queued up on that connection
+    /// right after each other, so no other message can end up in between them.
+    fn send_messages_ordered(&mut self, messages: OrderedMessages<T, L>) {
+        if messages.is_empty() {
+            return;
+        }
+
+        for (message, _) in messages.iter() {
+            self.remember_send(message);
+        }
+
+        let mut pool_connection = self.take_sending_connection(None);
+
+        for (message, return_sender) in messages {
+            pool_connection.register_sent_message();
+
+            pool_connection
+                .connection
+                .connection_loop_tx
+                .send(ConnectionLoopCommand::SendMessage(
+                    message,
+                    Some(return_sender),
+                ))
+                .unwrap();
+        }
+
+        // put the connection back to the end of the queue
+        self.connections.push_back(pool_connection);
+
+        self.update_metrics();
+    }
+
+    /// Sends the message like `send_message`, but if sending it fails with an
+    /// `Error::OutgoingError`, the message is sent again (up to `retries_left` more times)
+    /// by feeding it back into the client loop. The connection the send failed on is still
+    /// part of the pool until its `StateClosed` arrives, so it is excluded when picking the
+    /// connection for the next attempt.
+    fn send_message_with_retry(
+        &mut self,
+        message: IRCMessage,
+        retries_left: usize,
+        failed_connection_id: Option<usize>,
+        return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
+    ) {
+        self.remember_send(&message);
+
+        let mut pool_connection = self.take_sending_connection(failed_connection_id);
+        let connection_id = pool_connection.id;
         pool_connection.register_sent_message();
 
+        let (attempt_tx, attempt_rx) = oneshot::channel();
         pool_connection
             .connection
             .connection_loop_tx
             .send(ConnectionLoopCommand::SendMessage(
-                message,
-                Some(return_sender),
+                message.clone(),
+                Some(attempt_tx),
             ))
             .unwrap();
 
-        // put the connection back to the end of the queue
         self.connections.push_back(pool_connection);
-
         self.update_metrics();
+
+        let client_loop_tx = self.client_loop_tx.clone();
+        let log_prefix = self.log_prefix.clone();
+        tokio::spawn(async move {
+            // Err(_): the connection was dropped without replying, dropping the return_sender
+            // reports that to the caller the same way
+            if let Ok(result) = attempt_rx.await {
+                match result {
+                    Err(Error::OutgoingError(error)) if retries_left > 0 => {
+                        log::warn!(
+                            "{}Sending over pool connection {} failed, retrying: {}",
+                            log_prefix,
+                            connection_id,
+                            error
+                        );
+                        if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                            client_loop_tx
+                                .send(ClientLoopCommand::SendMessageWithRetry {
+                                    message,
+                                    retries_left: retries_left - 1,
+                                    failed_connection_id: Some(connection_id),
+                                    return_sender,
+                                })
+                                .ok();
+                        }
+                    }
+                    result => {
+                        return_sender.send(result).ok();
+                    }
+                }
+            }
+        });
+    }
+
+    /// Removes a connection that can be used to send messages from the pool (making a new one
+    /// if necessary), skipping the connection with the `excluded_connection_id`. The caller
+    /// must put it back to the end of the queue.
+    fn take_sending_connection(
+        &mut self,
+        excluded_connection_id: Option<usize>,
+    ) -> PoolConnection<T, L> {
+        self.connections
+            .iter()
+            // read-only connections are not logged in as the sending user
+            .position(|c| !c.read_only && c.not_busy() && Some(c.id) != excluded_connection_id)
+            // take what we found
+            .map(|pos| self.connections.remove(pos).unwrap())
+            // or else make a new one
+            .unwrap_or_else(|| self.make_new_connection(false))
+    }
+
+    /// Remembers sent `PRIVMSG`s so a `NOTICE` reporting a failed send can be attributed to
+    /// them (see `ClientConfig::on_send_failure`).
+    fn remember_send(&mut self, message: &IRCMessage) {
+        if self.config.on_send_failure.is_some() && message.command == "PRIVMSG" {
+            if let [channel, message_text] = &message.params[..] {
+                if let Some(channel_login) = channel.strip_prefix('#') {
+                    self.forget_old_sends();
+                    self.recent_sends.push_back((
+                        Instant::now(),
+                        channel_login.to_owned(),
+                        message_text.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn forget_old_sends(&mut self) {
+        while let Some((sent_at, _, _)) = self.recent_sends.front() {
+            if sent_at.elapsed() <= SEND_FAILURE_CORRELATION_WINDOW {
+                break;
+            }
+            self.recent_sends.pop_front();
+        }
+    }
+
+    /// Remembers the given message ID, and returns whether a message with the same ID was
+    /// already received within the `window`.
+    fn is_duplicate_message(&mut self, window: Duration, id: &str) -> bool {
+        while let Some((received_at, _)) = self.recent_message_ids.front() {
+            if received_at.elapsed() <= window
+                && self.recent_message_ids.len() < MAX_DEDUP_MESSAGE_IDS
+            {
+                break;
+            }
+            let (_, id) = self.recent_message_ids.pop_front().unwrap();
+            self.recent_message_id_set.remove(&id);
+        }
+
+        if self.recent_message_id_set.contains(id) {
+            return true;
+        }
+        self.recent_message_ids
+            .push_back((Instant::now(), id.to_owned()));
+        self.recent_message_id_set.insert(id.to_owned());
+        false
+    }
+
+    /// Matches a `NOTICE` reporting a failed send to the oldest recently sent message in the
+    /// same channel, and reports it to the `on_send_failure` hook.
+    fn on_notice(&mut self, notice: &NoticeMessage) {
+        let hook = match self.config.on_send_failure {
+            Some(ref hook) => hook.clone(),
+            None => return,
+        };
+        let (reason, channel_login) = match (notice.send_failure_reason(), &notice.channel_login) {
+            (Some(reason), Some(channel_login)) => (reason, channel_login),
+            _ => return,
+        };
+
+        self.forget_old_sends();
+        let sent_message = self
+            .recent_sends
+            .iter()
+            .position(|(_, sent_channel_login, _)| sent_channel_login == channel_login)
+            .and_then(|pos| self.recent_sends.remove(pos));
+        match sent_message {
+            Some((_, channel_login, message_text)) => (hook.0)(SendFailure {
+                channel_login,
+                message_text,
+                reason,
+                notice: notice.clone(),
+            }),
+            None => log::debug!(
+                "{}Could not match send failure notice to a recently sent message: {}",
+                self.log_prefix,
+                notice
+            ),
+        }
     }
 
     /// Instructs the client to now start "wanting to be joined" to that channel.
@@ -230,6 +797,9 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             return;
         }
 
+        // if reads are split from sends, channels are only ever joined on read-only connections
+        let read_only = self.reads_split();
+
         let mut pool_connection = self
             .connections
             .iter()
@@ -241,12 +811,12 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             .or_else(|| {
                 self.connections
                     .iter()
-                    .position(|c| c.channels_limit_not_reached())
+                    .position(|c| c.read_only == read_only && c.channels_limit_not_reached())
             })
             // take what we found
             .map(|pos| self.connections.remove(pos).unwrap())
             // or else make a new connection
-            .unwrap_or_else(|| self.make_new_connection());
+            .unwrap_or_else(|| self.make_new_connection(read_only));
 
         // delegate join command to connection
         pool_connection
@@ -265,6 +835,9 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         self.connections.push_back(pool_connection);
         // update metrics about channel numbers
         self.update_metrics();
+
+        // replace the warm connection if this join used one
+        self.ensure_warm_connections();
     }
 
     fn set_wanted_channels(&mut self, channels: HashSet<String>) {
@@ -327,6 +900,7 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
 
         pool_connection.register_sent_message();
         pool_connection.wanted_channels.remove(&channel_login);
+        self.room_states.remove(&channel_login);
 
         // put the connection back to the end of the queue
         self.connections.push_back(pool_connection);
@@ -334,10 +908,128 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         self.update_metrics();
     }
 
+    /// Sends the message over the connection with the given ID, without considering which
+    /// channels are joined on it or how busy it is.
+    fn send_on_connection(
+        &mut self,
+        connection_id: usize,
+        message: IRCMessage,
+        return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
+    ) {
+        match self.connections.iter_mut().find(|c| c.id == connection_id) {
+            Some(pool_connection) => {
+                pool_connection.register_sent_message();
+                pool_connection
+                    .connection
+                    .connection_loop_tx
+                    .send(ConnectionLoopCommand::SendMessage(
+                        message,
+                        Some(return_sender),
+                    ))
+                    .unwrap();
+            }
+            None => {
+                return_sender
+                    .send(Err(Error::ConnectionNotFound(connection_id)))
+                    .ok();
+            }
+        }
+    }
+
+    /// Delivers the message to the library user, or holds it back while paused.
+    fn forward_message(&mut self, message: ServerMessage) {
+        if !self.paused {
+            self.deliver_message(message);
+            return;
+        }
+
+        self.paused_messages.push_back(message);
+        if self.paused_messages.len() > self.config.paused_messages_capacity {
+            self.paused_messages.pop_front();
+            log::trace!(
+                "{}Dropped an incoming message held back while paused, the buffer is full",
+                self.log_prefix
+            );
+            #[cfg(feature = "metrics-collection")]
+            if let Some(ref metrics_identifier) = self.config.metrics_identifier {
+                metrics::counter!("twitch_irc_incoming_messages_dropped", 1, "client" => metrics_identifier.clone());
+            }
+        }
+    }
+
+    /// Delivers the message to the library user.
+    fn deliver_message(&mut self, message: ServerMessage) {
+        if !self.message_waiters.is_empty() {
+            self.message_waiters = std::mem::take(&mut self.message_waiters)
+                .into_iter()
+                .filter_map(|(predicate, return_sender)| {
+                    if return_sender.is_closed() {
+                        // timed out
+                        None
+                    } else if (predicate.0)(&message) {
+                        return_sender.send(message.clone()).ok();
+                        None
+                    } else {
+                        Some((predicate, return_sender))
+                    }
+                })
+                .collect();
+        }
+        if !self.command_subscribers.is_empty() {
+            let kind = message.kind();
+            // the message is only cloned for the subscribers that want it. Sending fails once
+            // the receiver has been dropped.
+            self.command_subscribers.retain(|(kinds, sender)| {
+                if kinds.contains(&kind) {
+                    sender.send(message.clone()).is_ok()
+                } else {
+                    sender.receiver_count() > 0
+                }
+            });
+        }
+        if let Some(client_broadcast_tx) = &self.client_broadcast_tx {
+            // ignore if there are currently no subscribers
+            client_broadcast_tx.send(message.clone()).ok();
+        }
+        let dropped = self.client_incoming_messages_tx.send(message);
+        if dropped {
+            log::trace!(
+                "{}Dropped an incoming message, the buffer of incoming messages is full",
+                self.log_prefix
+            );
+            #[cfg(feature = "metrics-collection")]
+            if let Some(ref metrics_identifier) = self.config.metrics_identifier {
+                metrics::counter!("twitch_irc_incoming_messages_dropped", 1, "client" => metrics_identifier.clone());
+            }
+        }
+    }
+
     fn ping(&mut self, return_sender: oneshot::Sender<Result<(), Error<T, L>>>) {
         self.send_message(irc!["PING", "tmi.twitch.tv"], return_sender)
     }
 
+    /// Instructs the connection with the given ID to close itself. The connection then reports
+    /// back `StateClosed`, upon which it is replaced the same way as a failed connection.
+    /// Returns `false` if no connection with that ID exists.
+    fn force_reconnect(&mut self, connection_id: usize) -> bool {
+        match self.connections.iter().find(|c| c.id == connection_id) {
+            Some(pool_connection) => {
+                log::info!(
+                    "{}Forcing pool connection {} to reconnect",
+                    self.log_prefix,
+                    connection_id
+                );
+                pool_connection
+                    .connection
+                    .connection_loop_tx
+                    .send(ConnectionLoopCommand::ForceClose())
+                    .unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
     fn on_incoming_message(
         &mut self,
         source_connection_id: usize,
@@ -345,6 +1037,37 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
     ) {
         match *message {
             ConnectionIncomingMessage::IncomingMessage(message) => {
+                // the same message can arrive on two connections, e.g. while a channel is
+                // still joined on a connection in its reconnect grace period
+                if let Some(window) = self.config.dedup_window {
+                    // messages without tags have no ID and are never considered duplicates
+                    if let Some(id) = message.get_tag("id").filter(|id| !id.is_empty()) {
+                        if self.is_duplicate_message(window, id) {
+                            log::debug!(
+                                "{}Ignoring duplicate message with ID {} from connection {}",
+                                self.log_prefix,
+                                id,
+                                source_connection_id
+                            );
+                            return; // ignore message, don't forward.
+                        }
+                    }
+                }
+
+                if self.config.drop_source_only_shared_messages {
+                    if let ServerMessage::Privmsg(privmsg) = &message {
+                        if privmsg.source_only && privmsg.source_channel.is_some() {
+                            log::trace!(
+                                "{}Ignoring source-only message {} shared into #{}",
+                                self.log_prefix,
+                                privmsg.message_id,
+                                privmsg.channel_login
+                            );
+                            return; // ignore message, don't forward.
+                        }
+                    }
+                }
+
                 let is_whisper = matches!(message, ServerMessage::Whisper(_));
                 if is_whisper {
                     match self.current_whisper_connection_id {
@@ -353,71 +1076,152 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                             // so we ignore this message if it doesn't come from that connection
                             if current_whisper_connection_id != source_connection_id {
                                 log::debug!(
-                                    "Ignoring whisper from connection {} (not whisper connection)",
+                                    "{}Ignoring whisper from connection {} (not whisper connection)", self.log_prefix,
                                     source_connection_id
                                 );
                                 return; // ignore message, don't forward.
                             }
-                            log::debug!("Received whisper from connection {}, will be forwarded as it is the current whisper connection", source_connection_id)
+                            log::debug!("{}Received whisper from connection {}, will be forwarded as it is the current whisper connection", self.log_prefix, source_connection_id)
+                        }
+                        None if !self
+                            .connections
+                            .iter()
+                            .any(|c| c.id == source_connection_id) =>
+                        {
+                            // connection that is in its reconnect grace period, it is already
+                            // removed from the pool and cannot become the whisper connection
+                            log::debug!("{}Received whisper from connection {} in its reconnect grace period, message was forwarded", self.log_prefix, source_connection_id);
                         }
                         None => {
                             // no connection chosen to be whisper connection yet
                             // since we just got a whisper, we will assign this connection to
                             // now be the responsible whisper connection. (and the message
                             // will be forwarded)
-                            log::debug!("Received whisper and had no whisper connection selected. Selecting pool connection {}. Message was forwarded", source_connection_id);
+                            log::debug!("{}Received whisper and had no whisper connection selected. Selecting pool connection {}. Message was forwarded", self.log_prefix, source_connection_id);
                             self.current_whisper_connection_id = Some(source_connection_id)
                         }
                     }
                 }
 
-                match &message {
-                    ServerMessage::Join(JoinMessage { channel_login, .. }) => {
+                // the connection is not found if it is in its reconnect grace period,
+                // in that case its channels are no longer tracked
+                let pool_connection = self
+                    .connections
+                    .iter_mut()
+                    .find(|c| c.id == source_connection_id);
+
+                // with the twitch.tv/membership capability, we also receive the JOINs and PARTs
+                // of other users, which must not be mistaken for our own
+                let membership_user_login = match &message {
+                    ServerMessage::Join(JoinMessage { user_login, .. })
+                    | ServerMessage::Part(PartMessage { user_login, .. }) => Some(user_login),
+                    _ => None,
+                };
+                let own_login = pool_connection.as_ref().and_then(|c| c.user_login.as_ref());
+                let is_other_users_membership = match (membership_user_login, own_login) {
+                    (Some(user_login), Some(own_login)) => user_login != own_login,
+                    // a connection in its reconnect grace period no longer joins or parts
+                    // anything, so these can't be our own
+                    (Some(_), None) => pool_connection.is_none(),
+                    (None, _) => false,
+                };
+                if is_other_users_membership {
+                    // not relevant for the channel bookkeeping below
+                    if self.config.membership == MembershipMode::All
+                        || self
+                            .extra_capabilities
+                            .iter()
+                            .any(|c| c == "twitch.tv/membership")
+                    {
+                        self.forward_message(message);
+                    }
+                    return;
+                }
+
+                match (&message, pool_connection) {
+                    (ServerMessage::Join(JoinMessage { channel_login, .. }), Some(c)) => {
                         // we successfully joined a channel
-                        let c = self
-                            .connections
-                            .iter_mut()
-                            .find(|c| c.id == source_connection_id)
-                            .unwrap();
                         c.server_channels.insert(channel_login.clone());
 
                         // update metrics about channel numbers
                         self.update_metrics();
                     }
-                    ServerMessage::Part(PartMessage { channel_login, .. }) => {
+                    (ServerMessage::Part(PartMessage { channel_login, .. }), Some(c)) => {
                         // we successfully parted a channel
-                        let c = self
-                            .connections
-                            .iter_mut()
-                            .find(|c| c.id == source_connection_id)
-                            .unwrap();
                         c.server_channels.remove(channel_login);
+                        self.room_states.remove(channel_login);
 
                         // update metrics about channel numbers
                         self.update_metrics();
                     }
+                    (ServerMessage::Welcome(welcome), Some(c)) => {
+                        // the server tells us the login name it actually assigned, which
+                        // replaces the one we sent with NICK
+                        c.user_login = Some(welcome.user_login.clone());
+                    }
                     _ => {}
                 }
 
-                self.client_incoming_messages_tx.send(message).ok(); // ignore if the library user is not using the incoming messages
+                if let ServerMessage::Generic(_) = &message {
+                    if message.source().command == "CAP" {
+                        self.on_capability_reply(source_connection_id, message.source());
+                    }
+                }
+
+                if let ServerMessage::Notice(notice) = &message {
+                    self.on_notice(notice);
+                    if let Some(room_state) = notice
+                        .channel_login
+                        .as_ref()
+                        .and_then(|channel_login| self.room_states.get_mut(channel_login))
+                    {
+                        room_state.update_from_notice(notice);
+                    }
+                }
+
+                if let ServerMessage::RoomState(room_state_message) = &message {
+                    match self.room_states.get_mut(&room_state_message.channel_login) {
+                        Some(room_state) => room_state.update(room_state_message),
+                        None => {
+                            self.room_states.insert(
+                                room_state_message.channel_login.clone(),
+                                RoomState::from(room_state_message),
+                            );
+                        }
+                    }
+                }
+
+                self.forward_message(message);
             }
-            #[cfg(feature = "metrics-collection")]
-            ConnectionIncomingMessage::StateOpen => {
-                let c = self
+            ConnectionIncomingMessage::StateOpen { user_login } => {
+                if let Some(c) = self
                     .connections
                     .iter_mut()
                     .find(|c| c.id == source_connection_id)
-                    .unwrap();
-                c.reported_state = ReportedConnectionState::Open;
-                self.update_metrics();
+                {
+                    c.reported_state = ConnectionState::Open;
+                    c.user_login = Some(user_login);
+                    self.update_metrics();
+                }
+                self.consecutive_failures = 0;
+                self.failing_since = None;
+                self.reconnect_backoff = None;
             }
             ConnectionIncomingMessage::StateClosed { cause } => {
                 log::error!(
-                    "Pool connection {} has failed due to error (removing it): {}",
+                    "{}Pool connection {} has failed due to error (removing it): {}",
+                    self.log_prefix,
                     source_connection_id,
                     cause
                 );
 
+                // remember why this connection closed, forgetting the oldest entry if needed
+                if self.recent_close_reasons.len() >= MAX_RECENT_CLOSE_REASONS {
+                    self.recent_close_reasons.pop_front();
+                }
+                self.recent_close_reasons
+                    .push_back((Instant::now(), cause.clone()));
+
                 // remove it from the list of connections.
                 // unwrap(): asserts that this is the first and only time we get an Err from
                 // that connection
@@ -428,7 +1232,18 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                     .and_then(|pos| self.connections.remove(pos))
                     .unwrap();
 
+                // capability requests can't complete on this connection anymore (its
+                // replacement requests the capabilities again, see make_new_connection)
+                for request in std::mem::take(&mut self.capability_requests) {
+                    if request.pending_connections.contains(&source_connection_id) {
+                        request.return_sender.send(Err(cause.clone())).ok();
+                    } else {
+                        self.capability_requests.push(request);
+                    }
+                }
+
                 // count up reconnects counter
+                self.reconnect_count += 1;
                 #[cfg(feature = "metrics-collection")]
                 if let Some(ref metrics_identifier) = self.config.metrics_identifier {
                     metrics::counter!("twitch_irc_reconnects", 1, "client" => metrics_identifier.clone());
@@ -436,9 +1251,49 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 // also update twitch_irc_channels and twitch_irc_connections gauges
                 self.update_metrics();
 
+                if !matches!(
+                    cause,
+                    Error::ReconnectCmd | Error::ForcedReconnect | Error::IdleTimeout
+                ) {
+                    self.consecutive_failures += 1;
+                    let failing_since = *self.failing_since.get_or_insert_with(Instant::now);
+                    let too_many_attempts = matches!(
+                        self.config.max_reconnect_attempts,
+                        Some(max_attempts) if self.consecutive_failures > max_attempts
+                    );
+                    let failing_too_long = matches!(
+                        self.config.max_reconnect_duration,
+                        Some(max_duration) if failing_since.elapsed() >= max_duration
+                    );
+                    if too_many_attempts || failing_too_long {
+                        self.give_up(cause);
+                        return;
+                    }
+
+                    let delay = self.backoff_delay();
+                    if delay > Duration::ZERO {
+                        log::info!(
+                            "{}{} connections failed in a row, waiting {:?} before reconnecting",
+                            self.log_prefix,
+                            self.consecutive_failures,
+                            delay
+                        );
+                    }
+                    self.reconnect_backoff = Some(ReconnectBackoff {
+                        consecutive_failures: self.consecutive_failures,
+                        delay,
+                        next_attempt_at: (Instant::now() + delay).into_std(),
+                    });
+                }
+
+                let reconnect_requested = matches!(cause, Error::ReconnectCmd);
+
+                // the connections made from here on replace the failed connection
+                self.replacing_failed_connection = Some(cause);
+
                 // rejoin channels
                 log::debug!(
-                    "Pool connection {} previously was joined to {} channels ({:?}), rejoining them",
+                    "{}Pool connection {} previously was joined to {} channels ({:?}), rejoining them", self.log_prefix,
                     source_connection_id,
                     pool_connection.wanted_channels.len(),
                     pool_connection.wanted_channels
@@ -450,22 +1305,157 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 // remove it from role of "current whisper connection" if it was whisper conn before
                 if self.current_whisper_connection_id == Some(source_connection_id) {
                     log::debug!(
-                        "Connection {} was whisper connection, removing it",
+                        "{}Connection {} was whisper connection, removing it",
+                        self.log_prefix,
                         source_connection_id
                     );
                     self.current_whisper_connection_id = None;
                 }
 
                 // make sure we stay connected in order to receive whispers
-                if self.connections.is_empty() {
-                    let new_connection = self.make_new_connection();
-                    self.connections.push_back(new_connection);
-                    self.update_metrics();
+                self.ensure_sending_connection();
+                self.ensure_warm_connections();
+
+                self.replacing_failed_connection = None;
+                self.reconnect_hook_future = None;
+
+                // after a RECONNECT, the connection keeps delivering messages until
+                // it is dropped at the end of the grace period
+                if reconnect_requested {
+                    let grace_period = self.config.reconnect_grace_period;
+                    if !grace_period.is_zero() {
+                        tokio::spawn(async move {
+                            tokio::time::sleep(grace_period).await;
+                            drop(pool_connection);
+                        });
+                    }
                 }
             }
         }
     }
 
+    /// How long to wait before replacing a failed connection, based on how many connections
+    /// have failed in a row (see `ClientConfig::reconnect_backoff`).
+    fn backoff_delay(&self) -> Duration {
+        if self.consecutive_failures <= 1 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.saturating_pow(self.consecutive_failures - 2);
+        self.config
+            .reconnect_backoff
+            .checked_mul(factor)
+            .map_or(self.config.max_reconnect_backoff, |delay| {
+                delay.min(self.config.max_reconnect_backoff)
+            })
+    }
+
+    /// Sends a `CAP REQ` for the given capabilities over every connection in the pool, see
+    /// `TwitchIRCClient::request_capabilities`.
+    fn request_capabilities(
+        &mut self,
+        capabilities: Vec<String>,
+        return_sender: oneshot::Sender<Result<bool, Error<T, L>>>,
+    ) {
+        if capabilities.is_empty() {
+            return_sender.send(Ok(true)).ok();
+            return;
+        }
+
+        // done before remembering the capabilities, so a new connection made here does not
+        // request them twice
+        self.ensure_sending_connection();
+        for capability in capabilities.iter() {
+            if !self.extra_capabilities.contains(capability) {
+                self.extra_capabilities.push(capability.clone());
+            }
+        }
+
+        let message = irc!["CAP", "REQ", capabilities.join(" ")];
+        for pool_connection in self.connections.iter() {
+            pool_connection
+                .connection
+                .connection_loop_tx
+                .send(ConnectionLoopCommand::SendMessage(message.clone(), None))
+                .unwrap();
+        }
+
+        let mut capabilities = capabilities;
+        capabilities.sort();
+        self.capability_requests.push(CapabilityRequest {
+            capabilities,
+            pending_connections: self.connections.iter().map(|c| c.id).collect(),
+            all_acknowledged: true,
+            return_sender,
+        });
+    }
+
+    /// Handles a `CAP ACK` or `CAP NAK` reply, completing the `request_capabilities()` call that
+    /// requested the same capabilities once all of its connections have replied.
+    fn on_capability_reply(&mut self, source_connection_id: usize, message: &IRCMessage) {
+        let (acknowledged, capabilities) = match &message.params[..] {
+            [_, subcommand, capabilities] if subcommand == "ACK" => (true, capabilities),
+            [_, subcommand, capabilities] if subcommand == "NAK" => (false, capabilities),
+            _ => return,
+        };
+        let mut capabilities = capabilities.split_whitespace().collect::<Vec<_>>();
+        capabilities.sort_unstable();
+
+        if !acknowledged {
+            // new connections request all extra capabilities at once, and a single unsupported
+            // capability would make the server reject all of them
+            self.extra_capabilities
+                .retain(|c| !capabilities.contains(&c.as_str()));
+        }
+
+        let position = self.capability_requests.iter().position(|request| {
+            request.pending_connections.contains(&source_connection_id)
+                && request.capabilities.iter().eq(capabilities.iter())
+        });
+        if let Some(position) = position {
+            let request = &mut self.capability_requests[position];
+            request.pending_connections.remove(&source_connection_id);
+            request.all_acknowledged &= acknowledged;
+            if request.pending_connections.is_empty() {
+                let request = self.capability_requests.remove(position);
+                request
+                    .return_sender
+                    .send(Ok(request.all_acknowledged))
+                    .ok();
+            }
+        }
+    }
+
+    /// Stops replacing failed connections, see `ClientConfig::max_reconnect_attempts`.
+    fn give_up(&mut self, cause: Error<T, L>) {
+        log::error!(
+            "{}Giving up reconnecting after {} failed connections in a row, closing all connections", self.log_prefix,
+            self.consecutive_failures
+        );
+        let gave_up = Error::GaveUpReconnecting(Box::new(cause));
+        for request in self.capability_requests.drain(..) {
+            request.return_sender.send(Err(gave_up.clone())).ok();
+        }
+        self.gave_up = Some(gave_up);
+        self.reconnect_backoff = None;
+
+        // dropping the connections closes them
+        self.connections.clear();
+        self.current_whisper_connection_id = None;
+        self.room_states.clear();
+        self.update_metrics();
+
+        // no more messages will arrive, so the ones held back while paused are delivered
+        // (by run(), like after resume()) before the channel is closed
+        self.paused = false;
+    }
+
+    /// Lets the library user know that no more messages will arrive, after giving up.
+    fn close_incoming_messages(&mut self) {
+        self.client_incoming_messages_tx.close();
+        self.client_broadcast_tx = None;
+        self.command_subscribers.clear();
+    }
+
     #[cfg(feature = "metrics-collection")]
     fn update_metrics(&mut self) {
         if let Some(ref metrics_identifier) = self.config.metrics_identifier {
@@ -473,8 +1463,8 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 .connections
                 .iter()
                 .map(|c| match &c.reported_state {
-                    ReportedConnectionState::Initializing => (1i64, 0i64),
-                    ReportedConnectionState::Open => (0i64, 1i64),
+                    ConnectionState::Initializing => (1i64, 0i64),
+                    ConnectionState::Open => (0i64, 1i64),
                 })
                 // sum up all the tuples (like vectors)
                 .fold((0i64, 0i64), |(a, b), (c, d)| (a + c, b + d));
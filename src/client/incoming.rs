@@ -0,0 +1,327 @@
+use crate::config::OverflowPolicy;
+use crate::message::ServerMessage;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{mpsc, Notify};
+
+/// Creates the channel the client loop uses to deliver incoming messages to the library user.
+/// If `capacity` is `None`, the channel is unbounded.
+pub(crate) fn incoming_channel(
+    capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+) -> (IncomingMessagesSender, IncomingMessages) {
+    match capacity {
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (
+                IncomingMessagesSender {
+                    inner: Sender::Unbounded(tx),
+                },
+                IncomingMessages {
+                    inner: Receiver::Unbounded(rx),
+                },
+            )
+        }
+        Some(capacity) => {
+            assert!(
+                capacity > 0,
+                "incoming_messages_capacity must be at least 1"
+            );
+            let queue = Arc::new(BoundedQueue {
+                state: Mutex::new(QueueState {
+                    messages: VecDeque::with_capacity(capacity),
+                    sender_dropped: false,
+                    receiver_dropped: false,
+                }),
+                message_available: Notify::new(),
+                space_available: Notify::new(),
+                capacity,
+                overflow_policy,
+            });
+            (
+                IncomingMessagesSender {
+                    inner: Sender::Bounded(Arc::clone(&queue)),
+                },
+                IncomingMessages {
+                    inner: Receiver::Bounded(queue),
+                },
+            )
+        }
+    }
+}
+
+struct BoundedQueue {
+    state: Mutex<QueueState>,
+    /// notified by the sender after each message
+    message_available: Notify,
+    /// notified by the receiver after taking messages out of the queue
+    space_available: Notify,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+}
+
+struct QueueState {
+    messages: VecDeque<ServerMessage>,
+    sender_dropped: bool,
+    receiver_dropped: bool,
+}
+
+/// Receives the messages from a `TwitchIRCClient`, see `TwitchIRCClient::new`.
+///
+/// By default, messages are buffered without limit until they are received. If
+/// `ClientConfig::incoming_messages_capacity` is set, at most that many messages are buffered,
+/// and `ClientConfig::incoming_overflow_policy` decides what happens once the buffer is full.
+pub struct IncomingMessages {
+    inner: Receiver,
+}
+
+enum Receiver {
+    Unbounded(mpsc::UnboundedReceiver<ServerMessage>),
+    Bounded(Arc<BoundedQueue>),
+}
+
+impl IncomingMessages {
+    /// Receives the next message, waiting until one is available.
+    ///
+    /// Returns `None` once the client has been closed (all `TwitchIRCClient` handles were dropped,
+    /// or the client gave up reconnecting, see `ClientConfig::max_reconnect_attempts`)
+    /// and all remaining messages have been received.
+    pub async fn recv(&mut self) -> Option<ServerMessage> {
+        match &mut self.inner {
+            Receiver::Unbounded(rx) => rx.recv().await,
+            Receiver::Bounded(queue) => loop {
+                {
+                    let mut state = queue.state.lock().unwrap();
+                    if let Some(message) = state.messages.pop_front() {
+                        queue.space_available.notify_one();
+                        return Some(message);
+                    }
+                    if state.sender_dropped {
+                        return None;
+                    }
+                }
+                queue.message_available.notified().await;
+            },
+        }
+    }
+
+    /// Receives all currently buffered messages, up to `limit` messages, and appends them
+    /// to `buffer`. If no message is buffered, this waits until at least one is available.
+    ///
+    /// Returns the number of messages received. Returns `0` if `limit` is `0`, or once the client
+    /// has been closed and all remaining messages have been received.
+    pub async fn recv_many(&mut self, buffer: &mut Vec<ServerMessage>, limit: usize) -> usize {
+        match &mut self.inner {
+            Receiver::Unbounded(rx) => rx.recv_many(buffer, limit).await,
+            Receiver::Bounded(queue) => loop {
+                if limit == 0 {
+                    return 0;
+                }
+                {
+                    let mut state = queue.state.lock().unwrap();
+                    let count = limit.min(state.messages.len());
+                    if count > 0 {
+                        buffer.extend(state.messages.drain(..count));
+                        queue.space_available.notify_one();
+                        return count;
+                    }
+                    if state.sender_dropped {
+                        return 0;
+                    }
+                }
+                queue.message_available.notified().await;
+            },
+        }
+    }
+
+    /// Receives the next message if one is available, without waiting.
+    pub fn try_recv(&mut self) -> Result<ServerMessage, TryRecvError> {
+        match &mut self.inner {
+            Receiver::Unbounded(rx) => rx.try_recv(),
+            Receiver::Bounded(queue) => {
+                let mut state = queue.state.lock().unwrap();
+                match state.messages.pop_front() {
+                    Some(message) => {
+                        queue.space_available.notify_one();
+                        Ok(message)
+                    }
+                    None if state.sender_dropped => Err(TryRecvError::Disconnected),
+                    None => Err(TryRecvError::Empty),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for IncomingMessages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncomingMessages").finish()
+    }
+}
+
+impl Drop for IncomingMessages {
+    fn drop(&mut self) {
+        if let Receiver::Bounded(queue) = &self.inner {
+            let mut state = queue.state.lock().unwrap();
+            state.receiver_dropped = true;
+            state.messages.clear();
+            // the client loop might be waiting for space with OverflowPolicy::Block
+            queue.space_available.notify_one();
+        }
+    }
+}
+
+pub(crate) struct IncomingMessagesSender {
+    inner: Sender,
+}
+
+enum Sender {
+    Unbounded(mpsc::UnboundedSender<ServerMessage>),
+    Bounded(Arc<BoundedQueue>),
+    /// after `close()`
+    Closed,
+}
+
+impl IncomingMessagesSender {
+    /// Delivers the message to the library user. Returns `true` if a message had to be
+    /// dropped because the buffer was full.
+    ///
+    /// With `OverflowPolicy::Block`, the message is always added to the buffer. The caller
+    /// has to use `wait_for_capacity` before sending the next message instead.
+    pub fn send(&self, message: ServerMessage) -> bool {
+        match &self.inner {
+            Sender::Unbounded(tx) => {
+                tx.send(message).ok(); // ignore if the library user is not using the incoming messages
+                false
+            }
+            Sender::Closed => false,
+            Sender::Bounded(queue) => {
+                let mut state = queue.state.lock().unwrap();
+                if state.receiver_dropped {
+                    return false;
+                }
+                let mut dropped = false;
+                if state.messages.len() >= queue.capacity {
+                    match queue.overflow_policy {
+                        OverflowPolicy::DropOldest => {
+                            state.messages.pop_front();
+                            dropped = true;
+                        }
+                        OverflowPolicy::DropNewest => return true,
+                        OverflowPolicy::Block => {}
+                    }
+                }
+                state.messages.push_back(message);
+                queue.message_available.notify_one();
+                dropped
+            }
+        }
+    }
+
+    /// Closes the channel before this sender is dropped. The receiver returns `None` once
+    /// all remaining messages have been received, and messages sent afterwards are discarded.
+    pub fn close(&mut self) {
+        // the replaced sender is dropped, which closes the channel
+        *self = IncomingMessagesSender {
+            inner: Sender::Closed,
+        };
+    }
+
+    /// With `OverflowPolicy::Block`, waits until the buffer is no longer full (or the receiver
+    /// was dropped). Returns immediately with any other policy.
+    pub async fn wait_for_capacity(&self) {
+        if let Sender::Bounded(queue) = &self.inner {
+            if queue.overflow_policy != OverflowPolicy::Block {
+                return;
+            }
+            loop {
+                {
+                    let state = queue.state.lock().unwrap();
+                    if state.messages.len() < queue.capacity || state.receiver_dropped {
+                        return;
+                    }
+                }
+                queue.space_available.notified().await;
+            }
+        }
+    }
+}
+
+impl Drop for IncomingMessagesSender {
+    fn drop(&mut self) {
+        if let Sender::Bounded(queue) = &self.inner {
+            queue.state.lock().unwrap().sender_dropped = true;
+            queue.message_available.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::incoming_channel;
+    use crate::config::OverflowPolicy;
+    use crate::message::{IRCMessage, ServerMessage};
+    use futures_util::FutureExt;
+    use std::convert::TryFrom;
+    use tokio::sync::mpsc::error::TryRecvError;
+
+    fn ping(token: &str) -> ServerMessage {
+        ServerMessage::try_from(IRCMessage::new_simple(
+            "PING".to_owned(),
+            vec![token.to_owned()],
+        ))
+        .unwrap()
+    }
+
+    fn token(message: ServerMessage) -> String {
+        message.source().params[0].clone()
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let (tx, mut rx) = incoming_channel(Some(2), OverflowPolicy::DropOldest);
+        assert!(!tx.send(ping("1")));
+        assert!(!tx.send(ping("2")));
+        assert!(tx.send(ping("3")));
+        assert!(tx.send(ping("4")));
+        drop(tx);
+
+        let mut buffer = vec![];
+        assert_eq!(rx.recv_many(&mut buffer, 10).await, 2);
+        let tokens: Vec<String> = buffer.into_iter().map(token).collect();
+        assert_eq!(tokens, vec!["3", "4"]);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let (tx, mut rx) = incoming_channel(Some(2), OverflowPolicy::DropNewest);
+        assert!(!tx.send(ping("1")));
+        assert!(!tx.send(ping("2")));
+        assert!(tx.send(ping("3")));
+
+        assert_eq!(token(rx.recv().await.unwrap()), "1");
+        assert_eq!(token(rx.recv().await.unwrap()), "2");
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+        drop(tx);
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Disconnected)));
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let (tx, mut rx) = incoming_channel(Some(1), OverflowPolicy::Block);
+        tx.wait_for_capacity().await;
+        assert!(!tx.send(ping("1")));
+        // the buffer is full until the message is received
+        assert!(tx.wait_for_capacity().now_or_never().is_none());
+
+        assert_eq!(token(rx.recv().await.unwrap()), "1");
+        tx.wait_for_capacity().await;
+
+        // the sender no longer waits once the receiver is gone
+        assert!(!tx.send(ping("2")));
+        drop(rx);
+        tx.wait_for_capacity().await;
+    }
+}
@@ -1,19 +1,23 @@
 mod event_loop;
+mod incoming;
 mod pool_connection;
 
-use crate::client::event_loop::{ClientLoopCommand, ClientLoopWorker};
+pub use incoming::IncomingMessages;
+pub use pool_connection::{ConnectionState, ConnectionStatus, ReconnectBackoff};
+
+use crate::client::event_loop::{ClientLoopCommand, ClientLoopWorker, MessagePredicate};
 use crate::config::ClientConfig;
 use crate::error::Error;
 use crate::irc;
 use crate::login::LoginCredentials;
-use crate::message::commands::ServerMessage;
-use crate::message::IRCMessage;
-use crate::message::{IRCTags, PrivmsgMessage};
+use crate::message::{ChannelLogin, ChatCommand, IRCTags, PrivmsgMessage, ReplyContext, RoomState};
+use crate::message::{IRCMessage, MessageKind, ServerMessage};
 use crate::transport::Transport;
 use std::collections::HashSet;
+use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot};
 
 /// A send-only handle to control the Twitch IRC Client.
 #[derive(Debug)]
@@ -28,6 +32,12 @@ pub struct TwitchIRCClient<T: Transport, L: LoginCredentials> {
     // it always only holds a Weak<> and has to check whether the weak reference is still
     // valid before sending itself messages.
     client_loop_tx: Arc<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
+    // only present if `ClientConfig::subscription_capacity` is set. New subscribers are
+    // created from this sender, the client loop holds another handle to send the messages.
+    client_broadcast_tx: Option<broadcast::Sender<ServerMessage>>,
+    // copy of `ClientConfig::subscription_capacity`, used for the buffers of
+    // `subscribe_commands()` subscribers.
+    subscription_capacity: Option<usize>,
 }
 
 // we have to implement Debug and Clone manually, the derive macro places
@@ -36,6 +46,8 @@ impl<T: Transport, L: LoginCredentials> Clone for TwitchIRCClient<T, L> {
     fn clone(&self) -> Self {
         TwitchIRCClient {
             client_loop_tx: self.client_loop_tx.clone(),
+            client_broadcast_tx: self.client_broadcast_tx.clone(),
+            subscription_capacity: self.subscription_capacity,
         }
     }
 }
@@ -45,16 +57,52 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// Note this method is not side-effect-free - a background task will be spawned
     /// as a result of calling this function.
-    pub fn new(
-        config: ClientConfig<L>,
-    ) -> (
-        mpsc::UnboundedReceiver<ServerMessage>,
-        TwitchIRCClient<T, L>,
-    ) {
+    ///
+    /// All messages received by the client are delivered through the returned receiver.
+    /// By default, messages are buffered without limit until you receive them, so make sure
+    /// to keep consuming them (see `ClientConfig::incoming_messages_capacity` to limit
+    /// the buffer instead). Messages are delivered in the order the client processed them.
+    /// Messages from the same connection are always delivered in the order they were received,
+    /// but there is no ordering guarantee between different connections (see
+    /// `ServerMessage::sort_by_server_timestamp` if you need one).
+    ///
+    /// Consumers handling high message rates can use
+    /// [`recv_many`](IncomingMessages::recv_many) to take all currently
+    /// buffered messages (up to a limit) in one call, instead of one `recv()` per message:
+    ///
+    /// ```no_run
+    /// use twitch_irc::login::StaticLoginCredentials;
+    /// use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let (mut incoming_messages, client) =
+    ///     TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(ClientConfig::default());
+    /// client.join("sodapoppin".to_owned());
+    ///
+    /// let mut buffer = Vec::with_capacity(1000);
+    /// // waits for at least one message, then takes up to 1000 messages at once.
+    /// // returns 0 once the client has been dropped and all messages were received.
+    /// while incoming_messages.recv_many(&mut buffer, 1000).await > 0 {
+    ///     for message in buffer.drain(..) {
+    ///         println!("{}", message);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn new(config: ClientConfig<L>) -> (IncomingMessages, TwitchIRCClient<T, L>) {
         let config = Arc::new(config);
         let (client_loop_tx, client_loop_rx) = mpsc::unbounded_channel();
         let client_loop_tx = Arc::new(client_loop_tx);
-        let (client_incoming_messages_tx, client_incoming_messages_rx) = mpsc::unbounded_channel();
+        let (client_incoming_messages_tx, client_incoming_messages_rx) = incoming::incoming_channel(
+            config.incoming_messages_capacity,
+            config.incoming_overflow_policy,
+        );
+        let subscription_capacity = config.subscription_capacity;
+        let client_broadcast_tx = subscription_capacity.map(|capacity| {
+            assert!(capacity > 0, "subscription_capacity must be at least 1");
+            broadcast::channel(capacity).0
+        });
 
         ClientLoopWorker::spawn(
             config,
@@ -62,16 +110,132 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
             Arc::downgrade(&client_loop_tx),
             client_loop_rx,
             client_incoming_messages_tx,
+            client_broadcast_tx.clone(),
         );
 
         (
             client_incoming_messages_rx,
-            TwitchIRCClient { client_loop_tx },
+            TwitchIRCClient {
+                client_loop_tx,
+                client_broadcast_tx,
+                subscription_capacity,
+            },
         )
     }
 }
 
 impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
+    /// Create an additional receiver for the incoming messages. Each subscriber receives all
+    /// messages that arrive after it was created, independently of the other subscribers,
+    /// so different parts of an application can each consume the full message stream.
+    ///
+    /// Returns `None` unless `ClientConfig::subscription_capacity` is set. See there for what
+    /// happens when a subscriber falls behind. `recv()` returns `RecvError::Closed` once the
+    /// client has been closed and all buffered messages were received.
+    ///
+    /// ```no_run
+    /// use tokio::sync::broadcast::error::RecvError;
+    /// use twitch_irc::login::StaticLoginCredentials;
+    /// use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = ClientConfig {
+    ///     subscription_capacity: Some(1000),
+    ///     ..ClientConfig::default()
+    /// };
+    /// let (incoming_messages, client) =
+    ///     TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(config);
+    /// // only the subscriptions are used
+    /// drop(incoming_messages);
+    ///
+    /// let mut subscription = client.subscribe().unwrap();
+    /// tokio::spawn(async move {
+    ///     loop {
+    ///         match subscription.recv().await {
+    ///             Ok(message) => println!("{}", message),
+    ///             Err(RecvError::Lagged(skipped)) => println!("Missed {} messages", skipped),
+    ///             Err(RecvError::Closed) => break,
+    ///         }
+    ///     }
+    /// });
+    /// client.join("sodapoppin".to_owned());
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> Option<broadcast::Receiver<ServerMessage>> {
+        self.client_broadcast_tx
+            .as_ref()
+            .map(|client_broadcast_tx| client_broadcast_tx.subscribe())
+    }
+
+    /// Same as `subscribe()`, but the new receiver only gets the incoming messages of the
+    /// given `kinds`, e.g. only `PRIVMSG`s. The client checks the kind of each message before
+    /// handing it to the subscriber, so messages of other kinds are never cloned for it, which
+    /// makes this cheaper than filtering the messages of a `subscribe()` receiver.
+    ///
+    /// Each of these subscribers has its own buffer of `ClientConfig::subscription_capacity`
+    /// messages, so it only falls behind (see `subscribe()`) because of the messages it
+    /// actually receives. Returns `None` unless `ClientConfig::subscription_capacity` is set.
+    ///
+    /// ```no_run
+    /// use twitch_irc::login::StaticLoginCredentials;
+    /// use twitch_irc::message::{MessageKind, ServerMessage};
+    /// use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = ClientConfig {
+    ///     subscription_capacity: Some(1000),
+    ///     ..ClientConfig::default()
+    /// };
+    /// let (_incoming_messages, client) =
+    ///     TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(config);
+    ///
+    /// let mut chat_messages = client.subscribe_commands(&[MessageKind::Privmsg]).unwrap();
+    /// tokio::spawn(async move {
+    ///     while let Ok(message) = chat_messages.recv().await {
+    ///         if let ServerMessage::Privmsg(msg) = message {
+    ///             println!("{}: {}", msg.sender.name, msg.message_text);
+    ///         }
+    ///     }
+    /// });
+    /// client.join("sodapoppin".to_owned());
+    /// # }
+    /// ```
+    pub fn subscribe_commands(
+        &self,
+        kinds: &[MessageKind],
+    ) -> Option<broadcast::Receiver<ServerMessage>> {
+        let (sender, receiver) = broadcast::channel(self.subscription_capacity?);
+        self.client_loop_tx
+            .send(ClientLoopCommand::SubscribeCommands {
+                kinds: kinds.to_vec(),
+                sender,
+            })
+            .unwrap();
+        Some(receiver)
+    }
+
+    /// Stop delivering incoming messages (to the `IncomingMessages` returned by `new()`, to
+    /// subscribers, and to `next_matching()`) until `resume()` is called, e.g. during a
+    /// maintenance window of your application.
+    ///
+    /// Unlike disconnecting, the connections stay open and joined to their channels, and are
+    /// kept alive as usual. Messages received while paused are held back and delivered in order
+    /// once resumed. At most `ClientConfig::paused_messages_capacity` messages are held back,
+    /// after that the oldest ones are dropped.
+    ///
+    /// Pausing an already paused client has no effect.
+    pub fn pause(&self) {
+        self.client_loop_tx.send(ClientLoopCommand::Pause).unwrap();
+    }
+
+    /// Resume delivering incoming messages after `pause()`, starting with the messages that
+    /// were held back while paused. Has no effect if the client is not paused.
+    pub fn resume(&self) {
+        self.client_loop_tx.send(ClientLoopCommand::Resume).unwrap();
+    }
+
     /// Connect to Twitch IRC without joining any channels.
     ///
     /// **You typically do not need to call this method.** This is only provided for the rare
@@ -105,6 +269,85 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         return_rx.await.unwrap()
     }
 
+    /// Send an arbitrary IRC message like `send_message()`, but retry sending it if that
+    /// fails with an `Error::OutgoingError` (e.g. because the connection broke down). The
+    /// failed connection is closed and replaced as usual, and the message is sent again over
+    /// another connection of the pool, up to `max_retries` times. Other errors are returned
+    /// right away.
+    ///
+    /// Only use this for messages that are safe to be sent more than once: a failed send may
+    /// still have reached Twitch. Chat messages usually are not, which is why `send_message()`
+    /// never retries.
+    pub async fn send_with_retry(
+        &self,
+        message: IRCMessage,
+        max_retries: usize,
+    ) -> Result<(), Error<T, L>> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::SendMessageWithRetry {
+                message,
+                retries_left: max_retries,
+                failed_connection_id: None,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Send multiple arbitrary IRC messages in the given order, without any other message
+    /// being sent in between them (e.g. a `/timeout` followed by a `PRIVMSG` explaining it).
+    ///
+    /// Unlike calling `send_message()` multiple times, all messages are sent over the same
+    /// connection of the connection pool, and are queued up on that connection all at once.
+    /// Messages sent concurrently by other tasks are sent before or after the whole batch.
+    ///
+    /// Returns the first error in case any of the messages could not be sent over the picked
+    /// connection. In that case, messages later in the batch were most likely not sent either.
+    pub async fn send_all_ordered(&self, messages: Vec<IRCMessage>) -> Result<(), Error<T, L>> {
+        let (messages, return_rxs): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .map(|message| {
+                let (return_tx, return_rx) = oneshot::channel();
+                ((message, return_tx), return_rx)
+            })
+            .unzip();
+        self.client_loop_tx
+            .send(ClientLoopCommand::SendMessagesOrdered { messages })
+            .unwrap();
+        for return_rx in return_rxs {
+            // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+            return_rx.await.unwrap()?;
+        }
+        Ok(())
+    }
+
+    /// Send an arbitrary IRC message over the pool connection with the given ID, bypassing
+    /// the usual choice of connection. This is intended for debugging and testing, e.g. to
+    /// diagnose issues with a specific connection. See `pool_status()` for the IDs of the
+    /// connections currently in the pool.
+    ///
+    /// Returns `Error::ConnectionNotFound` if there is no connection with the given ID in the
+    /// pool (e.g. because it was already closed), or another error in case the message could
+    /// not be sent over that connection.
+    pub async fn send_on_connection(
+        &self,
+        connection_id: usize,
+        message: IRCMessage,
+    ) -> Result<(), Error<T, L>> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::SendOnConnection {
+                connection_id,
+                message,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
     /// Send a `PRIVMSG`-type IRC message to a Twitch channel. The `message` can be a normal
     /// chat message or a chat command like `/ban` or similar.
     ///
@@ -126,11 +369,14 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         target_login: &str,
         reason: Option<&str>,
     ) -> Result<(), Error<T, L>> {
-        let command = match reason {
-            Some(reason) => format!("/ban {} {}", target_login, reason),
-            None => format!("/ban {}", target_login),
-        };
-        self.privmsg(channel_login, command).await
+        self.run_command(
+            channel_login,
+            ChatCommand::Ban {
+                user_login: target_login.to_owned(),
+                reason: reason.map(|r| r.to_owned()),
+            },
+        )
+        .await
     }
 
     /// Unban a user from the given Twitch channel.
@@ -143,8 +389,13 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         channel_login: String,
         target_login: &str,
     ) -> Result<(), Error<T, L>> {
-        self.privmsg(channel_login, format!("/unban {}", target_login))
-            .await
+        self.run_command(
+            channel_login,
+            ChatCommand::Unban {
+                user_login: target_login.to_owned(),
+            },
+        )
+        .await
     }
 
     /// Timeout a user in the given Twitch channel.
@@ -159,17 +410,15 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         duration: Duration,
         reason: Option<&str>,
     ) -> Result<(), Error<T, L>> {
-        let command = match reason {
-            Some(reason) => format!(
-                "/timeout {} {} {}",
-                target_login,
-                duration.as_secs(),
-                reason
-            ),
-            None => format!("/timeout {} {}", target_login, duration.as_secs()),
-        };
-
-        self.privmsg(channel_login, command).await
+        self.run_command(
+            channel_login,
+            ChatCommand::Timeout {
+                user_login: target_login.to_owned(),
+                duration,
+                reason: reason.map(|r| r.to_owned()),
+            },
+        )
+        .await
     }
 
     /// Remove the timeout from a user in the given Twitch channel.
@@ -183,8 +432,32 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         channel_login: String,
         target_login: &str,
     ) -> Result<(), Error<T, L>> {
-        self.privmsg(channel_login, format!("/untimeout {}", target_login))
-            .await
+        self.run_command(
+            channel_login,
+            ChatCommand::Untimeout {
+                user_login: target_login.to_owned(),
+            },
+        )
+        .await
+    }
+
+    /// Run a chat command (e.g. `/slow` or `/emoteonly`) in the given Twitch channel.
+    /// See [`ChatCommand`](crate::message::ChatCommand) for the available commands.
+    ///
+    /// Note that this will not throw an error if the command fails on Twitch's side, e.g.
+    /// because the logged-in user does not have the required permission. An error is only
+    /// returned if something prevented the command from being sent over the wire, or with
+    /// `Error::InvalidChatCommand` if the channel login or an argument of the command is not
+    /// usable (see `ChatCommand::to_irc_message()`), in which case nothing is sent.
+    pub async fn run_command(
+        &self,
+        channel_login: String,
+        command: ChatCommand,
+    ) -> Result<(), Error<T, L>> {
+        let message = command
+            .to_irc_message(&channel_login)
+            .map_err(Error::InvalidChatCommand)?;
+        self.send_message(message).await
     }
 
     /// Say a chat message in the given Twitch channel.
@@ -203,6 +476,8 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     /// Say a chat message in the given Twitch channel, but send it as a response to another message if `reply_to_id` is specified.
     ///
     /// Behaves the same as `say()` when `reply_to_id` is None, but tags the original message and it's sender if specified.
+    /// An empty `reply_to_id` (the `message_id` of messages received without tags) is treated
+    /// like `None`.
     pub async fn say_in_response(
         &self,
         channel_login: String,
@@ -211,7 +486,8 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ) -> Result<(), Error<T, L>> {
         let mut tags = IRCTags::new();
 
-        if let Some(id) = reply_to_id {
+        // messages received without tags have an empty ID, which cannot be replied to
+        if let Some(id) = reply_to_id.filter(|id| !id.is_empty()) {
             tags.0.insert("reply-parent-msg-id".to_string(), Some(id));
         }
 
@@ -241,6 +517,21 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         .await
     }
 
+    /// Replies to a message described by a `ReplyContext`, tagging the original message and
+    /// its sender. Use this instead of `reply_to_privmsg()` when you don't want to keep the
+    /// full `PrivmsgMessage` around until the reply is sent.
+    ///
+    /// This method behaves the same as `reply_to_privmsg()`. If the `message_id` of the
+    /// `ReplyContext` is empty, the message is sent without referring to the original message.
+    pub async fn reply(&self, message: String, reply_to: &ReplyContext) -> Result<(), Error<T, L>> {
+        self.say_in_response(
+            reply_to.channel_login.clone(),
+            message,
+            Some(reply_to.message_id.clone()),
+        )
+        .await
+    }
+
     /// Join the given Twitch channel (When a channel is joined, the client will receive messages
     /// sent to it).
     ///
@@ -273,16 +564,29 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     /// different or new connection.
     /// Unless an answer is again received by the server, the `join()` will then make attempts again
     /// to join that channel.
+    ///
+    /// The `channel_login` is converted to lowercase, since Twitch does not join channels
+    /// given with uppercase letters. Channel logins that are not valid (see `ChannelLogin`),
+    /// e.g. because they contain whitespace or start with a `#`, are ignored and a warning
+    /// is logged.
     pub fn join(&self, channel_login: String) {
-        self.client_loop_tx
-            .send(ClientLoopCommand::Join { channel_login })
-            .unwrap();
+        if let Some(channel_login) = normalize_channel_login(&channel_login) {
+            self.client_loop_tx
+                .send(ClientLoopCommand::Join { channel_login })
+                .unwrap();
+        }
     }
 
     /// Instruct the client to only be connected to these channels. Channels currently joined
     /// but not in the given set are parted, and channels in the set that are not currently
     /// joined are joined.
+    ///
+    /// The channel logins are normalized the same way as for `join()`.
     pub fn set_wanted_channels(&self, channels: HashSet<String>) {
+        let channels = channels
+            .iter()
+            .filter_map(|channel_login| normalize_channel_login(channel_login))
+            .collect();
         self.client_loop_tx
             .send(ClientLoopCommand::SetWantedChannels { channels })
             .unwrap();
@@ -312,6 +616,7 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     /// `(false, false)` is returned for a channel that has not been joined previously at all
     /// or where a previous `PART` command has completed.
     pub async fn get_channel_status(&self, channel_login: String) -> (bool, bool) {
+        let channel_login = channel_login.to_lowercase();
         let (return_tx, return_rx) = oneshot::channel();
         self.client_loop_tx
             .send(ClientLoopCommand::GetChannelStatus {
@@ -323,14 +628,96 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         return_rx.await.unwrap()
     }
 
+    /// Get the current "room state" (e.g. whether slow mode or emote-only mode are enabled)
+    /// of a joined channel.
+    ///
+    /// The client keeps track of all `ROOMSTATE` messages it receives, merging the partial
+    /// updates Twitch sends when a setting changes onto the full state that is sent
+    /// after joining the channel. See `RoomState` for details.
+    ///
+    /// Returns `None` if no `ROOMSTATE` has been received for this channel yet, e.g. because
+    /// the channel was not joined (yet). The state is forgotten when the channel is parted.
+    pub async fn room_state(&self, channel_login: String) -> Option<RoomState> {
+        let channel_login = channel_login.to_lowercase();
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetRoomState {
+                channel_login,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Look up the login name of a joined channel by its ID (`room-id`). Channel IDs never
+    /// change, while channels can be renamed.
+    ///
+    /// Twitch IRC only accepts channel login names for joining and sending to channels.
+    /// The client learns the ID of each channel from the `ROOMSTATE` message that Twitch sends
+    /// after joining it (see `room_state()`), so only channels that are currently joined can
+    /// be looked up. Returns `None` for all other channels.
+    pub async fn channel_login_by_id(&self, channel_id: String) -> Option<String> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetChannelLoginById {
+                channel_id,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Say a chat message in the joined channel with the given ID (`room-id`), see `say()`.
+    ///
+    /// The channel is looked up using `channel_login_by_id()`. Returns
+    /// `Error::UnknownChannelId` if no joined channel with that ID is known.
+    pub async fn say_by_channel_id(
+        &self,
+        channel_id: String,
+        message: String,
+    ) -> Result<(), Error<T, L>> {
+        match self.channel_login_by_id(channel_id.clone()).await {
+            Some(channel_login) => self.say(channel_login, message).await,
+            None => Err(Error::UnknownChannelId(channel_id)),
+        }
+    }
+
     /// Part (leave) a channel, to stop receiving messages sent to that channel.
     ///
     /// This has the same semantics as `join()`. Similarly, a `part()` call will have no effect
-    /// if the channel is not currently joined.
+    /// if the channel is not currently joined. The `channel_login` is normalized the same way
+    /// as for `join()`.
     pub fn part(&self, channel_login: String) {
+        if let Some(channel_login) = normalize_channel_login(&channel_login) {
+            self.client_loop_tx
+                .send(ClientLoopCommand::Part { channel_login })
+                .unwrap();
+        }
+    }
+
+    /// Request additional IRC capabilities (e.g. `twitch.tv/membership`) at runtime, without
+    /// reconnecting. A `CAP REQ` is sent over every connection in the pool (a connection is
+    /// made if there is none yet), and connections made later (e.g. replacements for failed
+    /// connections) request the capabilities as well.
+    ///
+    /// Resolves once all connections have replied: `Ok(true)` if Twitch acknowledged the
+    /// capabilities (`CAP ACK`) on all of them, `Ok(false)` if any connection received a
+    /// `CAP NAK`. An error is returned if a connection closed before replying.
+    ///
+    /// If `twitch.tv/membership` is requested this way, the `JOIN` and `PART` messages of
+    /// other users are delivered like with `MembershipMode::All`.
+    pub async fn request_capabilities(&self, capabilities: &[&str]) -> Result<bool, Error<T, L>> {
+        let (return_tx, return_rx) = oneshot::channel();
         self.client_loop_tx
-            .send(ClientLoopCommand::Part { channel_login })
+            .send(ClientLoopCommand::RequestCapabilities {
+                capabilities: capabilities.iter().map(|c| (*c).to_owned()).collect(),
+                return_sender: return_tx,
+            })
             .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
     }
 
     /// Ping a random connection. This does not await the `PONG` response from Twitch.
@@ -346,4 +733,2230 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
         return_rx.await.unwrap()
     }
+
+    /// Wait for the next incoming message for which `predicate` returns `true`, for at most
+    /// `timeout`. Returns `None` if no matching message arrived in time, or if the client
+    /// was closed in the meantime.
+    ///
+    /// This only observes the incoming messages: All messages, including the matching one,
+    /// are still delivered to the `IncomingMessages` and to all subscribers as usual.
+    /// Any number of calls can wait at the same time, and a message can match multiple of them.
+    ///
+    /// Waiting starts when this method is called, not when the returned future is first
+    /// awaited. This way, you can call this method before sending a command or joining a
+    /// channel, and the reply cannot be missed even if it arrives before you await the future:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use twitch_irc::login::StaticLoginCredentials;
+    /// use twitch_irc::message::ServerMessage;
+    /// use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let (_incoming_messages, client) =
+    ///     TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(ClientConfig::default());
+    ///
+    /// // Twitch sends a ROOMSTATE once the channel has been joined
+    /// let joined = client.next_matching(
+    ///     |message| matches!(message, ServerMessage::RoomState(room_state)
+    ///         if room_state.channel_login == "sodapoppin"),
+    ///     Duration::from_secs(5),
+    /// );
+    /// client.join("sodapoppin".to_owned());
+    /// match joined.await {
+    ///     Some(_) => println!("joined sodapoppin"),
+    ///     None => println!("did not join sodapoppin in time"),
+    /// }
+    /// # }
+    /// ```
+    pub fn next_matching<F>(
+        &self,
+        predicate: F,
+        timeout: Duration,
+    ) -> impl Future<Output = Option<ServerMessage>>
+    where
+        F: Fn(&ServerMessage) -> bool + Send + 'static,
+    {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::WaitForMessage {
+                predicate: MessagePredicate(Box::new(predicate)),
+                return_sender: return_tx,
+            })
+            .unwrap();
+        async move {
+            // the return sender is dropped without sending if the client is closed
+            tokio::time::timeout(timeout, return_rx).await.ok()?.ok()
+        }
+    }
+
+    /// Close the pool connection with the given ID, as if it had failed. Channels that were
+    /// joined on that connection are rejoined on other or new connections, the same as after
+    /// any other connection failure (the close reason is `Error::ForcedReconnect`).
+    /// Other connections are left untouched.
+    ///
+    /// Pool connections are numbered sequentially starting at 0, and the ID of each connection
+    /// is included in the log output of this library.
+    ///
+    /// Returns `false` if there is no connection with the given ID (e.g. because it
+    /// was already closed).
+    pub async fn force_reconnect(&self, connection_id: usize) -> bool {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::ForceReconnect {
+                connection_id,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Get the causes of the most recently closed connections of this client, together with the
+    /// time at which each connection was closed. The oldest entry comes first.
+    ///
+    /// Only the last 32 close reasons are kept, older entries are discarded. This is intended
+    /// for on-demand introspection (e.g. for a health dashboard or interactive debugging),
+    /// and complements the `twitch_irc_reconnects` metric.
+    pub async fn recent_close_reasons(&self) -> Vec<(Instant, Error<T, L>)> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetRecentCloseReasons {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Get the delay the client currently waits before replacing a failed connection, e.g.
+    /// to show "reconnecting in 8 seconds" on a dashboard. See `ClientConfig::reconnect_backoff`
+    /// for how the delay grows while connections keep failing.
+    ///
+    /// Returns `None` if no connection has failed since a connection was last opened
+    /// successfully.
+    pub async fn reconnect_backoff(&self) -> Option<ReconnectBackoff> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetReconnectBackoff {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Get a snapshot of the connections currently in the connection pool of this client,
+    /// including the state of each connection and the channels assigned to it.
+    /// Connections are listed in the order they were created.
+    ///
+    /// This is intended for on-demand introspection, e.g. for a dashboard or for debugging.
+    /// The returned values are not updated when the pool changes.
+    pub async fn pool_status(&self) -> Vec<ConnectionStatus> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetPoolStatus {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Get the login name that messages sent by this client are sent as, as acknowledged by
+    /// the server (see `ConnectionStatus::user_login`). This is e.g. useful to recognize the
+    /// client's own messages, also when logged in anonymously.
+    ///
+    /// Returns `None` if no connection that messages are sent over is open yet.
+    pub async fn user_login(&self) -> Option<String> {
+        self.pool_status()
+            .await
+            .into_iter()
+            .filter(|status| !status.read_only)
+            .find_map(|status| status.user_login)
+    }
+}
+
+/// Validates and lowercases a channel login given to `join()`, `part()` or
+/// `set_wanted_channels()`. Invalid logins are logged and `None` is returned.
+fn normalize_channel_login(channel_login: &str) -> Option<String> {
+    match ChannelLogin::new(channel_login) {
+        Ok(channel_login) => Some(channel_login.into_string()),
+        Err(e) => {
+            log::warn!("Ignoring channel: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{
+        ClientConfig, MembershipMode, OutgoingMessageHook, OverflowPolicy, RawLineHook,
+        ReconnectHook, SendFailureHook,
+    };
+    use crate::error::Error;
+    use crate::irc;
+    use crate::login::{CredentialsPair, StaticLoginCredentials};
+    use crate::message::{
+        AsRawIRC, ChatCommand, ChatCommandError, IRCMessage, IRCParseError, MessageKind,
+        PrivmsgMessage, ReplyContext, SendFailureReason, ServerMessage,
+    };
+    use crate::transport::mock::MockTransportError;
+    use crate::transport::mock::{
+        set_connect_failing, wait_for_connections, MockConnection, MockTransport,
+    };
+    use crate::{ConnectionState, ConnectionStatus, IncomingMessages, TwitchIRCClient};
+    use futures_util::FutureExt;
+    use std::convert::TryFrom;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::broadcast::error::RecvError;
+
+    #[tokio::test]
+    async fn test_connection_rate_limit_spacing() {
+        let mut config = ClientConfig {
+            // forces each join onto its own connection
+            max_channels_per_connection: 1,
+            ..ClientConfig::default()
+        };
+        config.set_connection_rate_limit(1, Duration::from_millis(100));
+
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        client.join("forsen".to_owned());
+
+        let connections = wait_for_connections(2).await;
+        assert!(
+            connections[1].connected_at - connections[0].connected_at >= Duration::from_millis(100)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_rate_limit_parallel() {
+        let mut config = ClientConfig {
+            max_channels_per_connection: 1,
+            ..ClientConfig::default()
+        };
+        config.set_connection_rate_limit(2, Duration::from_secs(10));
+
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        client.join("forsen".to_owned());
+
+        // both connections hold a permit each, so neither had to wait for the other
+        let connections = wait_for_connections(2).await;
+        assert!(
+            connections[1].connected_at - connections[0].connected_at < Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_connection_rate_limit_zero_permits() {
+        let mut config = ClientConfig::default();
+        config.set_connection_rate_limit(0, Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_connection_logs_in() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+
+        let mut connections = wait_for_connections(1).await;
+        let outgoing_rx = &mut connections[0].outgoing_rx;
+        assert_eq!(
+            outgoing_rx.recv().await.unwrap(),
+            irc!["CAP", "REQ", "twitch.tv/tags twitch.tv/commands"]
+        );
+        assert_eq!(
+            outgoing_rx.recv().await.unwrap(),
+            irc!["NICK", "justinfan12345"]
+        );
+    }
+
+    async fn get_login_messages(credentials: CredentialsPair) -> Vec<IRCMessage> {
+        let config = ClientConfig {
+            login_credentials: StaticLoginCredentials { credentials },
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let mut messages = vec![];
+        for _ in 0..3 {
+            messages.push(connection.outgoing_rx.recv().await.unwrap());
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_connection_logs_in_with_token() {
+        let messages = get_login_messages(CredentialsPair::new(
+            "randers".to_owned(),
+            Some("abcdef123456".to_owned()),
+        ))
+        .await;
+        assert_eq!(messages[1], irc!["PASS", "oauth:abcdef123456"]);
+        assert_eq!(messages[2], irc!["NICK", "randers"]);
+    }
+
+    #[tokio::test]
+    async fn test_connection_logs_in_with_custom_token_prefix() {
+        let messages = get_login_messages(CredentialsPair {
+            token_prefix: "".to_owned(),
+            ..CredentialsPair::new("randers".to_owned(), Some("abcdef123456".to_owned()))
+        })
+        .await;
+        assert_eq!(messages[1], irc!["PASS", "abcdef123456"]);
+        assert_eq!(messages[2], irc!["NICK", "randers"]);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_connection_closed() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        // the server closing the connection
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        drop(connection.incoming_tx);
+
+        // the client keeps one connection open to receive whispers
+        wait_for_connections(1).await;
+
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(
+            recent_close_reasons[0].1,
+            Error::RemoteUnexpectedlyClosedConnection
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            handshake_timeout: Duration::from_millis(100),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        // the server accepts the connection, but never says anything
+        let _silent_connection = wait_for_connections(1).await;
+
+        // the connection is replaced once the handshake timeout expires
+        wait_for_connections(1).await;
+
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(recent_close_reasons[0].1, Error::HandshakeTimeout));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_completed_by_welcome_message() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            handshake_timeout: Duration::from_millis(100),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            )
+            .unwrap()))
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(client.recent_close_reasons().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_login_from_welcome_message() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        assert_eq!(client.user_login().await, None);
+        client.connect().await;
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+
+        // the login name sent with NICK is used until the server acknowledges one
+        while connection.outgoing_rx.recv().await.unwrap().command != "NICK" {}
+        assert_eq!(client.user_login().await, Some("justinfan12345".to_owned()));
+
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv 001 justinfan67890 :Welcome, GLHF!",
+            )
+            .unwrap()))
+            .unwrap();
+        incoming_messages.recv().await.unwrap();
+        assert_eq!(client.user_login().await, Some("justinfan67890".to_owned()));
+        assert_eq!(
+            client.pool_status().await[0].user_login,
+            Some("justinfan67890".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recv_many_preserves_order() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        for i in 0..5 {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(&format!(
+                    ":tmi.twitch.tv NOTICE #pajlada :message {}",
+                    i
+                ))
+                .unwrap()))
+                .unwrap();
+        }
+
+        let mut buffer = Vec::new();
+        while buffer.len() < 5 {
+            incoming_messages.recv_many(&mut buffer, 3).await;
+        }
+        assert_eq!(
+            buffer
+                .iter()
+                .map(|message| message.source().params[1].as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "message 0",
+                "message 1",
+                "message 2",
+                "message 3",
+                "message 4"
+            ]
+        );
+    }
+
+    /// Connects a client to the mock server, completes the handshake and waits for the first
+    /// keepalive `PING`. Returns the client, the server side of the connection and the token
+    /// sent with the `PING`.
+    async fn connect_and_wait_for_ping() -> (
+        TwitchIRCClient<MockTransport, StaticLoginCredentials>,
+        MockConnection,
+        String,
+    ) {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            )
+            .unwrap()))
+            .unwrap();
+
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PING" {
+                let token = message.params[0].clone();
+                return (client, connection, token);
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_with_matching_pong_keeps_connection_open() {
+        let (client, connection, token) = connect_and_wait_for_ping().await;
+
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(&format!(
+                ":tmi.twitch.tv PONG tmi.twitch.tv :{}",
+                token
+            ))
+            .unwrap()))
+            .unwrap();
+
+        // past the point where the pong is checked
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(client.recent_close_reasons().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_with_unmatched_pong_times_out() {
+        let (client, connection, token) = connect_and_wait_for_ping().await;
+
+        // answers to some other (e.g. an earlier) PING must not satisfy the check
+        for pong in &[
+            "PONG :tmi.twitch.tv".to_owned(),
+            format!(":tmi.twitch.tv PONG tmi.twitch.tv :{}-stale", token),
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(pong).unwrap()))
+                .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(recent_close_reasons[0].1, Error::PingTimeout));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_pings_disabled() {
+        let config = ClientConfig {
+            send_pings: false,
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            )
+            .unwrap()))
+            .unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse("PING :tmi.twitch.tv").unwrap()))
+            .unwrap();
+
+        // long past the point where the first PING would have been sent and checked
+        tokio::time::sleep(Duration::from_secs(120)).await;
+
+        let mut commands = vec![];
+        while let Ok(message) = connection.outgoing_rx.try_recv() {
+            commands.push(message.command);
+        }
+        assert!(commands.contains(&"PONG".to_owned()));
+        assert!(!commands.contains(&"PING".to_owned()));
+        assert!(client.recent_close_reasons().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_if_idle() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            reconnect_if_idle: Some(Duration::from_secs(20)),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            )
+            .unwrap()))
+            .unwrap();
+
+        // PINGs from the server don't count as activity
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse("PING :tmi.twitch.tv").unwrap()))
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert!(client.recent_close_reasons().await.is_empty());
+
+        // the server stays silent, so the connection is replaced after the idle period
+        // (before the first PING from the client would have been checked)
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        wait_for_connections(1).await;
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(recent_close_reasons[0].1, Error::IdleTimeout));
+    }
+
+    #[tokio::test]
+    async fn test_pong_echoes_ping_token() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        for ping in &[
+            "PING :tmi.twitch.tv",
+            "PING :some-token",
+            ":tmi.twitch.tv PING",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(ping).unwrap()))
+                .unwrap();
+        }
+
+        let mut pongs = vec![];
+        while pongs.len() < 3 {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PONG" {
+                pongs.push(message.as_raw_irc());
+            }
+        }
+        assert_eq!(
+            pongs,
+            vec![
+                "PONG tmi.twitch.tv",
+                "PONG some-token",
+                "PONG tmi.twitch.tv"
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_next_matching() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        let is_ban_notice = |message: &ServerMessage| {
+            matches!(message, ServerMessage::Notice(notice)
+                if notice.message_id.as_deref() == Some("msg_banned"))
+        };
+        let ban_notice = client.next_matching(is_ban_notice, Duration::from_secs(5));
+        let ban_notice_2 = client.next_matching(is_ban_notice, Duration::from_secs(5));
+        let never_matching = client.next_matching(|_| false, Duration::from_secs(5));
+
+        let sources = [
+            ":tmi.twitch.tv NOTICE #forsen :Some other notice.",
+            ":tmi.twitch.tv PING",
+            "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen.",
+            "@msg-id=msg_banned :tmi.twitch.tv NOTICE #pajlada :You are permanently banned from talking in pajlada.",
+        ];
+        for source in sources.iter() {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(source).unwrap()))
+                .unwrap();
+        }
+
+        // both waiters get the first matching message
+        assert_eq!(ban_notice.await.unwrap().source().as_raw_irc(), sources[2]);
+        assert_eq!(
+            ban_notice_2.await.unwrap().source().as_raw_irc(),
+            sources[2]
+        );
+        assert!(never_matching.await.is_none());
+
+        // the messages are still delivered as usual
+        for source in sources.iter() {
+            assert_eq!(
+                &incoming_messages
+                    .recv()
+                    .await
+                    .unwrap()
+                    .source()
+                    .as_raw_irc(),
+                source
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_tokens_are_unique() {
+        let (_client, mut connection, first_token) = connect_and_wait_for_ping().await;
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(&format!(
+                ":tmi.twitch.tv PONG tmi.twitch.tv :{}",
+                first_token
+            ))
+            .unwrap()))
+            .unwrap();
+
+        let second_ping = loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PING" {
+                break message;
+            }
+        };
+        assert_ne!(second_ping.params[0], first_token);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_grace_period() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            reconnect_grace_period: Duration::from_millis(200),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+
+        let first_connection = wait_for_connections(1).await.pop().unwrap();
+        for message in &[
+            ":tmi.twitch.tv RECONNECT",
+            "@badge-info=;badges=;color=;display-name=randers;emotes=;flags=;id=6e2ccb1f-01ed-44d0-85b6-edf762524475;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :still here",
+        ] {
+            first_connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(message).unwrap()))
+                .unwrap();
+        }
+
+        assert!(matches!(
+            incoming_messages.recv().await.unwrap(),
+            ServerMessage::Reconnect(_)
+        ));
+        // the replacement connection is made right away
+        let _second_connection = wait_for_connections(1).await;
+        // and the message sent after RECONNECT is still delivered
+        match incoming_messages.recv().await.unwrap() {
+            ServerMessage::Privmsg(msg) => assert_eq!(msg.message_text, "still here"),
+            other => panic!("expected PRIVMSG, got {:?}", other),
+        }
+
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(recent_close_reasons[0].1, Error::ReconnectCmd));
+
+        // the old connection is kept open during the grace period, and closed once it is over
+        tokio::time::advance(Duration::from_millis(150)).await;
+        assert!(!first_connection.incoming_tx.is_closed());
+        tokio::time::advance(Duration::from_millis(100)).await;
+        tokio::task::yield_now().await;
+        assert!(first_connection.incoming_tx.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_room_state_cache() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.join("pajlada".to_owned());
+        assert_eq!(client.room_state("pajlada".to_owned()).await, None);
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        for message in &[
+            "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=11148817;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #pajlada",
+            "@room-id=11148817;slow=10 :tmi.twitch.tv ROOMSTATE #pajlada",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(message).unwrap()))
+                .unwrap();
+        }
+        // wait until both messages have been processed by the client
+        incoming_messages.recv().await.unwrap();
+        incoming_messages.recv().await.unwrap();
+
+        let room_state = client.room_state("pajlada".to_owned()).await.unwrap();
+        assert_eq!(room_state.channel_id, "11148817");
+        assert_eq!(room_state.emote_only, Some(false));
+        assert_eq!(room_state.slow_mode, Some(Duration::from_secs(10)));
+        assert_eq!(client.room_state("forsen".to_owned()).await, None);
+
+        client.part("pajlada".to_owned());
+        assert_eq!(client.room_state("pajlada".to_owned()).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_room_state_cache_notice_without_roomstate() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.join("pajlada".to_owned());
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        for message in &[
+            "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=11148817;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #pajlada",
+            // no ROOMSTATE follows these
+            "@msg-id=emote_only_on :tmi.twitch.tv NOTICE #pajlada :This room is now in emote-only mode.",
+            "@msg-id=slow_on :tmi.twitch.tv NOTICE #pajlada :This room is now in slow mode. You may send messages every 10 seconds.",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(message).unwrap()))
+                .unwrap();
+        }
+        for _ in 0..3 {
+            incoming_messages.recv().await.unwrap();
+        }
+
+        let room_state = client.room_state("pajlada".to_owned()).await.unwrap();
+        assert_eq!(room_state.emote_only, Some(true));
+        // slow mode is on, but its duration is only known from a ROOMSTATE
+        assert_eq!(room_state.slow_mode, None);
+        assert_eq!(room_state.subscribers_only, Some(false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_capabilities() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.join("pajlada".to_owned());
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { client.request_capabilities(&["twitch.tv/membership"]).await }
+        });
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message == irc!["CAP", "REQ", "twitch.tv/membership"] {
+                break;
+            }
+        }
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv CAP * ACK :twitch.tv/membership",
+            )
+            .unwrap()))
+            .unwrap();
+        assert!(request.await.unwrap().unwrap());
+
+        // the JOINs of other users are delivered now
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":randers!randers@randers.tmi.twitch.tv JOIN #pajlada",
+            )
+            .unwrap()))
+            .unwrap();
+        loop {
+            if let ServerMessage::Join(join) = incoming_messages.recv().await.unwrap() {
+                assert_eq!(join.user_login, "randers");
+                break;
+            }
+        }
+
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { client.request_capabilities(&["twitch.tv/unknown"]).await }
+        });
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "CAP" {
+                break;
+            }
+        }
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":tmi.twitch.tv CAP * NAK :twitch.tv/unknown",
+            )
+            .unwrap()))
+            .unwrap();
+        assert!(!request.await.unwrap().unwrap());
+
+        // a new connection requests the acknowledged capabilities again
+        assert!(client.force_reconnect(0).await);
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "CAP" && message.params[1] != "twitch.tv/tags twitch.tv/commands"
+            {
+                assert_eq!(message, irc!["CAP", "REQ", "twitch.tv/membership"]);
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_command_invalid_argument() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+
+        assert!(matches!(
+            client
+                .ban("pajlada".to_owned(), "weeb123\r\nJOIN #forsen", None)
+                .await,
+            Err(Error::InvalidChatCommand(ChatCommandError::InvalidArgument(argument)))
+                if argument == "weeb123\r\nJOIN #forsen"
+        ));
+        assert_eq!(client.pool_status().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_run_command_invalid_channel() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+
+        for channel_login in &["", "#pajlada", "pajlada JOIN #forsen"] {
+            assert!(matches!(
+                client
+                    .run_command((*channel_login).to_owned(), ChatCommand::Clear)
+                    .await,
+                Err(Error::InvalidChatCommand(ChatCommandError::InvalidChannelLogin(e)))
+                    if e.0 == *channel_login
+            ));
+        }
+        assert_eq!(client.pool_status().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_say_by_channel_id() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.join("pajlada".to_owned());
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+
+        assert!(matches!(
+            client
+                .say_by_channel_id("11148817".to_owned(), "hi".to_owned())
+                .await,
+            Err(Error::UnknownChannelId(channel_id)) if channel_id == "11148817"
+        ));
+
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=11148817;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #pajlada",
+            )
+            .unwrap()))
+            .unwrap();
+        incoming_messages.recv().await.unwrap();
+
+        assert_eq!(
+            client.channel_login_by_id("11148817".to_owned()).await,
+            Some("pajlada".to_owned())
+        );
+        assert_eq!(
+            client.channel_login_by_id("22484632".to_owned()).await,
+            None
+        );
+
+        client
+            .say_by_channel_id("11148817".to_owned(), "hi".to_owned())
+            .await
+            .unwrap();
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PRIVMSG" {
+                assert_eq!(message, irc!["PRIVMSG", "#pajlada", ". hi"]);
+                break;
+            }
+        }
+
+        client.part("pajlada".to_owned());
+        assert_eq!(
+            client.channel_login_by_id("11148817".to_owned()).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_status() {
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        assert_eq!(client.pool_status().await, vec![]);
+
+        client.join("pajlada".to_owned());
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(
+                ":justinfan12345!justinfan12345@justinfan12345.tmi.twitch.tv JOIN #pajlada",
+            )
+            .unwrap()))
+            .unwrap();
+        incoming_messages.recv().await.unwrap();
+
+        let pool_status = client.pool_status().await;
+        assert_eq!(
+            pool_status,
+            vec![ConnectionStatus {
+                id: 0,
+                state: ConnectionState::Open,
+                read_only: false,
+                wanted_channels: vec!["pajlada".to_owned()].into_iter().collect(),
+                server_channels: vec!["pajlada".to_owned()].into_iter().collect(),
+                user_login: Some("justinfan12345".to_owned()),
+            }]
+        );
+
+        client.part("pajlada".to_owned());
+        let pool_status = client.pool_status().await;
+        assert_eq!(pool_status.len(), 1);
+        assert!(pool_status[0].wanted_channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_channels_per_connection() {
+        let mut config = ClientConfig {
+            max_channels_per_connection: 2,
+            ..ClientConfig::default()
+        };
+        config.set_connection_rate_limit(1, Duration::from_millis(100));
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+
+        let channels = ["pajlada", "forsen", "nymn", "randers", "sodapoppin"];
+        for channel in &channels {
+            client.join((*channel).to_owned());
+        }
+
+        // the joins spill over onto new connections, which still respect the rate limit
+        let connections = wait_for_connections(3).await;
+        assert!(
+            connections[2].connected_at - connections[1].connected_at >= Duration::from_millis(100)
+        );
+        let pool_status = client.pool_status().await;
+        assert_eq!(pool_status.len(), 3);
+        let mut joined_channels = vec![];
+        for status in pool_status {
+            assert!(status.wanted_channels.len() <= 2);
+            joined_channels.extend(status.wanted_channels);
+        }
+        joined_channels.sort();
+        let mut expected_channels = channels.to_vec();
+        expected_channels.sort_unstable();
+        assert_eq!(joined_channels, expected_channels);
+    }
+
+    #[tokio::test]
+    async fn test_join_and_part_lowercase_channel() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.join("#forsen".to_owned());
+        client.join("Pajlada".to_owned());
+        assert_eq!(
+            client.get_channel_status("Pajlada".to_owned()).await,
+            (true, false)
+        );
+        assert_eq!(
+            client.get_channel_status("forsen".to_owned()).await,
+            (false, false)
+        );
+
+        client.part("PAJLADA".to_owned());
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let mut joins_and_parts = vec![];
+        while joins_and_parts.len() < 2 {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "JOIN" || message.command == "PART" {
+                joins_and_parts.push(message.as_raw_irc());
+            }
+        }
+        assert_eq!(joins_and_parts, vec!["JOIN #pajlada", "PART #pajlada"]);
+        assert_eq!(
+            client.get_channel_status("pajlada".to_owned()).await,
+            (false, false)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dedup_window() {
+        let config = ClientConfig {
+            dedup_window: Some(Duration::from_millis(200)),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        let send_privmsgs = |ids: &[&str]| {
+            for id in ids {
+                let message = format!(
+                    "@badge-info=;badges=;color=;display-name=randers;emotes=;flags=;id={};mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa",
+                    id
+                );
+                connection
+                    .incoming_tx
+                    .send(Ok(IRCMessage::parse(&message).unwrap()))
+                    .unwrap();
+            }
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse("PING :tmi.twitch.tv").unwrap()))
+                .unwrap();
+        };
+        async fn recv_privmsg_ids(incoming_messages: &mut IncomingMessages) -> Vec<String> {
+            let mut ids = vec![];
+            loop {
+                match incoming_messages.recv().await.unwrap() {
+                    ServerMessage::Ping(_) => return ids,
+                    ServerMessage::Privmsg(msg) => ids.push(msg.message_id),
+                    _ => {}
+                }
+            }
+        }
+
+        send_privmsgs(&["a", "a", "b", "a"]);
+        assert_eq!(
+            recv_privmsg_ids(&mut incoming_messages).await,
+            vec!["a", "b"]
+        );
+
+        // once the window has passed, the ID is forgotten
+        tokio::time::advance(Duration::from_millis(300)).await;
+        send_privmsgs(&["a", "a"]);
+        assert_eq!(recv_privmsg_ids(&mut incoming_messages).await, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_dedup_window_messages_without_tags() {
+        let config = ClientConfig {
+            dedup_window: Some(Duration::from_secs(10)),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        // without tags, messages have no ID, so identical messages are not duplicates
+        for _ in 0..2 {
+            connection
+                .raw_incoming_tx
+                .send(":randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa".to_owned())
+                .unwrap();
+        }
+        for _ in 0..2 {
+            match incoming_messages.recv().await.unwrap() {
+                ServerMessage::Privmsg(msg) => assert_eq!(msg.message_id, ""),
+                other => panic!("expected PRIVMSG, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_source_only_shared_messages() {
+        let config = ClientConfig {
+            drop_source_only_shared_messages: true,
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        for (id, source_tags) in [
+            ("a", ""),
+            // source-only, sent in another channel of the shared chat session
+            ("b", "source-only=1;source-room-id=1025594235;"),
+            // source-only, sent in this channel
+            ("c", "source-only=1;source-room-id=11148817;"),
+            ("d", "source-only=0;source-room-id=1025594235;"),
+        ] {
+            let message = format!(
+                "@badge-info=;badges=;color=;display-name=randers;emotes=;flags=;id={};mod=0;room-id=11148817;{}subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa",
+                id, source_tags
+            );
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(&message).unwrap()))
+                .unwrap();
+        }
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse("PING :tmi.twitch.tv").unwrap()))
+            .unwrap();
+
+        let mut ids = vec![];
+        loop {
+            match incoming_messages.recv().await.unwrap() {
+                ServerMessage::Ping(_) => break,
+                ServerMessage::Privmsg(msg) => ids.push(msg.message_id),
+                _ => {}
+            }
+        }
+        assert_eq!(ids, vec!["a", "c", "d"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warm_connections() {
+        let config = ClientConfig {
+            warm_connections: 1,
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+
+        // connected before anything was joined
+        let mut warm_connection = wait_for_connections(1).await.pop().unwrap();
+        let pool_status = client.pool_status().await;
+        assert_eq!(pool_status.len(), 1);
+        assert!(pool_status[0].wanted_channels.is_empty());
+
+        // the join uses the warm connection, and a new warm connection takes its place
+        client.join("pajlada".to_owned());
+        loop {
+            let message = warm_connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "JOIN" {
+                assert_eq!(message, irc!["JOIN", "#pajlada"]);
+                break;
+            }
+        }
+        wait_for_connections(1).await;
+        let mut channel_counts: Vec<usize> = client
+            .pool_status()
+            .await
+            .iter()
+            .map(|c| c.wanted_channels.len())
+            .collect();
+        assert_eq!(channel_counts, vec![1, 0]);
+
+        // joining more channels fills up the first connection before using the warm one
+        client.join("forsen".to_owned());
+        channel_counts = client
+            .pool_status()
+            .await
+            .iter()
+            .map(|c| c.wanted_channels.len())
+            .collect();
+        channel_counts.sort_unstable();
+        assert_eq!(channel_counts, vec![0, 2]);
+    }
+
+    fn read_credentials_config() -> ClientConfig<StaticLoginCredentials> {
+        ClientConfig {
+            login_credentials: StaticLoginCredentials::new(
+                "randers".to_owned(),
+                Some("abcdef123456".to_owned()),
+            ),
+            read_credentials: Some(CredentialsPair::new("justinfan12345".to_owned(), None)),
+            new_connection_every: Duration::from_millis(10),
+            ..ClientConfig::default()
+        }
+    }
+
+    /// Reads the messages sent over the connection until one with the given command is found.
+    /// Returns the login name sent with `NICK` and the found message.
+    async fn recv_until_command(
+        connection: &mut MockConnection,
+        command: &str,
+    ) -> (Option<String>, IRCMessage) {
+        let mut nick = None;
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "NICK" {
+                nick = Some(message.params[0].clone());
+            }
+            if message.command == command {
+                return (nick, message);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_credentials_join() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(read_credentials_config());
+        client.join("pajlada".to_owned());
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let (nick, join) = recv_until_command(&mut connection, "JOIN").await;
+        assert_eq!(nick.as_deref(), Some("justinfan12345"));
+        assert_eq!(join, irc!["JOIN", "#pajlada"]);
+
+        for status in client.pool_status().await {
+            assert_eq!(status.read_only, !status.wanted_channels.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_credentials_privmsg() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(read_credentials_config());
+        client.join("pajlada".to_owned());
+        let mut read_connection = wait_for_connections(1).await.pop().unwrap();
+        recv_until_command(&mut read_connection, "JOIN").await;
+
+        client
+            .privmsg("pajlada".to_owned(), "hi".to_owned())
+            .await
+            .unwrap();
+        let mut send_connection = wait_for_connections(1).await.pop().unwrap();
+        let (nick, privmsg) = recv_until_command(&mut send_connection, "PRIVMSG").await;
+        assert_eq!(nick.as_deref(), Some("randers"));
+        assert_eq!(privmsg, irc!["PRIVMSG", "#pajlada", "hi"]);
+
+        // the read-only connection never sends anything other than the login and the join
+        while let Ok(message) = read_connection.outgoing_rx.try_recv() {
+            assert_ne!(message.command, "PRIVMSG");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_credentials_keeps_sending_connection() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(read_credentials_config());
+        client.connect().await;
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let (nick, _) = recv_until_command(&mut connection, "NICK").await;
+        assert_eq!(nick.as_deref(), Some("randers"));
+
+        // the server closing the connection, it is replaced even though no channel is joined
+        drop(connection.incoming_tx);
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let (nick, _) = recv_until_command(&mut connection, "NICK").await;
+        assert_eq!(nick.as_deref(), Some("randers"));
+
+        let pool_status = client.pool_status().await;
+        assert_eq!(pool_status.len(), 1);
+        assert!(!pool_status[0].read_only);
+        assert!(pool_status[0].wanted_channels.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause() {
+        let config = ClientConfig {
+            paused_messages_capacity: 2,
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        let send = |source: &str| {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(source).unwrap()))
+                .unwrap();
+        };
+        async fn recv_source(incoming_messages: &mut IncomingMessages) -> String {
+            incoming_messages
+                .recv()
+                .await
+                .unwrap()
+                .source()
+                .as_raw_irc()
+        }
+
+        send("PING a");
+        assert_eq!(recv_source(&mut incoming_messages).await, "PING a");
+
+        // nothing is delivered while paused, and only the newest 2 messages are held back
+        client.pause();
+        send("PING b");
+        send("PING c");
+        send("PING d");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(incoming_messages.try_recv().is_err());
+
+        client.resume();
+        assert_eq!(recv_source(&mut incoming_messages).await, "PING c");
+        assert_eq!(recv_source(&mut incoming_messages).await, "PING d");
+
+        send("PING e");
+        assert_eq!(recv_source(&mut incoming_messages).await, "PING e");
+
+        // the connection stayed open the whole time
+        assert_eq!(client.pool_status().await.len(), 1);
+        assert!(incoming_messages.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_paused_messages_delivered_after_giving_up() {
+        let config = ClientConfig {
+            max_reconnect_attempts: Some(0),
+            paused_messages_capacity: 3,
+            incoming_messages_capacity: Some(1),
+            incoming_overflow_policy: OverflowPolicy::Block,
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        client.pause();
+        for token in &["1", "2", "3"] {
+            connection
+                .incoming_tx
+                .send(Ok(irc!["PING", *token]))
+                .unwrap();
+        }
+        // the connection failing makes the client give up right away
+        drop(connection.incoming_tx);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // the held back messages are delivered without overfilling the buffer
+        let mut buffer = vec![];
+        assert_eq!(incoming_messages.recv_many(&mut buffer, 10).await, 1);
+        for token in &["2", "3"] {
+            buffer.push(incoming_messages.recv().await.unwrap());
+            assert_eq!(buffer.last().unwrap().source().params[0], *token);
+        }
+        assert_eq!(buffer[0].source().params[0], "1");
+        assert!(incoming_messages.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        assert!(client.subscribe().is_none());
+
+        let config = ClientConfig {
+            subscription_capacity: Some(2),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        let mut subscription_a = client.subscribe().unwrap();
+        let mut subscription_b = client.clone().subscribe().unwrap();
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        // sends the messages, and waits until the client has delivered them
+        async fn send_messages(
+            connection: &MockConnection,
+            incoming_messages: &mut IncomingMessages,
+            sources: &[&str],
+        ) {
+            for source in sources {
+                connection
+                    .incoming_tx
+                    .send(Ok(IRCMessage::parse(source).unwrap()))
+                    .unwrap();
+            }
+            for _ in sources {
+                incoming_messages.recv().await.unwrap();
+            }
+        }
+        fn source(message: Result<ServerMessage, RecvError>) -> String {
+            message.unwrap().source().as_raw_irc()
+        }
+
+        // every subscriber gets all messages
+        send_messages(&connection, &mut incoming_messages, &["PING a", "PING b"]).await;
+        assert_eq!(source(subscription_a.recv().await), "PING a");
+        assert_eq!(source(subscription_a.recv().await), "PING b");
+        assert_eq!(source(subscription_b.recv().await), "PING a");
+        assert_eq!(source(subscription_b.recv().await), "PING b");
+
+        // a subscriber that falls behind skips the oldest messages, without affecting others
+        send_messages(&connection, &mut incoming_messages, &["PING c"]).await;
+        assert_eq!(source(subscription_b.recv().await), "PING c");
+        send_messages(&connection, &mut incoming_messages, &["PING d", "PING e"]).await;
+        assert_eq!(
+            subscription_a.recv().await.unwrap_err(),
+            RecvError::Lagged(1)
+        );
+        assert_eq!(source(subscription_a.recv().await), "PING d");
+        assert_eq!(source(subscription_a.recv().await), "PING e");
+        assert_eq!(source(subscription_b.recv().await), "PING d");
+        assert_eq!(source(subscription_b.recv().await), "PING e");
+
+        // new subscribers only get messages that arrive after subscribing
+        let mut subscription_c = client.subscribe().unwrap();
+        send_messages(&connection, &mut incoming_messages, &["PING f"]).await;
+        assert_eq!(source(subscription_c.recv().await), "PING f");
+
+        // subscriptions are closed once the client is closed
+        drop(client);
+        assert_eq!(source(subscription_a.recv().await), "PING f");
+        assert_eq!(subscription_a.recv().await.unwrap_err(), RecvError::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_commands() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        assert!(client.subscribe_commands(&[MessageKind::Privmsg]).is_none());
+
+        let config = ClientConfig {
+            subscription_capacity: Some(10),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        let mut privmsgs = client.subscribe_commands(&[MessageKind::Privmsg]).unwrap();
+        let mut states = client
+            .subscribe_commands(&[MessageKind::RoomState, MessageKind::UserState])
+            .unwrap();
+        // dropped subscribers are removed without affecting the others
+        drop(client.subscribe_commands(&[MessageKind::Privmsg]).unwrap());
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        let sources = [
+            "@room-id=11148817 :tmi.twitch.tv ROOMSTATE #pajlada",
+            ":a!a@a.tmi.twitch.tv PRIVMSG #pajlada :hello",
+            "@badge-info=;badges=;color=;display-name=TESTUSER;emote-sets=0;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE #pajlada",
+            "PING a",
+            ":b!b@b.tmi.twitch.tv PRIVMSG #pajlada :world",
+        ];
+        for source in &sources {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(source).unwrap()))
+                .unwrap();
+        }
+        for _ in &sources {
+            incoming_messages.recv().await.unwrap();
+        }
+        // closing the client closes the subscriptions after all messages were received
+        drop(client);
+
+        let mut received_privmsgs = Vec::new();
+        while let Ok(message) = privmsgs.recv().await {
+            match message {
+                ServerMessage::Privmsg(msg) => received_privmsgs.push(msg.message_text),
+                other => panic!("received {:?}", other),
+            }
+        }
+        assert_eq!(received_privmsgs, vec!["hello", "world"]);
+
+        let mut received_states = Vec::new();
+        while let Ok(message) = states.recv().await {
+            received_states.push(message.kind());
+        }
+        assert_eq!(
+            received_states,
+            vec![MessageKind::RoomState, MessageKind::UserState]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_reconnect_attempts() {
+        set_connect_failing(true);
+        let config = ClientConfig {
+            max_reconnect_attempts: Some(2),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+
+        // the first connection and both replacements fail, then the client gives up
+        assert!(incoming_messages.recv().await.is_none());
+        let close_reasons = client.recent_close_reasons().await;
+        assert_eq!(close_reasons.len(), 3);
+        assert!(close_reasons
+            .iter()
+            .all(|(_, cause)| matches!(cause, Error::ConnectError(_))));
+        assert_eq!(client.pool_status().await, vec![]);
+
+        match client.say("pajlada".to_owned(), "test".to_owned()).await {
+            Err(Error::GaveUpReconnecting(cause)) => {
+                assert!(matches!(*cause, Error::ConnectError(_)))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // no new connections are made after giving up
+        client.join("forsen".to_owned());
+        client.connect().await;
+        assert_eq!(client.pool_status().await, vec![]);
+        assert_eq!(client.recent_close_reasons().await.len(), 3);
+
+        set_connect_failing(false);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_backoff() {
+        set_connect_failing(true);
+        let config = ClientConfig {
+            reconnect_backoff: Duration::from_secs(1),
+            max_reconnect_backoff: Duration::from_secs(4),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        assert_eq!(client.reconnect_backoff().await, None);
+        client.connect().await;
+
+        // (consecutive failures, delay, when the failure was noticed)
+        // the first failed connection is replaced right away, so the replacement might
+        // fail before the first failure can be observed
+        let mut failures = vec![];
+        while !matches!(failures.last(), Some((5, _, _))) {
+            if let Some(backoff) = client.reconnect_backoff().await {
+                if failures.last().map(|(count, _, _)| *count) != Some(backoff.consecutive_failures)
+                {
+                    failures.push((
+                        backoff.consecutive_failures,
+                        backoff.delay,
+                        tokio::time::Instant::now(),
+                    ));
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        failures.retain(|(count, _, _)| *count >= 2);
+
+        assert_eq!(
+            failures
+                .iter()
+                .map(|(count, delay, _)| (*count, delay.as_secs()))
+                .collect::<Vec<_>>(),
+            vec![(2, 1), (3, 2), (4, 4), (5, 4)]
+        );
+        // the replacement connection actually waited for the delay before failing again
+        for pair in failures.windows(2) {
+            let (_, delay, failed_at) = pair[0];
+            let (_, _, next_failed_at) = pair[1];
+            assert!(next_failed_at - failed_at >= delay);
+        }
+
+        // reset once a connection is opened successfully
+        set_connect_failing(false);
+        wait_for_connections(1).await;
+        assert_eq!(client.reconnect_backoff().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_incoming_messages_drop_oldest() {
+        let config = ClientConfig {
+            incoming_messages_capacity: Some(2),
+            incoming_overflow_policy: OverflowPolicy::DropOldest,
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        for token in &["1", "2", "3", "4", "5"] {
+            connection
+                .incoming_tx
+                .send(Ok(irc!["PING", *token]))
+                .unwrap();
+        }
+        // the receiver is stalled until all messages have been processed by the client
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let mut buffer = vec![];
+        assert_eq!(incoming_messages.recv_many(&mut buffer, 10).await, 2);
+        let tokens: Vec<&str> = buffer
+            .iter()
+            .map(|message| message.source().params[0].as_str())
+            .collect();
+        assert_eq!(tokens, vec!["4", "5"]);
+    }
+
+    async fn send_membership_messages(
+        membership: MembershipMode,
+    ) -> (
+        IncomingMessages,
+        TwitchIRCClient<MockTransport, StaticLoginCredentials>,
+        MockConnection,
+    ) {
+        let config = ClientConfig {
+            membership,
+            ..ClientConfig::default()
+        };
+        let (incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        assert_eq!(
+            connection.outgoing_rx.recv().await.unwrap(),
+            irc![
+                "CAP",
+                "REQ",
+                "twitch.tv/tags twitch.tv/commands twitch.tv/membership"
+            ]
+        );
+        for message in &[
+            ":justinfan12345!justinfan12345@justinfan12345.tmi.twitch.tv JOIN #pajlada",
+            ":randers!randers@randers.tmi.twitch.tv JOIN #pajlada",
+            ":randers!randers@randers.tmi.twitch.tv PART #pajlada",
+            "PING :tmi.twitch.tv",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(message).unwrap()))
+                .unwrap();
+        }
+        (incoming_messages, client, connection)
+    }
+
+    #[tokio::test]
+    async fn test_membership_own_only() {
+        let (mut incoming_messages, client, _connection) =
+            send_membership_messages(MembershipMode::OwnOnly).await;
+
+        let mut received = vec![];
+        loop {
+            match incoming_messages.recv().await.unwrap() {
+                ServerMessage::Ping(_) => break,
+                ServerMessage::Join(join) => received.push(join.user_login),
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+        assert_eq!(received, vec!["justinfan12345"]);
+        assert_eq!(
+            client.get_channel_status("pajlada".to_owned()).await,
+            (true, true)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_membership_own_only_reconnect_grace_period() {
+        let config = ClientConfig {
+            membership: MembershipMode::OwnOnly,
+            reconnect_grace_period: Duration::from_secs(1),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        for message in &[
+            ":tmi.twitch.tv RECONNECT",
+            ":randers!randers@randers.tmi.twitch.tv JOIN #pajlada",
+            ":randers!randers@randers.tmi.twitch.tv PART #pajlada",
+            "PING :tmi.twitch.tv",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(message).unwrap()))
+                .unwrap();
+        }
+
+        // the connection is no longer in the pool, but the JOIN and PART of the other
+        // user are still filtered out
+        assert!(matches!(
+            incoming_messages.recv().await.unwrap(),
+            ServerMessage::Reconnect(_)
+        ));
+        match incoming_messages.recv().await.unwrap() {
+            ServerMessage::Ping(_) => {}
+            message => panic!("unexpected message: {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_membership_all() {
+        let (mut incoming_messages, client, _connection) =
+            send_membership_messages(MembershipMode::All).await;
+
+        let mut received = vec![];
+        loop {
+            match incoming_messages.recv().await.unwrap() {
+                ServerMessage::Ping(_) => break,
+                message => received.push(message.source().as_raw_irc()),
+            }
+        }
+        assert_eq!(received.len(), 3);
+        // the PART of the other user does not affect the state of our own channel
+        assert_eq!(
+            client.get_channel_status("pajlada".to_owned()).await,
+            (true, true)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queued_messages_flushed_on_close() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+
+        // queue up the message (this happens on the first poll), then close the client
+        // right away without waiting for the message to be sent
+        let send = client.privmsg("pajlada".to_owned(), "bye".to_owned());
+        assert!(send.now_or_never().is_none());
+        drop(client);
+
+        let mut sent = vec![];
+        while let Some(message) = connection.outgoing_rx.recv().await {
+            sent.push(message.command);
+        }
+        // the connection was closed after sending the message
+        assert_eq!(sent.last().unwrap(), "PRIVMSG");
+    }
+
+    /// Receives messages sent over the connection until a `PRIVMSG` arrives, and returns its
+    /// text.
+    async fn next_privmsg_text(connection: &mut MockConnection) -> String {
+        loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PRIVMSG" {
+                return message.params[1].clone();
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_rate_limit() {
+        let mut config = ClientConfig {
+            max_waiting_messages_per_connection: 2,
+            time_per_message: Duration::from_secs(1),
+            ..ClientConfig::default()
+        };
+        config.set_connection_rate_limit(10, Duration::from_secs(2));
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let mut first_connection = wait_for_connections(1).await.pop().unwrap();
+
+        // the third message exceeds the limit of waiting messages on the first connection
+        for text in &["a", "b", "c"] {
+            client
+                .privmsg("pajlada".to_owned(), (*text).to_owned())
+                .now_or_never();
+        }
+        let mut second_connection = wait_for_connections(1).await.pop().unwrap();
+        assert_eq!(next_privmsg_text(&mut first_connection).await, "a");
+        assert_eq!(next_privmsg_text(&mut first_connection).await, "b");
+        assert_eq!(next_privmsg_text(&mut second_connection).await, "c");
+
+        // once the server has processed the messages, the first connection is used again
+        tokio::time::advance(Duration::from_secs(2)).await;
+        client
+            .privmsg("pajlada".to_owned(), "d".to_owned())
+            .now_or_never();
+        assert_eq!(next_privmsg_text(&mut first_connection).await, "d");
+    }
+
+    #[tokio::test]
+    async fn test_send_all_ordered() {
+        let config = ClientConfig {
+            // keeps all messages on the same connection
+            max_waiting_messages_per_connection: 100,
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        let batch = |name: &str| {
+            (1..=3)
+                .map(|i| irc!["PRIVMSG", "#pajlada", format!("{} {}", name, i)])
+                .collect::<Vec<_>>()
+        };
+
+        let (a, b, c) = tokio::join!(
+            client.send_all_ordered(batch("a")),
+            client.privmsg("pajlada".to_owned(), "single".to_owned()),
+            client.send_all_ordered(batch("b")),
+        );
+        a.unwrap();
+        b.unwrap();
+        c.unwrap();
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let mut sent = vec![];
+        while sent.len() < 7 {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PRIVMSG" {
+                sent.push(message.params[1].clone());
+            }
+        }
+        for name in ["a", "b"].iter() {
+            let start = sent.iter().position(|text| text.starts_with(name)).unwrap();
+            assert_eq!(
+                sent[start..start + 3],
+                batch(name)
+                    .into_iter()
+                    .map(|message| message.params[1].clone())
+                    .collect::<Vec<_>>()[..]
+            );
+        }
+
+        assert!(client.send_all_ordered(vec![]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_send_on_connection() {
+        let config = ClientConfig {
+            max_channels_per_connection: 1,
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        client.join("forsen".to_owned());
+        let mut connections = wait_for_connections(2).await;
+
+        client
+            .send_on_connection(1, irc!["PING", "diagnostics"])
+            .await
+            .unwrap();
+        let mut sent = vec![];
+        while sent.last() != Some(&irc!["PING", "diagnostics"]) {
+            sent.push(connections[1].outgoing_rx.recv().await.unwrap());
+        }
+        // the message was not routed to the other connection
+        while let Ok(message) = connections[0].outgoing_rx.try_recv() {
+            assert_ne!(message, irc!["PING", "diagnostics"]);
+        }
+
+        assert!(matches!(
+            client
+                .send_on_connection(42, irc!["PING", "diagnostics"])
+                .await,
+            Err(Error::ConnectionNotFound(42))
+        ));
+    }
+
+    /// Connects a client and breaks its connection's outgoing side once the handshake is sent,
+    /// so the next message sent over it fails.
+    async fn connect_with_broken_outgoing() -> (
+        IncomingMessages,
+        TwitchIRCClient<MockTransport, StaticLoginCredentials>,
+        MockConnection,
+    ) {
+        let (incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        client.connect().await;
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        // CAP REQ and NICK
+        connection.outgoing_rx.recv().await.unwrap();
+        connection.outgoing_rx.recv().await.unwrap();
+        connection.outgoing_rx.close();
+        (incoming_messages, client, connection)
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry() {
+        let (_incoming_messages, client, _connection) = connect_with_broken_outgoing().await;
+
+        client
+            .send_with_retry(irc!["PING", "diagnostics"], 1)
+            .await
+            .unwrap();
+        // the message was sent again over the replacement connection
+        let mut replacement = wait_for_connections(1).await.pop().unwrap();
+        let mut sent = vec![];
+        while sent.last() != Some(&irc!["PING", "diagnostics"]) {
+            sent.push(replacement.outgoing_rx.recv().await.unwrap());
+        }
+        assert!(matches!(
+            client.recent_close_reasons().await[..],
+            [(_, Error::OutgoingError(_))]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_exhausted() {
+        let (_incoming_messages, client, _connection) = connect_with_broken_outgoing().await;
+
+        assert!(matches!(
+            client.send_with_retry(irc!["PING", "diagnostics"], 0).await,
+            Err(Error::OutgoingError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reply() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        let reply_context = ReplyContext {
+            channel_login: "pajlada".to_owned(),
+            message_id: "e9d998c3-36f1-430f-89ec-6b887c28af36".to_owned(),
+            sender_login: "jun1orrrr".to_owned(),
+        };
+        client
+            .reply("hi there".to_owned(), &reply_context)
+            .await
+            .unwrap();
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let privmsg = loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PRIVMSG" {
+                break message;
+            }
+        };
+        assert_eq!(
+            privmsg.as_raw_irc(),
+            "@reply-parent-msg-id=e9d998c3-36f1-430f-89ec-6b887c28af36 PRIVMSG #pajlada :. hi there"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reply_to_message_without_tags() {
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(ClientConfig::default());
+        let message = PrivmsgMessage::try_from(
+            IRCMessage::parse(":randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa")
+                .unwrap(),
+        )
+        .unwrap();
+        client
+            .reply("hi there".to_owned(), &ReplyContext::from(&message))
+            .await
+            .unwrap();
+
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        let privmsg = loop {
+            let message = connection.outgoing_rx.recv().await.unwrap();
+            if message.command == "PRIVMSG" {
+                break message;
+            }
+        };
+        assert_eq!(privmsg.as_raw_irc(), "PRIVMSG #pajlada :. hi there");
+    }
+
+    #[tokio::test]
+    async fn test_send_failure_hook() {
+        let failures = Arc::new(Mutex::new(vec![]));
+        let failures_clone = Arc::clone(&failures);
+        let config = ClientConfig {
+            on_send_failure: Some(SendFailureHook::new(move |failure| {
+                failures_clone.lock().unwrap().push(failure);
+            })),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+
+        client
+            .privmsg("pajlada".to_owned(), "first message".to_owned())
+            .await
+            .unwrap();
+        client
+            .privmsg("forsen".to_owned(), "other channel".to_owned())
+            .await
+            .unwrap();
+        client
+            .privmsg("pajlada".to_owned(), "second message".to_owned())
+            .await
+            .unwrap();
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        for notice in &[
+            "@msg-id=msg_emoteonly :tmi.twitch.tv NOTICE #pajlada :This room is in emote-only mode.",
+            // not a send failure
+            "@msg-id=slow_on :tmi.twitch.tv NOTICE #pajlada :This room is now in slow mode. You may send messages every 5 seconds.",
+            "@msg-id=msg_slowmode :tmi.twitch.tv NOTICE #pajlada :This room is in slow mode and you are sending messages too quickly. You will be able to talk again in 5 seconds.",
+            // nothing was sent to this channel
+            "@msg-id=msg_subsonly :tmi.twitch.tv NOTICE #randers :This room is in subscribers-only mode.",
+        ] {
+            connection
+                .incoming_tx
+                .send(Ok(IRCMessage::parse(notice).unwrap()))
+                .unwrap();
+        }
+        // all notices are still delivered normally
+        for _ in 0..4 {
+            assert!(matches!(
+                incoming_messages.recv().await.unwrap(),
+                ServerMessage::Notice(_)
+            ));
+        }
+
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].channel_login, "pajlada");
+        assert_eq!(failures[0].message_text, "first message");
+        assert_eq!(failures[0].reason, SendFailureReason::EmoteOnly);
+        assert_eq!(failures[1].message_text, "second message");
+        assert_eq!(failures[1].reason, SendFailureReason::SlowMode);
+    }
+
+    /// Sends a line that is not valid IRC, followed by a valid message, to a client
+    /// with the given `close_on_parse_error` setting.
+    async fn send_garbage_line(
+        close_on_parse_error: bool,
+    ) -> (
+        TwitchIRCClient<MockTransport, StaticLoginCredentials>,
+        IncomingMessages,
+        MockConnection,
+    ) {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            close_on_parse_error,
+            ..ClientConfig::default()
+        };
+        let (incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Err(itertools::Either::Right(
+                IRCMessage::parse("@ :tmi.twitch.tv PING").unwrap_err(),
+            )))
+            .unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse(":tmi.twitch.tv PING").unwrap()))
+            .unwrap();
+        (client, incoming_messages, connection)
+    }
+
+    #[tokio::test]
+    async fn test_parse_error_skipped() {
+        let (client, mut incoming_messages, _connection) = send_garbage_line(false).await;
+
+        // the garbage line is skipped, the connection keeps working
+        assert!(matches!(
+            incoming_messages.recv().await.unwrap(),
+            ServerMessage::Ping(_)
+        ));
+        assert!(client.recent_close_reasons().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_raw_line_hook() {
+        let raw_lines = Arc::new(Mutex::new(vec![]));
+        let raw_lines_clone = Arc::clone(&raw_lines);
+        let config = ClientConfig {
+            on_raw_line: Some(RawLineHook::new(move |line| {
+                raw_lines_clone.lock().unwrap().push(line.to_owned())
+            })),
+            // the hook is then combined with recording the message size metric
+            #[cfg(feature = "metrics-collection")]
+            metrics_identifier: Some("test".into()),
+            ..ClientConfig::default()
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let connection = wait_for_connections(1).await.pop().unwrap();
+
+        for line in &["@ PING", "", "PING :tmi.twitch.tv"] {
+            connection.raw_incoming_tx.send(line.to_string()).unwrap();
+        }
+
+        // the malformed and the empty line are dropped, but still observed by the hook
+        // (before the valid message is delivered)
+        assert!(matches!(
+            incoming_messages.recv().await.unwrap(),
+            ServerMessage::Ping(_)
+        ));
+        assert_eq!(
+            *raw_lines.lock().unwrap(),
+            vec!["@ PING", "", "PING :tmi.twitch.tv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_outgoing_message_hook() {
+        let outgoing_messages = Arc::new(Mutex::new(vec![]));
+        let outgoing_messages_clone = Arc::clone(&outgoing_messages);
+        let config = ClientConfig {
+            on_outgoing_message: Some(OutgoingMessageHook::new(move |message| {
+                outgoing_messages_clone
+                    .lock()
+                    .unwrap()
+                    .push(message.clone())
+            })),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+        let mut connection = wait_for_connections(1).await.pop().unwrap();
+        connection
+            .incoming_tx
+            .send(Ok(IRCMessage::parse("PING :tmi.twitch.tv").unwrap()))
+            .unwrap();
+
+        // the handshake and the automatic PONG are observed, in the order they are sent
+        let mut sent = vec![];
+        while sent.len() < 3 {
+            sent.push(connection.outgoing_rx.recv().await.unwrap());
+        }
+        assert_eq!(sent.last().unwrap().command, "PONG");
+        assert_eq!(*outgoing_messages.lock().unwrap(), sent);
+    }
+
+    #[tokio::test]
+    async fn test_parse_error_closes_connection() {
+        let (client, _incoming_messages, _connection) = send_garbage_line(true).await;
+
+        // the connection is replaced
+        let _second_connection = wait_for_connections(1).await;
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(
+            recent_close_reasons[0].1,
+            Error::IRCParseError(IRCParseError::EmptyTagsDeclaration)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_force_reconnect_rejoins_channels() {
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.join("pajlada".to_owned());
+        // keep the server side of this connection alive, dropping it would close the connection
+        let _first_connection = wait_for_connections(1).await;
+
+        assert!(client.force_reconnect(0).await);
+        assert!(!client.force_reconnect(42).await);
+
+        let mut connections = wait_for_connections(1).await;
+        let outgoing_rx = &mut connections[0].outgoing_rx;
+        assert_eq!(
+            outgoing_rx.recv().await.unwrap(),
+            irc!["CAP", "REQ", "twitch.tv/tags twitch.tv/commands"]
+        );
+        assert_eq!(
+            outgoing_rx.recv().await.unwrap(),
+            irc!["NICK", "justinfan12345"]
+        );
+        assert_eq!(outgoing_rx.recv().await.unwrap(), irc!["JOIN", "#pajlada"]);
+
+        let recent_close_reasons = client.recent_close_reasons().await;
+        assert_eq!(recent_close_reasons.len(), 1);
+        assert!(matches!(recent_close_reasons[0].1, Error::ForcedReconnect));
+        assert_eq!(
+            client.get_channel_status("pajlada".to_owned()).await,
+            (true, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_hook() {
+        let hook_calls = Arc::new(Mutex::new(vec![]));
+        let hook_calls_clone = Arc::clone(&hook_calls);
+        let config = ClientConfig {
+            new_connection_every: Duration::from_millis(10),
+            on_reconnect: Some(ReconnectHook::new(move |close_reason, reconnect_count| {
+                let is_incoming_error = matches!(
+                    close_reason.downcast_ref::<Error<MockTransport, StaticLoginCredentials>>(),
+                    Some(Error::IncomingError(_))
+                );
+                hook_calls_clone
+                    .lock()
+                    .unwrap()
+                    .push((is_incoming_error, reconnect_count));
+                // a slow hook delays the reconnect
+                tokio::time::sleep(Duration::from_millis(200))
+            })),
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+        client.connect().await;
+
+        let first_connection = wait_for_connections(1).await.pop().unwrap();
+        // the hook is not called for the initial connection
+        assert!(hook_calls.lock().unwrap().is_empty());
+
+        first_connection
+            .incoming_tx
+            .send(Err(itertools::Either::Left(MockTransportError)))
+            .unwrap();
+        let closed_at = tokio::time::Instant::now();
+
+        let second_connection = wait_for_connections(1).await.pop().unwrap();
+        assert_eq!(*hook_calls.lock().unwrap(), vec![(true, 1)]);
+        assert!(second_connection.connected_at - closed_at >= Duration::from_millis(200));
+    }
 }
@@ -4,20 +4,66 @@ use crate::login::LoginCredentials;
 use crate::transport::Transport;
 use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::Duration;
 use tokio::sync::oneshot;
+use tokio::time::Instant;
 
-/// The actual state of the connection loop is held only by the connection loop.
-/// However the connection sends out messages indicating that it has changed its state.
-/// This enum tracks that "reported state" as received via messages from the connection.
+/// State of a pool connection, as reported in `ConnectionStatus`.
 ///
-/// (The only use of this is to be able to provide metrics counting channels on a per-state basis)
-#[cfg(feature = "metrics-collection")]
-pub(crate) enum ReportedConnectionState {
+/// There is no "closed" state: Closed connections are removed from the pool
+/// right away (and replaced if needed), so they are never part of the pool status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection is being established: connecting, logging in and so on.
     Initializing,
+    /// The connection is ready and can be used to send and receive messages.
     Open,
 }
 
+/// A snapshot of a single connection in the connection pool of a `TwitchIRCClient`,
+/// see `TwitchIRCClient::pool_status()`.
+///
+/// This struct is marked `#[non_exhaustive]`, so more details about the connections can be
+/// added in the future.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionStatus {
+    /// ID of this connection within the pool. Connections are numbered sequentially starting
+    /// at 0, the same IDs are used by `TwitchIRCClient::force_reconnect()` and in the log output.
+    pub id: usize,
+    /// Whether this connection is still initializing or already open.
+    pub state: ConnectionState,
+    /// `true` if this connection is logged in with `ClientConfig::read_credentials` and is
+    /// therefore only used to receive messages, never to send them.
+    pub read_only: bool,
+    /// The channels that have been assigned to this connection (see the documentation
+    /// on `TwitchIRCClient` for the difference to `server_channels`).
+    pub wanted_channels: HashSet<String>,
+    /// The channels this connection has actually been confirmed to be joined to
+    /// by the server.
+    pub server_channels: HashSet<String>,
+    /// The login name this connection is logged in as, as acknowledged by the server in its
+    /// `001` welcome message (e.g. `justinfan12345` for anonymous connections). Before the
+    /// welcome message is received, this is the login name sent to the server, and `None`
+    /// while the connection is still initializing.
+    pub user_login: Option<String>,
+}
+
+/// The delay the client waits before replacing a failed connection while connections keep
+/// failing, see `TwitchIRCClient::reconnect_backoff()` and `ClientConfig::reconnect_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectBackoff {
+    /// How many connections have failed in a row, without any connection being opened
+    /// successfully in between.
+    pub consecutive_failures: u32,
+    /// How long the replacement for the last failed connection waits before connecting.
+    /// This is zero after the first failure, since that connection is replaced right away.
+    pub delay: Duration,
+    /// When the replacement for the last failed connection starts to connect, i.e. when the
+    /// delay is over. This might already be in the past.
+    pub next_attempt_at: std::time::Instant,
+}
+
 pub(crate) struct PoolConnection<T: Transport, L: LoginCredentials> {
     config: Arc<ClientConfig<L>>,
     /// uniquely identifies this pool connection within its parent pool. This ID is assigned
@@ -29,6 +75,9 @@ pub(crate) struct PoolConnection<T: Transport, L: LoginCredentials> {
     pub id: usize,
     /// The connection handle that this is wrapping
     pub connection: Arc<Connection<T, L>>,
+    /// `true` if this connection is logged in with `config.read_credentials` and is therefore
+    /// only used to join channels, never to send messages.
+    pub read_only: bool,
     /// see the documentation on `TwitchIRCClient` for what `wanted_channels` and `server_channels` mean
     pub wanted_channels: HashSet<String>,
     /// see the documentation on `TwitchIRCClient` for what `wanted_channels` and `server_channels` mean
@@ -38,11 +87,12 @@ pub(crate) struct PoolConnection<T: Transport, L: LoginCredentials> {
     pub message_send_times: VecDeque<Instant>,
     /// The actual state of the connection loop is held only by the connection loop.
     /// However the connection sends out messages indicating that it has changed its state.
-    /// This enum tracks that "reported state" as received via messages from the connection.
-    ///
-    /// (The only use of this is to be able to provide metrics counting channels on a per-state basis)
-    #[cfg(feature = "metrics-collection")]
-    pub reported_state: ReportedConnectionState,
+    /// This tracks that "reported state" as received via messages from the connection.
+    pub reported_state: ConnectionState,
+    /// The login name this connection is logged in as. Only known once the connection is open,
+    /// and replaced with the login name acknowledged by the server once the `001` welcome
+    /// message is received.
+    pub user_login: Option<String>,
 
     // this is option-wrapped so it can be .take()n in the Drop implementation
     tx_kill_incoming: Option<oneshot::Sender<()>>,
@@ -53,6 +103,7 @@ impl<T: Transport, L: LoginCredentials> PoolConnection<T, L> {
         config: Arc<ClientConfig<L>>,
         id: usize,
         connection: Connection<T, L>,
+        read_only: bool,
         tx_kill_incoming: oneshot::Sender<()>,
     ) -> PoolConnection<T, L> {
         // this is just an optimization to initialize the VecDeque to its final size right away
@@ -61,15 +112,27 @@ impl<T: Transport, L: LoginCredentials> PoolConnection<T, L> {
             config,
             id,
             connection: Arc::new(connection),
+            read_only,
             wanted_channels: HashSet::new(),
             server_channels: HashSet::new(),
             message_send_times: VecDeque::with_capacity(message_send_times_max_entries),
-            #[cfg(feature = "metrics-collection")]
-            reported_state: ReportedConnectionState::Initializing,
+            reported_state: ConnectionState::Initializing,
+            user_login: None,
             tx_kill_incoming: Some(tx_kill_incoming),
         }
     }
 
+    pub fn status(&self) -> ConnectionStatus {
+        ConnectionStatus {
+            id: self.id,
+            state: self.reported_state,
+            read_only: self.read_only,
+            wanted_channels: self.wanted_channels.clone(),
+            server_channels: self.server_channels.clone(),
+            user_login: self.user_login.clone(),
+        }
+    }
+
     pub fn register_sent_message(&mut self) {
         let max_entries = self.config.max_waiting_messages_per_connection * 2;
 
@@ -1,17 +1,236 @@
-use crate::login::{LoginCredentials, StaticLoginCredentials};
-#[cfg(feature = "metrics-collection")]
+use crate::login::{
+    CredentialsPair, LoginCredentials, StaticLoginCredentials, TWITCH_TOKEN_PREFIX,
+};
+use crate::message::{IRCMessage, NoticeMessage, SendFailureReason};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
 use std::borrow::Cow;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Semaphore;
 
+/// Async function that is called right before the client opens a replacement connection for a
+/// connection that failed. See `ClientConfig::on_reconnect`.
+///
+/// The function receives the reason the previous connection was closed, and how many
+/// connections of this client have failed so far (including this one, so starting at 1).
+/// The close reason is a `twitch_irc::Error<T, L>` (with the transport and login credentials
+/// types of the client), and can be downcast to it if needed.
+#[derive(Clone)]
+pub struct ReconnectHook(pub(crate) Arc<ReconnectHookFn>);
+
+type ReconnectHookFn =
+    dyn Fn(Arc<dyn std::error::Error + Send + Sync>, u64) -> BoxFuture<'static, ()> + Send + Sync;
+
+impl ReconnectHook {
+    /// Create a new hook from the given async function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::ReconnectHook;
+    ///
+    /// let hook = ReconnectHook::new(|close_reason, reconnect_count| async move {
+    ///     println!("Reconnecting (#{}) after: {}", reconnect_count, close_reason);
+    /// });
+    /// ```
+    pub fn new<F, Fut>(hook: F) -> ReconnectHook
+    where
+        F: Fn(Arc<dyn std::error::Error + Send + Sync>, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        ReconnectHook(Arc::new(move |close_reason, reconnect_count| {
+            hook(close_reason, reconnect_count).boxed()
+        }))
+    }
+}
+
+impl std::fmt::Debug for ReconnectHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectHook").finish()
+    }
+}
+
+/// A chat message that Twitch refused to deliver, see `ClientConfig::on_send_failure`.
+#[derive(Debug, Clone)]
+pub struct SendFailure {
+    /// Login name of the channel the message was sent to.
+    pub channel_login: String,
+    /// Text of the message that (most likely) failed to be delivered, exactly as it was sent.
+    pub message_text: String,
+    /// Why the message was not delivered.
+    pub reason: SendFailureReason,
+    /// The `NOTICE` Twitch sent in response to the message.
+    pub notice: NoticeMessage,
+}
+
+/// Function that is called when a message sent by the client was refused by Twitch.
+/// See `ClientConfig::on_send_failure`.
+#[derive(Clone)]
+pub struct SendFailureHook(pub(crate) Arc<dyn Fn(SendFailure) + Send + Sync>);
+
+impl SendFailureHook {
+    /// Create a new hook from the given function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::SendFailureHook;
+    ///
+    /// let hook = SendFailureHook::new(|failure| {
+    ///     println!(
+    ///         "Message {:?} in #{} was not delivered: {:?}",
+    ///         failure.message_text, failure.channel_login, failure.reason
+    ///     );
+    /// });
+    /// ```
+    pub fn new<F>(hook: F) -> SendFailureHook
+    where
+        F: Fn(SendFailure) + Send + Sync + 'static,
+    {
+        SendFailureHook(Arc::new(hook))
+    }
+}
+
+impl std::fmt::Debug for SendFailureHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendFailureHook").finish()
+    }
+}
+
+/// Function that observes every raw line received from Twitch, see `ClientConfig::on_raw_line`.
+#[derive(Clone)]
+pub struct RawLineHook(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl RawLineHook {
+    /// Create a new hook from the given function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::RawLineHook;
+    ///
+    /// let hook = RawLineHook::new(|line| println!("< {}", line));
+    /// ```
+    pub fn new<F>(hook: F) -> RawLineHook
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        RawLineHook(Arc::new(hook))
+    }
+
+    /// Call the hook with the given line. This is used by `Transport` implementations,
+    /// see `TransportOptions::raw_line_hook`.
+    pub fn observe(&self, line: &str) {
+        (self.0)(line)
+    }
+}
+
+impl std::fmt::Debug for RawLineHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawLineHook").finish()
+    }
+}
+
+/// Function that observes every message sent to Twitch, see `ClientConfig::on_outgoing_message`.
+#[derive(Clone)]
+pub struct OutgoingMessageHook(Arc<dyn Fn(&IRCMessage) + Send + Sync>);
+
+impl OutgoingMessageHook {
+    /// Create a new hook from the given function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::AsRawIRC;
+    /// use twitch_irc::OutgoingMessageHook;
+    ///
+    /// let hook = OutgoingMessageHook::new(|message| println!("> {}", message.as_raw_irc()));
+    /// ```
+    pub fn new<F>(hook: F) -> OutgoingMessageHook
+    where
+        F: Fn(&IRCMessage) + Send + Sync + 'static,
+    {
+        OutgoingMessageHook(Arc::new(hook))
+    }
+
+    pub(crate) fn observe(&self, message: &IRCMessage) {
+        (self.0)(message)
+    }
+}
+
+impl std::fmt::Debug for OutgoingMessageHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutgoingMessageHook").finish()
+    }
+}
+
+/// Whether the `twitch.tv/membership` capability is requested, and which of the resulting
+/// `JOIN` and `PART` messages are delivered. See `ClientConfig::membership`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipMode {
+    /// The capability is not requested. Only the `JOIN`s and `PART`s of the logged in user
+    /// are received.
+    Disabled,
+    /// The capability is requested (e.g. to receive the list of chatters after joining a
+    /// channel), but `JOIN`s and `PART`s of other users are dropped by the client. Only the
+    /// `JOIN`s and `PART`s of the logged in user are delivered.
+    OwnOnly,
+    /// The capability is requested, and the `JOIN`s and `PART`s of all users are delivered.
+    /// In large channels, this can be a lot of messages.
+    All,
+}
+
+/// What happens when a message is received while the buffer of incoming messages is full.
+/// See `ClientConfig::incoming_messages_capacity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The oldest buffered message is dropped to make room for the new message.
+    DropOldest,
+    /// The new message is dropped.
+    DropNewest,
+    /// The client stops processing until the buffer is no longer full. No messages are dropped,
+    /// but everything else the client does (e.g. joining channels, sending messages) is held up
+    /// too, and messages are buffered on the connections instead.
+    Block,
+}
+
+/// Which IP versions are used to connect to Twitch. See `ClientConfig::address_family`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressFamily {
+    /// Both IPv4 and IPv6 addresses are used.
+    #[default]
+    Any,
+    /// Only IPv4 addresses are used.
+    IPv4Only,
+    /// Only IPv6 addresses are used.
+    IPv6Only,
+}
+
 /// Configures settings for a `TwitchIRCClient`.
+///
+/// All timing of the client (rate limits, timeouts, reconnect backoff, idle detection and so
+/// on) is based on tokio's clock. Tests of time-dependent behaviour can therefore pause and
+/// advance time deterministically using tokio's `test-util` feature, e.g. with
+/// `#[tokio::test(start_paused = true)]` and `tokio::time::advance()`.
 #[derive(Debug)]
 pub struct ClientConfig<L: LoginCredentials> {
     /// Gets a set of credentials every time the client needs to log in on a new connection.
     /// See [`LoginCredentials`](../login/trait.LoginCredentials.html) for details.
     pub login_credentials: L,
 
+    /// If set, reading and sending are split onto separate connections: Connections that
+    /// only join channels (to read messages from them) log in with these credentials,
+    /// while messages are only sent over connections logged in with `login_credentials`.
+    /// Connections used for sending do not join any channels in this mode.
+    ///
+    /// This is typically set to `Some(StaticLoginCredentials::anonymous().credentials)`,
+    /// so that join-heavy bots can read anonymously but still send messages as their
+    /// authenticated user. Default value: `None` (all connections use `login_credentials`
+    /// for both reading and sending).
+    pub read_credentials: Option<CredentialsPair>,
+
     /// A new connection will automatically be created if a channel is joined and all
     /// currently established connections have joined at least this many channels.
     pub max_channels_per_connection: usize,
@@ -30,6 +249,11 @@ pub struct ClientConfig<L: LoginCredentials> {
     /// only, which means connections cannot be opened in parallel. If this is set to more than 1
     /// permit, then that many connections can be opened in parallel.
     ///
+    /// Every connection attempt takes one permit, and holds it until `new_connection_every`
+    /// has elapsed after the connection was established (or until the attempt failed). So with
+    /// `N` permits, at most `N` connections are opened per `new_connection_every`.
+    /// Use `set_connection_rate_limit()` to configure both values at once.
+    ///
     /// This is designed to be wrapped in an Arc to allow it to be shared between multiple
     /// TwitchIRCClient instances.
     pub connection_rate_limiter: Arc<Semaphore>,
@@ -42,12 +266,265 @@ pub struct ClientConfig<L: LoginCredentials> {
     /// back after this period has elapsed.
     pub new_connection_every: Duration,
 
+    /// How many idle connections (connections not joined to any channel) the client keeps open
+    /// ahead of time, so that joining a channel or sending a message can use an already
+    /// established connection instead of waiting for a new one to connect. These connections
+    /// are made as soon as the client is created, and each time one of them starts being used
+    /// (e.g. for joining a channel), a new one is made to take its place.
+    ///
+    /// If `read_credentials` is set, these are read-only connections used for joining channels.
+    ///
+    /// Default value: 0 (connections are only made when they are needed)
+    pub warm_connections: usize,
+
+    /// If set, this identifier is prepended to the log messages of this client, e.g.
+    /// `[my-bot] ...` for the client itself and `[my-bot, connection 3] ...` for the messages
+    /// logged by its connections. This helps to tell apart the log output of different
+    /// clients running in the same process.
+    ///
+    /// The log messages of connections always carry the ID of the connection, even if this
+    /// is not set (e.g. `[connection 3] ...`).
+    ///
+    /// Default value: `None`
+    pub log_identifier: Option<Cow<'static, str>>,
+
     /// Imposes a general timeout for new connections. This is in place in addition to possible
     /// operating system timeouts (E.g. for new TCP connections), since additional "connect" work
     /// takes place after the TCP connection is opened, e.g. to set up TLS or perform a WebSocket
     /// handshake. Default value: 20 seconds.
     pub connect_timeout: Duration,
 
+    /// Which IP versions may be used to connect to Twitch. With `AddressFamily::Any`, all
+    /// addresses of the server are tried, alternating between IPv6 and IPv4, with each attempt
+    /// getting a short head start before the next one is started in parallel ("happy
+    /// eyeballs"). The first connection to succeed is used, so a broken IPv6 (or IPv4) path
+    /// only delays connecting by a fraction of a second.
+    ///
+    /// Restricting this to one IP version is useful on hosts where the other one is
+    /// misconfigured. This is currently only supported by the TCP transports, the
+    /// WebSocket transports ignore it.
+    ///
+    /// Default value: `AddressFamily::Any`
+    pub address_family: AddressFamily,
+
+    /// After connecting and logging in, the server is expected to greet us (with the `001`
+    /// welcome message) within this time, otherwise the connection is closed with
+    /// `Error::HandshakeTimeout`. This detects silent servers much faster than the
+    /// regular PING-based liveness check. Default value: 10 seconds.
+    pub handshake_timeout: Duration,
+
+    /// Whether the client sends a `PING` on every connection every 30 seconds, and closes
+    /// the connection with `Error::PingTimeout` if the server does not answer it within
+    /// 5 seconds. `PING`s sent by the server are always answered, regardless of this setting.
+    ///
+    /// If this is disabled, dead connections are only detected once the operating system
+    /// reports the connection as closed, so it is recommended to set `reconnect_if_idle`
+    /// in that case.
+    ///
+    /// Default value: `true`
+    pub send_pings: bool,
+
+    /// If set, a connection is closed with `Error::IdleTimeout` (and replaced with a new
+    /// connection) if no messages other than `PING` and `PONG` have been received on it
+    /// for this long. Unlike the PING-based liveness check, this also detects connections
+    /// that are alive, but silently stopped delivering chat messages.
+    ///
+    /// Note that connections that have only joined quiet channels (or no channels at all)
+    /// are reconnected regularly if this is set. Such reconnects are not counted as failures
+    /// for `max_reconnect_attempts` and `max_reconnect_duration`.
+    ///
+    /// Default value: `None` (disabled)
+    pub reconnect_if_idle: Option<Duration>,
+
+    /// When Twitch sends a `RECONNECT`, keep receiving messages from the old connection for
+    /// this long while the replacement connection is being established, instead of closing
+    /// it immediately. Twitch often still delivers messages on the old connection for a short
+    /// while, which would otherwise be lost.
+    ///
+    /// The old connection is no longer used to send messages or join channels during this time.
+    /// Note that messages may be received twice if they arrive on both the old and the
+    /// replacement connection during the grace period.
+    ///
+    /// Default value: 0 (close the connection immediately)
+    pub reconnect_grace_period: Duration,
+
+    /// Whether a line received from the server that cannot be parsed as an IRC message closes
+    /// the connection (with `Error::IRCParseError`). If `false`, such lines are logged and
+    /// skipped, and the connection continues to be used.
+    ///
+    /// Note that messages that are valid IRC but cannot be parsed as their specific
+    /// `ServerMessage` type never close the connection, they are emitted as a generic
+    /// message instead. Default value: `false`.
+    pub close_on_parse_error: bool,
+
+    /// If set, incoming messages carrying the same `id` tag as a message received within this
+    /// duration are dropped, instead of being delivered twice. This can happen while a channel
+    /// is briefly joined on two connections, e.g. during the `reconnect_grace_period`.
+    ///
+    /// At most 10000 message IDs are remembered, so with very busy channels, duplicates may
+    /// still be delivered if they arrive further apart.
+    ///
+    /// Default value: `None` (no deduplication)
+    pub dedup_window: Option<Duration>,
+
+    /// If `true`, messages that were sent in another channel of a shared chat session and
+    /// are marked to only be displayed in that channel (`PrivmsgMessage::source_only`) are
+    /// dropped instead of being delivered. The same message is still delivered if the channel
+    /// it was sent in is joined as well. This prevents displaying messages in the wrong channel.
+    ///
+    /// Default value: `false`
+    pub drop_source_only_shared_messages: bool,
+
+    /// When the client is closed (by dropping all `TwitchIRCClient` handles), each open
+    /// connection waits at most this long for the messages that were queued up before closing
+    /// to be sent out, and for the connection to be closed cleanly. After that, the connection
+    /// is dropped regardless.
+    ///
+    /// This does not apply to connections that close because of an error.
+    ///
+    /// Default value: 5 seconds
+    pub shutdown_flush_timeout: Duration,
+
+    /// Whether the `twitch.tv/membership` capability is requested from Twitch, and whether
+    /// the `JOIN` and `PART` messages of other users are delivered. See `MembershipMode`.
+    ///
+    /// Default value: `MembershipMode::Disabled`
+    pub membership: MembershipMode,
+
+    /// If set, at most this many incoming messages are buffered until you receive them
+    /// from the `IncomingMessages` returned by `TwitchIRCClient::new`. What happens once the
+    /// buffer is full is decided by `incoming_overflow_policy`. This protects against
+    /// unbounded memory growth if the application stalls while handling messages.
+    ///
+    /// Must be at least 1. Default value: `None` (the buffer is unbounded)
+    pub incoming_messages_capacity: Option<usize>,
+
+    /// What happens to new incoming messages while the buffer is full. Only used if
+    /// `incoming_messages_capacity` is set. Dropped messages are counted in the
+    /// `twitch_irc_incoming_messages_dropped` metric.
+    ///
+    /// Default value: `OverflowPolicy::DropOldest`
+    pub incoming_overflow_policy: OverflowPolicy,
+
+    /// If set, additional receivers for the incoming messages can be created using
+    /// `TwitchIRCClient::subscribe`. Every subscriber receives its own copy of all messages
+    /// that arrive after it subscribed, independently of the other subscribers and of the
+    /// `IncomingMessages` returned by `TwitchIRCClient::new`.
+    ///
+    /// This is a [`tokio::sync::broadcast`](tokio::sync::broadcast) channel buffering this many
+    /// messages. Sending to subscribers never waits: if a subscriber falls behind by more than
+    /// this many messages, the oldest messages are dropped for that subscriber, and its next
+    /// `recv()` returns `RecvError::Lagged` with the number of skipped messages. Receiving then
+    /// continues with the oldest message still buffered.
+    ///
+    /// Messages are still delivered to the `IncomingMessages` as well. If you only use
+    /// subscriptions, drop the `IncomingMessages` so messages are not buffered for it.
+    ///
+    /// Subscribers created with `TwitchIRCClient::subscribe_commands` (which only receive some
+    /// kinds of messages) each get their own buffer of this many messages.
+    ///
+    /// Must be at least 1. Default value: `None` (`subscribe()` and `subscribe_commands()`
+    /// return `None`)
+    pub subscription_capacity: Option<usize>,
+
+    /// How many incoming messages are held back while the client is paused using
+    /// `TwitchIRCClient::pause()`. They are delivered once `TwitchIRCClient::resume()` is
+    /// called. If more messages arrive while paused, the oldest held back messages are dropped,
+    /// and counted in the `twitch_irc_incoming_messages_dropped` metric.
+    ///
+    /// Default value: 10000
+    pub paused_messages_capacity: usize,
+
+    /// If set, the client gives up once this many connections have failed in a row without
+    /// any connection being opened successfully in between. A failed connection is normally
+    /// replaced by a new one, so this is the number of attempts to reconnect: With `Some(3)`,
+    /// the client gives up when the connection made by the third attempt fails as well.
+    /// Failures of all connections in the pool count towards the same limit. Connections
+    /// closed because of a `RECONNECT` from Twitch, `TwitchIRCClient::force_reconnect()` or
+    /// `reconnect_if_idle` are not counted as failures.
+    ///
+    /// After giving up, the client closes all connections and does not make new ones:
+    /// The `IncomingMessages` returned by `TwitchIRCClient::new` returns `None` once all
+    /// remaining messages have been received, sending messages fails with
+    /// `Error::GaveUpReconnecting`, and joining channels has no effect. This is meant for
+    /// applications that should exit with an error instead of retrying forever.
+    ///
+    /// Default value: `None` (reconnect forever)
+    pub max_reconnect_attempts: Option<u32>,
+
+    /// If set, the client gives up once connections have kept failing for this long, measured
+    /// from the first failure after a connection was last opened successfully. This is checked
+    /// each time a connection fails. See `max_reconnect_attempts` for what giving up means,
+    /// both limits can be combined.
+    ///
+    /// Default value: `None` (reconnect forever)
+    pub max_reconnect_duration: Option<Duration>,
+
+    /// While connections keep failing, the client waits before opening each replacement
+    /// connection. The first failed connection is replaced right away. After that, the delay
+    /// starts at `reconnect_backoff` and doubles with every further failure in a row (counted
+    /// the same way as for `max_reconnect_attempts`), up to `max_reconnect_backoff`. The delay
+    /// is reset once a connection is opened successfully. See
+    /// `TwitchIRCClient::reconnect_backoff()` for the current delay.
+    ///
+    /// Set to `Duration::ZERO` to always reconnect right away. Default value: 1 second.
+    pub reconnect_backoff: Duration,
+
+    /// Upper limit for the delay before replacing a failed connection, see `reconnect_backoff`.
+    ///
+    /// Default value: 60 seconds.
+    pub max_reconnect_backoff: Duration,
+
+    /// Called right before a replacement connection is opened after a connection failed, e.g.
+    /// to rotate credentials or send a notification. The hook is only called if a new
+    /// connection is actually needed (the channels of the failed connection might also be
+    /// rejoined on connections that still have capacity left).
+    ///
+    /// The replacement connection(s) wait for the returned future to complete before connecting,
+    /// so a slow hook delays the reconnect. Default value: `None`.
+    pub on_reconnect: Option<ReconnectHook>,
+
+    /// Called when Twitch refuses to deliver a chat message sent by this client, e.g. because
+    /// the channel is in emote-only or subscribers-only mode.
+    ///
+    /// Twitch reports this with a `NOTICE` (see `NoticeMessage::send_failure_reason()`), which
+    /// does not identify the message that failed. The client therefore matches the `NOTICE`
+    /// to the oldest message sent to the same channel within the last 10 seconds that has not
+    /// been matched yet. This is best-effort: Messages that were delivered successfully are
+    /// not confirmed by Twitch, so a failure can be attributed to the wrong message if
+    /// some messages to the same channel succeed and others fail. Notices that cannot be
+    /// matched to any recently sent message are not reported to the hook.
+    ///
+    /// The hook is called from the client's event loop, so it should return quickly.
+    /// The `NOTICE` is delivered through the incoming messages as usual, regardless of this
+    /// setting. Default value: `None`.
+    pub on_send_failure: Option<SendFailureHook>,
+
+    /// Called with every line received from Twitch, exactly as it was received (without the
+    /// trailing `\r\n`), before it is parsed. This also includes empty lines and lines that
+    /// cannot be parsed, which are otherwise dropped (see `close_on_parse_error`). This is meant
+    /// for debugging and logging, the hook cannot modify or drop lines.
+    ///
+    /// The hook is called from the task reading from the connection, before the parsed message
+    /// is processed by the client. So a message is always observed by the hook before it is
+    /// delivered through the incoming messages. Lines from different connections are observed
+    /// concurrently, so the hook should return quickly.
+    ///
+    /// Only supported by transports that support `TransportOptions::raw_line_hook`, which
+    /// all transports of this library do. Default value: `None`.
+    pub on_raw_line: Option<RawLineHook>,
+
+    /// Called with every message that is sent to Twitch, including messages sent by the
+    /// client itself (e.g. the login, `JOIN`s, `PING`s and `PONG`s). Messages are observed
+    /// after rate limiting, right before they are handed to the transport. This is meant for
+    /// logging and auditing, the hook cannot modify or drop messages.
+    ///
+    /// The hook is called from the event loop of the connection, so it must return quickly
+    /// (e.g. by only storing the message, or sending it to a channel).
+    ///
+    /// Default value: `None`.
+    pub on_outgoing_message: Option<OutgoingMessageHook>,
+
     /// Set this to `None` to disable metrics collection for this client.
     ///
     /// If this is set to `Some(value)`, then metrics are collected from this client using
@@ -61,6 +538,15 @@ pub struct ClientConfig<L: LoginCredentials> {
     ///
     /// * `twitch_irc_messages_sent` counts messages sent out, with a `command` label. (Counter)
     ///
+    /// * `twitch_irc_message_size_bytes` records the size of each incoming message in bytes,
+    ///   with a `command` label. (Histogram) The size is measured on the line as it was
+    ///   received, without the trailing `\r\n`. Like `on_raw_line`, this is only recorded
+    ///   by transports that support `TransportOptions::raw_line_hook`, which all
+    ///   transports of this library do.
+    ///
+    /// * `twitch_irc_ping_rtt_seconds` records the round-trip time of the keepalive `PING`s
+    ///   the client sends every 30 seconds on each connection, in seconds. (Histogram)
+    ///
     /// * `twitch_irc_channels` with `type=allocated/confirmed` counts how many channels
     ///   you are joined to (Gauge). Allocated channels are joins that passed through the `TwitchIRCClient`
     ///   but may be waiting e.g. for the connection to finish connecting. Once a
@@ -76,6 +562,10 @@ pub struct ClientConfig<L: LoginCredentials> {
     ///   actually have been reconnected (despite the name `twitch_irc_reconnects`).
     ///   If other connections have enough capacity left to join the channels from the failed
     ///   connection, then no new connection will be made.
+    ///
+    /// * `twitch_irc_incoming_messages_dropped` counts incoming messages that were dropped
+    ///   because the buffer of incoming messages was full (Counter). See
+    ///   `incoming_messages_capacity`.
     #[cfg(feature = "metrics-collection")]
     pub metrics_identifier: Option<Cow<'static, str>>,
 }
@@ -86,6 +576,7 @@ impl<L: LoginCredentials> ClientConfig<L> {
     pub fn new_simple(login_credentials: L) -> ClientConfig<L> {
         ClientConfig {
             login_credentials,
+            read_credentials: None,
             max_channels_per_connection: 90,
 
             max_waiting_messages_per_connection: 5,
@@ -94,12 +585,87 @@ impl<L: LoginCredentials> ClientConfig<L> {
             // 1 connection every 2 seconds seems to work well
             connection_rate_limiter: Arc::new(Semaphore::new(1)),
             new_connection_every: Duration::from_secs(2),
+            warm_connections: 0,
+            log_identifier: None,
             connect_timeout: Duration::from_secs(20),
+            address_family: AddressFamily::Any,
+            handshake_timeout: Duration::from_secs(10),
+            send_pings: true,
+            reconnect_if_idle: None,
+            reconnect_grace_period: Duration::from_secs(0),
+            close_on_parse_error: false,
+            dedup_window: None,
+            drop_source_only_shared_messages: false,
+            shutdown_flush_timeout: Duration::from_secs(5),
+            membership: MembershipMode::Disabled,
+            incoming_messages_capacity: None,
+            incoming_overflow_policy: OverflowPolicy::DropOldest,
+            subscription_capacity: None,
+            paused_messages_capacity: 10_000,
+            max_reconnect_attempts: None,
+            max_reconnect_duration: None,
+            reconnect_backoff: Duration::from_secs(1),
+            max_reconnect_backoff: Duration::from_secs(60),
+            on_reconnect: None,
+            on_send_failure: None,
+            on_raw_line: None,
+            on_outgoing_message: None,
 
             #[cfg(feature = "metrics-collection")]
             metrics_identifier: None,
         }
     }
+
+    /// Allow at most `max_parallel_connects` connections to be opened in parallel, and only
+    /// allow a new connection to be made `new_connection_every` after each of those
+    /// connections was established.
+    ///
+    /// This replaces `connection_rate_limiter` with a new semaphore that has
+    /// `max_parallel_connects` permits (so it is no longer shared with other clients
+    /// it might have previously been shared with) and sets `new_connection_every`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_parallel_connects` is `0`, since no connection could ever be opened then.
+    pub fn set_connection_rate_limit(
+        &mut self,
+        max_parallel_connects: usize,
+        new_connection_every: Duration,
+    ) {
+        assert!(
+            max_parallel_connects > 0,
+            "max_parallel_connects must be at least 1"
+        );
+        self.connection_rate_limiter = Arc::new(Semaphore::new(max_parallel_connects));
+        self.new_connection_every = new_connection_every;
+    }
+}
+
+impl ClientConfig<StaticLoginCredentials> {
+    /// Create a new configuration that logs in with the given login name and OAuth access token,
+    /// with all other configuration options being default. This is the recommended way to
+    /// configure a bot that always logs in as the same user.
+    ///
+    /// The `token` may be given with or without the leading `oauth:` prefix.
+    ///
+    /// ```
+    /// use twitch_irc::ClientConfig;
+    ///
+    /// let config = ClientConfig::new_with_token(
+    ///     "your_bot_name".to_owned(),
+    ///     "oauth:u0i05p6kbswa1w72wu1h1skio3o20t".to_owned(),
+    /// );
+    /// let credentials = &config.login_credentials.credentials;
+    /// assert_eq!(credentials.login, "your_bot_name");
+    /// assert_eq!(credentials.token.as_deref(), Some("u0i05p6kbswa1w72wu1h1skio3o20t"));
+    /// ```
+    pub fn new_with_token(login: String, token: String) -> ClientConfig<StaticLoginCredentials> {
+        let token = match token.strip_prefix(TWITCH_TOKEN_PREFIX) {
+            Some(token) => token.to_owned(),
+            None => token,
+        };
+        ClientConfig::new_simple(StaticLoginCredentials::new(login, Some(token)))
+    }
 }
 
 impl Default for ClientConfig<StaticLoginCredentials> {
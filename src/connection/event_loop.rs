@@ -1,4 +1,4 @@
-use crate::config::ClientConfig;
+use crate::config::{ClientConfig, MembershipMode, RawLineHook};
 use crate::connection::ConnectionIncomingMessage;
 use crate::error::Error;
 use crate::irc;
@@ -6,8 +6,11 @@ use crate::login::{CredentialsPair, LoginCredentials};
 use crate::message::commands::ServerMessage;
 use crate::message::AsRawIRC;
 use crate::message::IRCMessage;
-use crate::transport::Transport;
+#[cfg(feature = "metrics-collection")]
+use crate::transport::framing;
+use crate::transport::{Transport, TransportOptions};
 use enum_dispatch::enum_dispatch;
+use futures_util::future::{BoxFuture, Shared};
 use futures_util::{SinkExt, StreamExt};
 use itertools::Either;
 use std::collections::VecDeque;
@@ -15,6 +18,7 @@ use std::convert::TryFrom;
 use std::sync::{Arc, Weak};
 use tokio::sync::oneshot::Sender;
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 use tokio::time::{interval_at, Duration, Instant};
 
 #[derive(Debug)]
@@ -35,6 +39,13 @@ pub(crate) enum ConnectionLoopCommand<T: Transport, L: LoginCredentials> {
     // commands that come from the ping loop
     SendPing(),
     CheckPong(),
+    CheckIdle(),
+
+    // comes from the client loop (TwitchIRCClient::force_reconnect)
+    ForceClose(),
+
+    // comes from the init task, once `handshake_timeout` has elapsed after init finished
+    CheckHandshake(),
 }
 
 #[enum_dispatch]
@@ -55,6 +66,9 @@ trait ConnectionLoopStateMethods<T: Transport, L: LoginCredentials> {
     ) -> ConnectionLoopState<T, L>;
     fn send_ping(&mut self);
     fn check_pong(self) -> ConnectionLoopState<T, L>;
+    fn check_idle(self) -> ConnectionLoopState<T, L>;
+    fn force_close(self) -> ConnectionLoopState<T, L>;
+    fn check_handshake(self) -> ConnectionLoopState<T, L>;
 }
 
 #[enum_dispatch(ConnectionLoopStateMethods<T, L>)]
@@ -64,9 +78,38 @@ enum ConnectionLoopState<T: Transport, L: LoginCredentials> {
     Closed(ConnectionLoopClosedState<T, L>),
 }
 
+/// The hook passed to the transport: the `ClientConfig::on_raw_line` hook, plus recording
+/// the size of each received line in the `twitch_irc_message_size_bytes` metric. The size is
+/// taken from the raw line, so the message does not have to be formatted again to measure it.
+fn make_raw_line_hook<L: LoginCredentials>(config: &ClientConfig<L>) -> Option<RawLineHook> {
+    #[cfg(feature = "metrics-collection")]
+    if let Some(ref metrics_identifier) = config.metrics_identifier {
+        let metrics_identifier = metrics_identifier.clone();
+        let on_raw_line = config.on_raw_line.clone();
+        return Some(RawLineHook::new(move |line| {
+            if let Some(command) = framing::line_command(line) {
+                let line_len = line.trim_end_matches(&['\r', '\n'][..]).len();
+                metrics::histogram!(
+                    "twitch_irc_message_size_bytes",
+                    line_len as f64,
+                    "client" => metrics_identifier.clone(),
+                    "command" => command.to_owned()
+                );
+            }
+            if let Some(ref on_raw_line) = on_raw_line {
+                on_raw_line.observe(line);
+            }
+        }));
+    }
+
+    config.on_raw_line.clone()
+}
+
 pub(crate) struct ConnectionLoopWorker<T: Transport, L: LoginCredentials> {
     connection_loop_rx: mpsc::UnboundedReceiver<ConnectionLoopCommand<T, L>>,
     state: ConnectionLoopState<T, L>,
+    /// prepended to all log messages of this connection, see `ClientConfig::log_identifier`
+    log_prefix: Arc<str>,
     #[cfg(feature = "metrics-collection")]
     config: Arc<ClientConfig<L>>,
 }
@@ -74,6 +117,9 @@ pub(crate) struct ConnectionLoopWorker<T: Transport, L: LoginCredentials> {
 impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
     pub fn spawn(
         config: Arc<ClientConfig<L>>,
+        log_prefix: Arc<str>,
+        read_only: bool,
+        before_connect: Option<Shared<BoxFuture<'static, ()>>>,
         connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
         connection_loop_rx: mpsc::UnboundedReceiver<ConnectionLoopCommand<T, L>>,
@@ -84,15 +130,19 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
                 commands_queue: VecDeque::new(),
                 connection_loop_tx: Weak::clone(&connection_loop_tx),
                 connection_incoming_tx,
-                #[cfg(feature = "metrics-collection")]
                 config: Arc::clone(&config),
+                log_prefix: Arc::clone(&log_prefix),
             }),
+            log_prefix: Arc::clone(&log_prefix),
             #[cfg(feature = "metrics-collection")]
             config: Arc::clone(&config),
         };
 
         tokio::spawn(ConnectionLoopWorker::run_init_task(
             config,
+            log_prefix,
+            read_only,
+            before_connect,
             connection_loop_tx,
         ));
         tokio::spawn(worker.run());
@@ -100,27 +150,44 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
 
     async fn run_init_task(
         config: Arc<ClientConfig<L>>,
+        log_prefix: Arc<str>,
+        read_only: bool,
+        before_connect: Option<Shared<BoxFuture<'static, ()>>>,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
     ) {
-        log::debug!("Spawned connection init task");
+        log::debug!("{}Spawned connection init task", log_prefix);
+        if let Some(before_connect) = before_connect {
+            log::trace!("{}Waiting for reconnect hook to complete...", log_prefix);
+            before_connect.await;
+        }
         // async{}.await is used in place of a try block since they are not stabilized yet
         // TODO revise this once try blocks are stabilized
+        let handshake_timeout = config.handshake_timeout;
         let res = async {
-            let credentials = config
-                .login_credentials
-                .get_credentials()
-                .await
-                .map_err(Arc::new)
-                .map_err(Error::LoginError)?;
+            let credentials = match (read_only, &config.read_credentials) {
+                (true, Some(read_credentials)) => read_credentials.clone(),
+                _ => config
+                    .login_credentials
+                    .get_credentials()
+                    .await
+                    .map_err(Arc::new)
+                    .map_err(Error::LoginError)?,
+            };
 
             // rate limits the opening of new connections
-            log::trace!("Trying to acquire permit for opening transport...");
+            log::trace!(
+                "{}Trying to acquire permit for opening transport...",
+                log_prefix
+            );
             let rate_limit_permit = Arc::clone(&config.connection_rate_limiter)
                 .acquire_owned()
                 .await;
-            log::trace!("Successfully got permit to open transport.");
+            log::trace!("{}Successfully got permit to open transport.", log_prefix);
 
-            let connect_attempt = T::new();
+            let connect_attempt = T::new_with_options(TransportOptions {
+                raw_line_hook: make_raw_line_hook(&config),
+                address_family: config.address_family,
+            });
             let timeout = tokio::time::sleep(config.connect_timeout);
 
             let transport = tokio::select! {
@@ -135,10 +202,14 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
 
             // release the rate limit permit after the transport is connected and after
             // the specified time has elapsed.
+            let log_prefix = Arc::clone(&log_prefix);
             tokio::spawn(async move {
                 tokio::time::sleep(config.new_connection_every).await;
                 drop(rate_limit_permit);
-                log::trace!("Successfully released permit after waiting specified duration.");
+                log::trace!(
+                    "{}Successfully released permit after waiting specified duration.",
+                    log_prefix
+                );
             });
 
             Ok::<(T, CredentialsPair), Error<T, L>>((transport, credentials))
@@ -146,19 +217,37 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
         .await;
 
         // res is now the result of the init work
+        let init_successful = res.is_ok();
         if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
             connection_loop_tx
                 .send(ConnectionLoopCommand::TransportInitFinished(res))
                 .ok();
         }
+
+        // the connection logs in as soon as it receives the init result. Give the server
+        // `handshake_timeout` to greet us, then make the connection check that it did.
+        if init_successful {
+            tokio::time::sleep(handshake_timeout).await;
+            if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
+                connection_loop_tx
+                    .send(ConnectionLoopCommand::CheckHandshake())
+                    .ok();
+            }
+        }
     }
 
     async fn run(mut self) {
-        log::debug!("Spawned connection event loop");
+        log::debug!("{}Spawned connection event loop", self.log_prefix);
         while let Some(command) = self.connection_loop_rx.recv().await {
             self = self.process_command(command);
         }
-        log::debug!("Connection event loop ended")
+        log::debug!("{}Connection event loop ended", self.log_prefix);
+
+        // all handles to this connection were dropped, so this is a deliberate close
+        // (as opposed to the connection failing). Give queued messages a chance to go out.
+        if let ConnectionLoopState::Open(open_state) = self.state {
+            open_state.flush_and_close().await;
+        }
     }
 
     /// Process a command, consuming the current state and returning a new state
@@ -176,7 +265,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
             ConnectionLoopCommand::IncomingMessage(maybe_msg) => {
                 match &maybe_msg {
                     Some(Ok(msg)) => {
-                        log::trace!("< {}", msg.as_raw_irc());
+                        log::trace!("{}< {}", self.log_prefix, msg.as_raw_irc());
                         #[cfg(feature = "metrics-collection")]
                         if let Some(ref metrics_identifier) = self.config.metrics_identifier {
                             metrics::counter!(
@@ -184,11 +273,11 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
                                 1,
                                 "client" => metrics_identifier.clone(),
                                 "command" => msg.command.clone()
-                            )
+                            );
                         }
                     }
-                    Some(Err(e)) => log::trace!("Error from transport: {}", e),
-                    None => log::trace!("EOF from transport"),
+                    Some(Err(e)) => log::trace!("{}Error from transport: {}", self.log_prefix, e),
+                    None => log::trace!("{}EOF from transport", self.log_prefix),
                 }
 
                 self.state = self.state.on_incoming_message(maybe_msg);
@@ -197,6 +286,15 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
             ConnectionLoopCommand::CheckPong() => {
                 self.state = self.state.check_pong();
             }
+            ConnectionLoopCommand::CheckIdle() => {
+                self.state = self.state.check_idle();
+            }
+            ConnectionLoopCommand::ForceClose() => {
+                self.state = self.state.force_close();
+            }
+            ConnectionLoopCommand::CheckHandshake() => {
+                self.state = self.state.check_handshake();
+            }
         };
         self
     }
@@ -216,13 +314,13 @@ struct ConnectionLoopInitializingState<T: Transport, L: LoginCredentials> {
     commands_queue: CommandQueue<T, L>,
     connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
     connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
-    #[cfg(feature = "metrics-collection")]
     config: Arc<ClientConfig<L>>,
+    log_prefix: Arc<str>,
 }
 
 impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
     fn transition_to_closed(self, err: Error<T, L>) -> ConnectionLoopState<T, L> {
-        log::info!("Closing connection, reason: {}", err);
+        log::info!("{}Closing connection, reason: {}", self.log_prefix, err);
 
         for (_message, return_sender) in self.commands_queue.into_iter() {
             if let Some(return_sender) = return_sender {
@@ -244,8 +342,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
         mut transport_incoming: T::Incoming,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
         mut shutdown_notify: oneshot::Receiver<()>,
+        close_on_parse_error: bool,
+        log_prefix: Arc<str>,
     ) {
-        log::debug!("Spawned incoming messages forwarder");
+        log::debug!("{}Spawned incoming messages forwarder", log_prefix);
         loop {
             tokio::select! {
                 _ = &mut shutdown_notify => {
@@ -253,6 +353,13 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
                     break;
                 }
                 incoming_message = transport_incoming.next() => {
+                    if let Some(Err(Either::Right(ref parse_error))) = incoming_message {
+                        if !close_on_parse_error {
+                            log::warn!("{}Skipping incoming line that could not be parsed as an IRC message: {}", log_prefix, parse_error);
+                            continue;
+                        }
+                    }
+
                     let do_exit = matches!(incoming_message, None | Some(Err(_)));
                     let incoming_message = incoming_message.map(|x| x.map_err(|e| match e {
                         Either::Left(e) => Error::IncomingError(Arc::new(e)),
@@ -271,15 +378,18 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
                 }
             }
         }
-        log::debug!("Incoming messages forwarder ended");
+        log::debug!("{}Incoming messages forwarder ended", log_prefix);
     }
 
     async fn run_outgoing_forward_task(
         mut transport_outgoing: T::Outgoing,
         mut messages_rx: MessageReceiver<T, L>,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
+        log_prefix: Arc<str>,
     ) {
-        log::debug!("Spawned outgoing messages forwarder");
+        log::debug!("{}Spawned outgoing messages forwarder", log_prefix);
+        // this loop ends once the connection's sender is dropped and all messages
+        // queued up before that have been sent
         while let Some((message, reply_sender)) = messages_rx.recv().await {
             let res = transport_outgoing.send(message).await.map_err(Arc::new);
 
@@ -297,47 +407,69 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
                 reply_sender.send(res.map_err(Error::OutgoingError)).ok();
             }
         }
+
+        if let Err(e) = transport_outgoing.close().await {
+            log::debug!("{}Failed to cleanly close transport: {}", log_prefix, e);
+        }
     }
 
     async fn run_ping_task(
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
         mut shutdown_notify: oneshot::Receiver<()>,
+        send_pings: bool,
+        reconnect_if_idle: Option<Duration>,
+        log_prefix: Arc<str>,
     ) {
-        log::debug!("Spawned pinger task");
+        log::debug!("{}Spawned pinger task", log_prefix);
         // every 30 seconds we send out a PING
         // 5 seconds after sending it out, we check that we got a PONG message since sending that PING
         // if not, the connection is failed with an error (Error::PingTimeout)
+        // (unless `send_pings` is disabled, then we only answer the PINGs sent by the server)
         let ping_every = Duration::from_secs(30);
         let check_pong_after = Duration::from_secs(5);
 
         let mut send_ping_interval = interval_at(Instant::now() + ping_every, ping_every);
         let mut check_pong_interval =
             interval_at(Instant::now() + ping_every + check_pong_after, ping_every);
+        // if `reconnect_if_idle` is set, the connection checks (at least every second) whether
+        // it has received a message within that time, otherwise it fails with Error::IdleTimeout
+        let check_idle_every = reconnect_if_idle.map_or(ping_every, |reconnect_if_idle| {
+            reconnect_if_idle.min(Duration::from_secs(1))
+        });
+        let mut check_idle_interval =
+            interval_at(Instant::now() + check_idle_every, check_idle_every);
 
         loop {
             tokio::select! {
                 _ = &mut shutdown_notify => {
                     break;
                 },
-                _ = send_ping_interval.tick() => {
-                    log::trace!("sending ping");
+                _ = send_ping_interval.tick(), if send_pings => {
+                    log::trace!("{}sending ping", log_prefix);
                     if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
                         connection_loop_tx.send(ConnectionLoopCommand::SendPing()).ok();
                     } else {
                         break;
                     }
                 }
-                _ = check_pong_interval.tick() => {
-                    log::trace!("checking for pong");
+                _ = check_pong_interval.tick(), if send_pings => {
+                    log::trace!("{}checking for pong", log_prefix);
                     if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
                         connection_loop_tx.send(ConnectionLoopCommand::CheckPong()).ok();
                     } else {
                         break;
                     }
                 }
+                _ = check_idle_interval.tick(), if reconnect_if_idle.is_some() => {
+                    if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
+                        connection_loop_tx.send(ConnectionLoopCommand::CheckIdle()).ok();
+                    } else {
+                        break;
+                    }
+                }
             }
         }
-        log::debug!("Pinger task ended");
+        log::debug!("{}Pinger task ended", log_prefix);
     }
 }
 
@@ -359,7 +491,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
         match init_result {
             Ok((transport, credentials)) => {
                 // transport was opened successfully
-                log::debug!("Transport init task has finished, transitioning to Initializing");
+                log::debug!(
+                    "{}Transport init task has finished, transitioning to Initializing",
+                    self.log_prefix
+                );
                 let (transport_incoming, transport_outgoing) = transport.split();
 
                 let (kill_incoming_loop_tx, kill_incoming_loop_rx) = oneshot::channel();
@@ -367,43 +502,61 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                     transport_incoming,
                     Weak::clone(&self.connection_loop_tx),
                     kill_incoming_loop_rx,
+                    self.config.close_on_parse_error,
+                    Arc::clone(&self.log_prefix),
                 ));
 
                 let (outgoing_messages_tx, outgoing_messages_rx) = mpsc::unbounded_channel();
-                tokio::spawn(ConnectionLoopInitializingState::run_outgoing_forward_task(
-                    transport_outgoing,
-                    outgoing_messages_rx,
-                    Weak::clone(&self.connection_loop_tx),
-                ));
+                let outgoing_forward_task =
+                    tokio::spawn(ConnectionLoopInitializingState::run_outgoing_forward_task(
+                        transport_outgoing,
+                        outgoing_messages_rx,
+                        Weak::clone(&self.connection_loop_tx),
+                        Arc::clone(&self.log_prefix),
+                    ));
 
                 let (kill_pinger_tx, kill_pinger_rx) = oneshot::channel();
                 tokio::spawn(ConnectionLoopInitializingState::run_ping_task(
                     Weak::clone(&self.connection_loop_tx),
                     kill_pinger_rx,
+                    self.config.send_pings,
+                    self.config.reconnect_if_idle,
+                    Arc::clone(&self.log_prefix),
                 ));
 
+                let capabilities = match self.config.membership {
+                    MembershipMode::Disabled => "twitch.tv/tags twitch.tv/commands",
+                    MembershipMode::OwnOnly | MembershipMode::All => {
+                        "twitch.tv/tags twitch.tv/commands twitch.tv/membership"
+                    }
+                };
+
                 // transition our own state from Initializing to Open
-                #[cfg(feature = "metrics-collection")]
                 self.connection_incoming_tx
-                    .send(ConnectionIncomingMessage::StateOpen)
+                    .send(ConnectionIncomingMessage::StateOpen {
+                        user_login: credentials.login.clone(),
+                    })
                     .ok();
 
                 let mut new_state = ConnectionLoopState::Open(ConnectionLoopOpenState {
                     connection_incoming_tx: self.connection_incoming_tx,
                     outgoing_messages_tx,
                     pong_received: false,
+                    pending_ping: None,
+                    pings_sent: 0,
+                    handshake_completed: false,
+                    last_message_received_at: Instant::now(),
+                    close_reported: false,
                     kill_incoming_loop_tx: Some(kill_incoming_loop_tx),
                     kill_pinger_tx: Some(kill_pinger_tx),
-                    #[cfg(feature = "metrics-collection")]
+                    outgoing_forward_task: Some(outgoing_forward_task),
                     config: self.config,
+                    log_prefix: self.log_prefix,
                 });
 
-                new_state.send_message(
-                    irc!["CAP", "REQ", "twitch.tv/tags twitch.tv/commands"],
-                    None,
-                );
-                if let Some(token) = credentials.token {
-                    new_state.send_message(irc!["PASS", format!("oauth:{}", token)], None);
+                new_state.send_message(irc!["CAP", "REQ", capabilities], None);
+                if let Some(pass) = credentials.pass() {
+                    new_state.send_message(irc!["PASS", pass], None);
                 }
                 new_state.send_message(irc!["NICK", credentials.login], None);
 
@@ -415,7 +568,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
             }
             Err(init_error) => {
                 // emit error to downstream + transition to closed
-                log::error!("Transport init task has finished with error, closing connection");
+                log::error!(
+                    "{}Transport init task has finished with error, closing connection",
+                    self.log_prefix
+                );
                 self.transition_to_closed(init_error)
             }
         }
@@ -439,6 +595,18 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     fn check_pong(self) -> ConnectionLoopState<T, L> {
         unreachable!("pinger should not run while initializing")
     }
+
+    fn check_idle(self) -> ConnectionLoopState<T, L> {
+        unreachable!("pinger should not run while initializing")
+    }
+
+    fn force_close(self) -> ConnectionLoopState<T, L> {
+        self.transition_to_closed(Error::ForcedReconnect)
+    }
+
+    fn check_handshake(self) -> ConnectionLoopState<T, L> {
+        unreachable!("handshake is only checked after transport init has finished")
+    }
 }
 
 //
@@ -448,23 +616,67 @@ struct ConnectionLoopOpenState<T: Transport, L: LoginCredentials> {
     connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
     outgoing_messages_tx: MessageSender<T, L>,
     pong_received: bool,
+    /// token and send time of the last PING we sent that has not been answered yet
+    pending_ping: Option<(String, Instant)>,
+    /// used to generate a unique token for every PING sent on this connection
+    pings_sent: u64,
+    /// whether the server has greeted us after logging in (with `001` or `GLOBALUSERSTATE`)
+    handshake_completed: bool,
+    /// when the last message other than `PING` or `PONG` was received (or when the connection
+    /// was opened), see `config.reconnect_if_idle`
+    last_message_received_at: Instant,
+    /// whether `StateClosed` has already been sent to the client. This happens ahead of the
+    /// actual closure after a `RECONNECT`, if `config.reconnect_grace_period` is nonzero.
+    close_reported: bool,
     /// To kill the background pinger and forward tasks when this gets dropped.
     /// These fields are wrapped in `Option` so we can use `take()` in the Drop implementation.
     kill_incoming_loop_tx: Option<oneshot::Sender<()>>,
     kill_pinger_tx: Option<oneshot::Sender<()>>,
-    #[cfg(feature = "metrics-collection")]
+    /// The task sending out `outgoing_messages_tx`, awaited by `flush_and_close`.
+    /// Wrapped in `Option` for the same reason as the fields above.
+    outgoing_forward_task: Option<JoinHandle<()>>,
     config: Arc<ClientConfig<L>>,
+    log_prefix: Arc<str>,
 }
 
 impl<T: Transport, L: LoginCredentials> ConnectionLoopOpenState<T, L> {
+    /// Closes the connection, but first waits (for at most `config.shutdown_flush_timeout`)
+    /// until all messages that were already queued up have been sent and the transport has
+    /// been closed cleanly.
+    async fn flush_and_close(mut self) {
+        let flush_timeout = self.config.shutdown_flush_timeout;
+        let mut outgoing_forward_task = self.outgoing_forward_task.take().unwrap();
+        let log_prefix = Arc::clone(&self.log_prefix);
+
+        // dropping the state drops `outgoing_messages_tx`, which makes the forward task
+        // exit after the last queued message (and also stops the other background tasks)
+        drop(self);
+
+        match tokio::time::timeout(flush_timeout, &mut outgoing_forward_task).await {
+            Ok(_) => log::debug!(
+                "{}Flushed outgoing messages and closed connection",
+                log_prefix
+            ),
+            Err(_) => {
+                log::warn!(
+                    "{}Could not send all queued outgoing messages within {:?}, closing connection anyway", log_prefix,
+                    flush_timeout
+                );
+                outgoing_forward_task.abort();
+            }
+        }
+    }
+
     fn transition_to_closed(self, cause: Error<T, L>) -> ConnectionLoopState<T, L> {
-        log::info!("Closing connection, cause: {}", cause);
+        log::info!("{}Closing connection, cause: {}", self.log_prefix, cause);
 
-        self.connection_incoming_tx
-            .send(ConnectionIncomingMessage::StateClosed {
-                cause: cause.clone(),
-            })
-            .ok();
+        if !self.close_reported {
+            self.connection_incoming_tx
+                .send(ConnectionIncomingMessage::StateClosed {
+                    cause: cause.clone(),
+                })
+                .ok();
+        }
 
         // the shutdown notify is invoked via the Drop implementation
 
@@ -475,6 +687,39 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopOpenState<T, L> {
     }
 }
 
+impl<T: Transport, L: LoginCredentials> ConnectionLoopOpenState<T, L> {
+    /// Twitch answers `PING <token>` with `PONG tmi.twitch.tv <token>`. Only a PONG carrying
+    /// the token of the outstanding PING counts, so unsolicited or late PONGs (answering an
+    /// older PING) cannot keep a dead connection alive.
+    fn on_pong(&mut self, token: Option<&str>) {
+        match &self.pending_ping {
+            Some((expected_token, sent_at)) if token == Some(expected_token.as_str()) => {
+                let rtt = sent_at.elapsed();
+                log::trace!(
+                    "{}Received pong, round-trip time: {:?}",
+                    self.log_prefix,
+                    rtt
+                );
+                #[cfg(feature = "metrics-collection")]
+                if let Some(ref metrics_identifier) = self.config.metrics_identifier {
+                    metrics::histogram!(
+                        "twitch_irc_ping_rtt_seconds",
+                        rtt.as_secs_f64(),
+                        "client" => metrics_identifier.clone()
+                    );
+                }
+                self.pong_received = true;
+                self.pending_ping = None;
+            }
+            _ => log::trace!(
+                "{}Ignoring unmatched pong with token {:?}",
+                self.log_prefix,
+                token
+            ),
+        }
+    }
+}
+
 impl<T: Transport, L: LoginCredentials> Drop for ConnectionLoopOpenState<T, L> {
     fn drop(&mut self) {
         self.kill_incoming_loop_tx.take().unwrap().send(()).ok();
@@ -490,7 +735,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
         message: IRCMessage,
         reply_sender: Option<Sender<Result<(), Error<T, L>>>>,
     ) {
-        log::trace!("> {}", message.as_raw_irc());
+        log::trace!("{}> {}", self.log_prefix, message.as_raw_irc());
+        if let Some(ref on_outgoing_message) = self.config.on_outgoing_message {
+            on_outgoing_message.observe(&message);
+        }
         #[cfg(feature = "metrics-collection")]
         if let Some(ref metrics_identifier) = self.config.metrics_identifier {
             metrics::counter!(
@@ -521,18 +769,41 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     ) -> ConnectionLoopState<T, L> {
         match maybe_message {
             None => {
-                log::info!("EOF received from transport incoming stream");
+                log::info!(
+                    "{}EOF received from transport incoming stream",
+                    self.log_prefix
+                );
                 self.transition_to_closed(Error::RemoteUnexpectedlyClosedConnection)
             }
             Some(Err(error)) => {
-                log::error!("Error received from transport incoming stream: {}", error);
+                log::error!(
+                    "{}Error received from transport incoming stream: {}",
+                    self.log_prefix,
+                    error
+                );
                 self.transition_to_closed(error)
             }
             Some(Ok(irc_message)) => {
+                // 001 is the welcome message (RPL_WELCOME) after logging in. GLOBALUSERSTATE
+                // is only sent to logged-in (non-anonymous) users.
+                if irc_message.command == "001" || irc_message.command == "GLOBALUSERSTATE" {
+                    self.handshake_completed = true;
+                }
+                if irc_message.command != "PING" && irc_message.command != "PONG" {
+                    self.last_message_received_at = Instant::now();
+                }
+
                 // Note! An error here (failing to parse to a ServerMessage) will not result
                 // in a connection abort. This is by design. See for example
                 // https://github.com/robotty/dank-twitch-irc/issues/22.
                 // The message will just be ignored instead
+                //
+                // Parsing happens here (and not in the incoming forward task) on purpose:
+                // messages are handled strictly in order, so a PING that arrives behind a
+                // burst of messages is answered only once everything before it has been
+                // processed, no matter which task does the parsing. Moving the parsing to
+                // the forward task was measured to make PING answers slower under load
+                // (the forward task then becomes the bottleneck).
                 let server_message = ServerMessage::try_from(irc_message);
 
                 match server_message {
@@ -546,22 +817,34 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                         // handle message
                         // react to PING, PONG and RECONNECT
                         match &server_message {
-                            ServerMessage::Ping(_) => {
-                                self.send_message(irc!["PONG", "tmi.twitch.tv"], None);
+                            ServerMessage::Ping(ping) => {
+                                self.send_message(ping.to_pong(), None);
                             }
-                            ServerMessage::Pong(_) => {
-                                log::trace!("Received pong");
-                                self.pong_received = true;
+                            ServerMessage::Pong(pong) => {
+                                self.on_pong(pong.source.params.last().map(String::as_str));
                             }
                             ServerMessage::Reconnect(_) => {
-                                // disconnect
-                                return self.transition_to_closed(Error::ReconnectCmd);
+                                if self.config.reconnect_grace_period.is_zero() {
+                                    // disconnect
+                                    return self.transition_to_closed(Error::ReconnectCmd);
+                                } else if !self.close_reported {
+                                    // Let the client replace this connection right away, but
+                                    // keep forwarding the messages Twitch still sends us until
+                                    // the client drops this connection after the grace period.
+                                    log::info!("{}Received RECONNECT, keeping connection open for the grace period", self.log_prefix);
+                                    self.connection_incoming_tx
+                                        .send(ConnectionIncomingMessage::StateClosed {
+                                            cause: Error::ReconnectCmd,
+                                        })
+                                        .ok();
+                                    self.close_reported = true;
+                                }
                             }
                             _ => {}
                         }
                     }
                     Err(parse_error) => {
-                        log::error!("Failed to parse incoming message as ServerMessage (emitting as generic instead): {}", parse_error);
+                        log::error!("{}Failed to parse incoming message as ServerMessage (emitting as generic instead): {}", self.log_prefix, parse_error);
                         self.connection_incoming_tx
                             .send(ConnectionIncomingMessage::IncomingMessage(
                                 ServerMessage::new_generic(IRCMessage::from(parse_error)),
@@ -577,8 +860,11 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     }
 
     fn send_ping(&mut self) {
+        self.pings_sent += 1;
+        let token = format!("twitch-irc-{}", self.pings_sent);
         self.pong_received = false;
-        self.send_message(irc!["PING", "tmi.twitch.tv"], None);
+        self.pending_ping = Some((token.clone(), Instant::now()));
+        self.send_message(irc!["PING", token], None);
     }
 
     fn check_pong(self) -> ConnectionLoopState<T, L> {
@@ -590,6 +876,32 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
             ConnectionLoopState::Open(self)
         }
     }
+
+    fn check_idle(self) -> ConnectionLoopState<T, L> {
+        let idle = matches!(
+            self.config.reconnect_if_idle,
+            Some(reconnect_if_idle) if self.last_message_received_at.elapsed() >= reconnect_if_idle
+        );
+        if idle {
+            self.transition_to_closed(Error::IdleTimeout)
+        } else {
+            // stay open
+            ConnectionLoopState::Open(self)
+        }
+    }
+
+    fn force_close(self) -> ConnectionLoopState<T, L> {
+        self.transition_to_closed(Error::ForcedReconnect)
+    }
+
+    fn check_handshake(self) -> ConnectionLoopState<T, L> {
+        if !self.handshake_completed {
+            self.transition_to_closed(Error::HandshakeTimeout)
+        } else {
+            // stay open
+            ConnectionLoopState::Open(self)
+        }
+    }
 }
 
 //
@@ -639,4 +951,19 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
         // do nothing, stay closed
         ConnectionLoopState::Closed(self)
     }
+
+    fn check_idle(self) -> ConnectionLoopState<T, L> {
+        // do nothing, stay closed
+        ConnectionLoopState::Closed(self)
+    }
+
+    fn force_close(self) -> ConnectionLoopState<T, L> {
+        // do nothing, stay closed
+        ConnectionLoopState::Closed(self)
+    }
+
+    fn check_handshake(self) -> ConnectionLoopState<T, L> {
+        // do nothing, stay closed
+        ConnectionLoopState::Closed(self)
+    }
 }
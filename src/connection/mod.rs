@@ -6,17 +6,15 @@ use crate::error::Error;
 use crate::login::LoginCredentials;
 use crate::message::commands::ServerMessage;
 use crate::transport::Transport;
+use futures_util::future::{BoxFuture, Shared};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
 pub enum ConnectionIncomingMessage<T: Transport, L: LoginCredentials> {
     IncomingMessage(ServerMessage),
-    #[cfg(feature = "metrics-collection")]
-    StateOpen,
-    StateClosed {
-        cause: Error<T, L>,
-    },
+    StateOpen { user_login: String },
+    StateClosed { cause: Error<T, L> },
 }
 
 pub(crate) struct Connection<T: Transport, L: LoginCredentials> {
@@ -27,8 +25,19 @@ pub(crate) struct Connection<T: Transport, L: LoginCredentials> {
 impl<T: Transport, L: LoginCredentials> Connection<T, L> {
     /// makes a tuple with the incoming messages and the `Connection` handle for outgoing
     /// messages.
+    ///
+    /// If `read_only` is set, the connection logs in with `config.read_credentials` (if
+    /// configured) instead of fetching credentials from `config.login_credentials`.
+    ///
+    /// If `before_connect` is given, the connection waits for that future to complete before
+    /// it starts connecting.
+    ///
+    /// The `connection_id` is only used in the log messages of this connection.
     pub fn new(
         config: Arc<ClientConfig<L>>,
+        connection_id: usize,
+        read_only: bool,
+        before_connect: Option<Shared<BoxFuture<'static, ()>>>,
     ) -> (
         mpsc::UnboundedReceiver<ConnectionIncomingMessage<T, L>>,
         Connection<T, L>,
@@ -37,8 +46,16 @@ impl<T: Transport, L: LoginCredentials> Connection<T, L> {
         let (connection_incoming_tx, connection_incoming_rx) = mpsc::unbounded_channel();
         let connection_loop_tx = Arc::new(connection_loop_tx);
 
+        let log_prefix = match &config.log_identifier {
+            Some(log_identifier) => format!("[{}, connection {}] ", log_identifier, connection_id),
+            None => format!("[connection {}] ", connection_id),
+        };
+
         ConnectionLoopWorker::spawn(
             config,
+            Arc::from(log_prefix),
+            read_only,
+            before_connect,
             connection_incoming_tx,
             Arc::downgrade(&connection_loop_tx),
             connection_loop_rx,
@@ -1,5 +1,5 @@
 use crate::login::LoginCredentials;
-use crate::message::IRCParseError;
+use crate::message::{ChatCommandError, IRCParseError};
 use crate::transport::Transport;
 use std::sync::Arc;
 use thiserror::Error;
@@ -34,6 +34,29 @@ pub enum Error<T: Transport, L: LoginCredentials> {
     /// Remote server unexpectedly closed connection
     #[error("Remote server unexpectedly closed connection")]
     RemoteUnexpectedlyClosedConnection,
+    /// Server did not greet us in time after logging in
+    #[error("Server did not send a welcome message in time after logging in")]
+    HandshakeTimeout,
+    /// No messages other than PING and PONG were received within `ClientConfig::reconnect_if_idle`
+    #[error("No messages were received on the connection for too long")]
+    IdleTimeout,
+    /// Connection was closed because a reconnect was requested via `force_reconnect()`
+    #[error("Connection was closed because a reconnect was requested via force_reconnect()")]
+    ForcedReconnect,
+    /// There is no connection with the ID given to `send_on_connection()` in the pool
+    #[error("There is no pool connection with ID {0}")]
+    ConnectionNotFound(usize),
+    /// No joined channel with the given ID is known (see `channel_login_by_id()`)
+    #[error("No joined channel with ID {0} is known")]
+    UnknownChannelId(String),
+    /// A chat command given to `run_command()` (or e.g. `ban()`) has an unusable argument, or
+    /// the channel login is not valid
+    #[error("Chat command was not sent: {0}")]
+    InvalidChatCommand(ChatCommandError),
+    /// The client stopped replacing failed connections (see
+    /// `ClientConfig::max_reconnect_attempts`). Holds the cause of the last failure.
+    #[error("Gave up reconnecting after too many failed connections, last error: {}", .0.to_string())]
+    GaveUpReconnecting(Box<Error<T, L>>),
 }
 
 impl<T: Transport, L: LoginCredentials> Clone for Error<T, L> {
@@ -48,6 +71,13 @@ impl<T: Transport, L: LoginCredentials> Clone for Error<T, L> {
             Error::ReconnectCmd => Error::ReconnectCmd,
             Error::PingTimeout => Error::PingTimeout,
             Error::RemoteUnexpectedlyClosedConnection => Error::RemoteUnexpectedlyClosedConnection,
+            Error::ForcedReconnect => Error::ForcedReconnect,
+            Error::IdleTimeout => Error::IdleTimeout,
+            Error::HandshakeTimeout => Error::HandshakeTimeout,
+            Error::ConnectionNotFound(id) => Error::ConnectionNotFound(*id),
+            Error::UnknownChannelId(id) => Error::UnknownChannelId(id.clone()),
+            Error::InvalidChatCommand(e) => Error::InvalidChatCommand(e.clone()),
+            Error::GaveUpReconnecting(e) => Error::GaveUpReconnecting(e.clone()),
         }
     }
 }
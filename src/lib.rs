@@ -86,11 +86,11 @@
 //!
 //! ```no_run
 //! # use twitch_irc::message::ServerMessage;
-//! # use tokio::sync::mpsc;
+//! # use twitch_irc::IncomingMessages;
 //! #
 //! # #[tokio::main]
 //! # async fn main() {
-//! # let mut incoming_messages: mpsc::UnboundedReceiver<ServerMessage> = unimplemented!();
+//! # let mut incoming_messages: IncomingMessages = unimplemented!();
 //! while let Some(message) = incoming_messages.recv().await {
 //!      match message {
 //!          ServerMessage::Privmsg(msg) => {
@@ -110,18 +110,16 @@
 //! `twitch_irc` ships with [`StaticLoginCredentials`](login/struct.StaticLoginCredentials.html)
 //! and [`RefreshingLoginCredentials`](login/struct.RefreshingLoginCredentials.html).
 //!
-//! For simple cases, `StaticLoginCredentials` fulfills all needs:
+//! For simple cases, `StaticLoginCredentials` fulfills all needs. The easiest way to use them
+//! is [`ClientConfig::new_with_token`](crate::ClientConfig::new_with_token):
 //!
 //! ```
-//! use twitch_irc::login::StaticLoginCredentials;
 //! use twitch_irc::ClientConfig;
 //!
 //! let login_name = "your_bot_name".to_owned();
 //! let oauth_token = "u0i05p6kbswa1w72wu1h1skio3o20t".to_owned();
 //!
-//! let config = ClientConfig::new_simple(
-//!     StaticLoginCredentials::new(login_name, Some(oauth_token))
-//! );
+//! let config = ClientConfig::new_with_token(login_name, oauth_token);
 //! ```
 //!
 //! However for most applications it is strongly recommended to have your login token automatically
@@ -191,11 +189,16 @@
 //! # Close the client
 //!
 //! To close the client, drop all clones of the `TwitchIRCClient` handle. The client will shut down
-//! and end the stream of incoming messages once all processing is done.
+//! and end the stream of incoming messages once all processing is done. Messages that were
+//! already queued up for sending are still sent out before the connections are closed
+//! (see `ClientConfig::shutdown_flush_timeout`).
 //!
 //! # Feature flags
 //!
 //! This library has these optional feature toggles:
+//! * **`client`** enables `TwitchIRCClient` and everything needed to run it (`ClientConfig`,
+//!   the `login` and `transport` modules), and pulls in `tokio`. All the transport features,
+//!   `refreshing-token` and `metrics-collection` enable this feature.
 //! * **`transport-tcp`** enables `PlainTCPTransport`, to connect using a plain-text TLS socket
 //!   using the normal IRC protocol.
 //!     * `transport-tcp-native-tls` enables `SecureTCPTransport` which will then use OS-native
@@ -221,22 +224,56 @@
 //!   documentation on `ClientConfig` for details.
 //! * **`with-serde`** pulls in `serde` v1.0 and adds `#[derive(Serialize, Deserialize)]` to many
 //!   structs.
+//! * **`with-chrono`** pulls in `chrono` v0.4 and adds the `server_timestamp` fields
+//!   (`DateTime<Utc>`) to the messages that carry a timestamp. Without it, the timestamps are
+//!   available as `SystemTime` using `ServerMessage::server_time()`. `refreshing-token`
+//!   enables this feature.
+//! * **`with-bytes`** pulls in `bytes` v1.0 and adds `ServerMessage::to_canonical_bytes()`.
+//!
+//! By default, `client`, `transport-tcp`, `transport-tcp-native-tls` and `with-chrono`
+//! are enabled.
+//!
+//! # Parsing messages only
+//!
+//! If you only want to use the message parser (e.g. to process archived chat logs), you can
+//! disable the default features. Only the [`message`](crate::message) module is then available,
+//! and the library can be used without `tokio` or any other async runtime:
+//!
+//! ```toml
+//! [dependencies]
+//! twitch-irc = { version = "3", default-features = false }
+//! ```
 //!
-//! By default, `transport-tcp` and `transport-tcp-native-tls` are enabled.
+//! Enable `with-serde` in addition to this if you want to serialize the parsed messages, and
+//! `with-chrono` if you want the timestamps of messages as `chrono::DateTime`.
 //!
 //! [rustls]: https://github.com/ctz/rustls
 //! [mozilla-roots]: https://github.com/ctz/webpki-roots
 
+#[cfg(feature = "client")]
 mod client;
+#[cfg(feature = "client")]
 mod config;
+#[cfg(feature = "client")]
 mod connection;
+#[cfg(feature = "client")]
 mod error;
+#[cfg(feature = "client")]
 pub mod login;
 pub mod message;
+#[cfg(feature = "client")]
 pub mod transport;
 
-pub use client::TwitchIRCClient;
-pub use config::ClientConfig;
+#[cfg(feature = "client")]
+pub use client::{
+    ConnectionState, ConnectionStatus, IncomingMessages, ReconnectBackoff, TwitchIRCClient,
+};
+#[cfg(feature = "client")]
+pub use config::{
+    AddressFamily, ClientConfig, MembershipMode, OutgoingMessageHook, OverflowPolicy, RawLineHook,
+    ReconnectHook, SendFailure, SendFailureHook,
+};
+#[cfg(feature = "client")]
 pub use error::Error;
 
 #[cfg(feature = "transport-tcp")]
@@ -10,6 +10,13 @@ use {chrono::DateTime, chrono::Utc, std::time::Duration, thiserror::Error, tokio
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
 
+/// The prefix Twitch expects in front of the OAuth token in the `PASS` command.
+pub const TWITCH_TOKEN_PREFIX: &str = "oauth:";
+
+fn default_token_prefix() -> String {
+    TWITCH_TOKEN_PREFIX.to_owned()
+}
+
 /// A pair of login name and OAuth token.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
@@ -20,6 +27,30 @@ pub struct CredentialsPair {
     /// If `None`, then no password will be sent to the server at all (for anonymous
     /// credentials).
     pub token: Option<String>,
+    /// Prefix that is put in front of the `token` to form the `PASS` sent to the server.
+    /// Twitch expects this to be `oauth:` (`TWITCH_TOKEN_PREFIX`), which is the default.
+    /// Servers using a different authentication scheme may need another value, e.g. an
+    /// empty string to send the `token` as-is.
+    #[cfg_attr(feature = "with-serde", serde(default = "default_token_prefix"))]
+    pub token_prefix: String,
+}
+
+impl CredentialsPair {
+    /// Create a new credentials pair with the Twitch default `oauth:` token prefix.
+    pub fn new(login: String, token: Option<String>) -> CredentialsPair {
+        CredentialsPair {
+            login,
+            token,
+            token_prefix: default_token_prefix(),
+        }
+    }
+
+    /// The value sent with the `PASS` command, `None` if no token is set.
+    pub fn pass(&self) -> Option<String> {
+        self.token
+            .as_ref()
+            .map(|token| format!("{}{}", self.token_prefix, token))
+    }
 }
 
 /// Encapsulates logic for getting the credentials to log into chat, whenever
@@ -47,7 +78,7 @@ impl StaticLoginCredentials {
     /// The `token` should be without the `oauth:` prefix.
     pub fn new(login: String, token: Option<String>) -> StaticLoginCredentials {
         StaticLoginCredentials {
-            credentials: CredentialsPair { login, token },
+            credentials: CredentialsPair::new(login, token),
         }
     }
 
@@ -248,9 +279,9 @@ impl<S: TokenStorage> LoginCredentials for RefreshingLoginCredentials<S> {
                 .map_err(RefreshingLoginError::UpdateError)?;
         }
 
-        Ok(CredentialsPair {
-            login: self.user_login.clone(),
-            token: Some(current_token.access_token.clone()),
-        })
+        Ok(CredentialsPair::new(
+            self.user_login.clone(),
+            Some(current_token.access_token.clone()),
+        ))
     }
 }
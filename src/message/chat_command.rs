@@ -0,0 +1,417 @@
+use crate::message::{IRCMessage, InvalidChannelLoginError};
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(feature = "with-serde")]
+use {serde::Deserialize, serde::Serialize};
+
+/// A Twitch chat command, such as `/ban` or `/slow`.
+///
+/// Chat commands are sent to Twitch as a `PRIVMSG` whose text is the command, e.g.
+/// `PRIVMSG #pajlada :/timeout weeb123 600 spam`. The `Display` implementation of this type
+/// produces that message text, and `to_irc_message()` builds the complete `PRIVMSG`.
+///
+/// The arguments of a command are checked by `validate()` before it is sent: user logins and
+/// message IDs must be non-empty and must not contain whitespace, and reasons must be
+/// non-empty and must not contain line breaks. Otherwise, an argument like `"weeb123\r\nJOIN #forsen"`
+/// would end the `PRIVMSG` early and be sent as a second IRC command.
+///
+/// Use `TwitchIRCClient::run_command()` to send a command to a channel.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::{AsRawIRC, ChatCommand};
+/// use std::time::Duration;
+///
+/// let command = ChatCommand::Timeout {
+///     user_login: "weeb123".to_owned(),
+///     duration: Duration::from_secs(600),
+///     reason: Some("spam".to_owned()),
+/// };
+/// assert_eq!(command.to_string(), "/timeout weeb123 600 spam");
+/// assert_eq!(
+///     command.to_irc_message("pajlada").unwrap().as_raw_irc(),
+///     "PRIVMSG #pajlada :/timeout weeb123 600 spam"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum ChatCommand {
+    /// Permanently ban a user from the channel (`/ban`).
+    Ban {
+        /// Login name of the user to ban
+        user_login: String,
+        /// Optional reason for the ban
+        reason: Option<String>,
+    },
+    /// Lift a permanent ban (`/unban`).
+    Unban {
+        /// Login name of the user to unban
+        user_login: String,
+    },
+    /// Temporarily ban (time out) a user from the channel (`/timeout`).
+    Timeout {
+        /// Login name of the user to time out
+        user_login: String,
+        /// How long the user is timed out for. Only whole seconds are sent to Twitch.
+        duration: Duration,
+        /// Optional reason for the timeout
+        reason: Option<String>,
+    },
+    /// Remove a timeout from a user (`/untimeout`).
+    Untimeout {
+        /// Login name of the user whose timeout should be removed
+        user_login: String,
+    },
+    /// Delete a single message from chat (`/delete`).
+    Delete {
+        /// ID of the message to delete, see `PrivmsgMessage::message_id`
+        message_id: String,
+    },
+    /// Clear the entire chat history of the channel (`/clear`).
+    Clear,
+    /// Enable slow mode: users have to wait this long between messages (`/slow`).
+    /// Only whole seconds are sent to Twitch.
+    Slow(Duration),
+    /// Disable slow mode (`/slowoff`).
+    SlowOff,
+    /// Enable followers-only mode: only users that have been following the channel for at least
+    /// the given duration may chat (`/followers`). Only whole minutes are sent to Twitch,
+    /// a duration of zero allows all followers to chat.
+    Followers(Duration),
+    /// Disable followers-only mode (`/followersoff`).
+    FollowersOff,
+    /// Enable emote-only mode (`/emoteonly`).
+    EmoteOnly,
+    /// Disable emote-only mode (`/emoteonlyoff`).
+    EmoteOnlyOff,
+    /// Enable subscribers-only mode (`/subscribers`).
+    Subscribers,
+    /// Disable subscribers-only mode (`/subscribersoff`).
+    SubscribersOff,
+    /// Enable unique-chat (formerly r9k) mode (`/uniquechat`).
+    UniqueChat,
+    /// Disable unique-chat mode (`/uniquechatoff`).
+    UniqueChatOff,
+    /// Grant moderator status to a user (`/mod`).
+    Mod {
+        /// Login name of the user to mod
+        user_login: String,
+    },
+    /// Revoke moderator status from a user (`/unmod`).
+    Unmod {
+        /// Login name of the user to unmod
+        user_login: String,
+    },
+    /// Grant VIP status to a user (`/vip`).
+    Vip {
+        /// Login name of the user to make a VIP
+        user_login: String,
+    },
+    /// Revoke VIP status from a user (`/unvip`).
+    Unvip {
+        /// Login name of the user to remove VIP status from
+        user_login: String,
+    },
+}
+
+/// Error returned when a `ChatCommand` cannot be sent, see `ChatCommand::validate()`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ChatCommandError {
+    /// The channel login given to `ChatCommand::to_irc_message()` is not usable.
+    #[error("{0}")]
+    InvalidChannelLogin(#[from] InvalidChannelLoginError),
+    /// An argument of the command is empty or contains characters that are not allowed in it.
+    #[error("Invalid chat command argument `{0}`")]
+    InvalidArgument(String),
+}
+
+fn validate_login_argument(argument: &str) -> Result<(), ChatCommandError> {
+    if argument.is_empty() || argument.chars().any(char::is_whitespace) {
+        return Err(ChatCommandError::InvalidArgument(argument.to_owned()));
+    }
+
+    Ok(())
+}
+
+fn validate_reason_argument(argument: &Option<String>) -> Result<(), ChatCommandError> {
+    match argument {
+        Some(reason) if reason.is_empty() || reason.contains(&['\r', '\n'][..]) => {
+            Err(ChatCommandError::InvalidArgument(reason.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl ChatCommand {
+    /// Check that the arguments of this command can be sent to Twitch without changing the
+    /// meaning of the command: user logins and message IDs must be non-empty and must not
+    /// contain whitespace, and reasons must be non-empty and must not contain line breaks.
+    pub fn validate(&self) -> Result<(), ChatCommandError> {
+        match self {
+            ChatCommand::Ban { user_login, reason }
+            | ChatCommand::Timeout {
+                user_login, reason, ..
+            } => {
+                validate_login_argument(user_login)?;
+                validate_reason_argument(reason)
+            }
+            ChatCommand::Unban { user_login }
+            | ChatCommand::Untimeout { user_login }
+            | ChatCommand::Mod { user_login }
+            | ChatCommand::Unmod { user_login }
+            | ChatCommand::Vip { user_login }
+            | ChatCommand::Unvip { user_login } => validate_login_argument(user_login),
+            ChatCommand::Delete { message_id } => validate_login_argument(message_id),
+            _ => Ok(()),
+        }
+    }
+
+    /// Create the `PRIVMSG` that runs this command in the channel `channel_login`.
+    ///
+    /// The `channel_login` is validated the same way as for
+    /// [`IRCMessage::new_privmsg`](IRCMessage::new_privmsg), and the arguments of the command
+    /// are checked using `validate()`.
+    pub fn to_irc_message(&self, channel_login: &str) -> Result<IRCMessage, ChatCommandError> {
+        self.validate()?;
+        Ok(IRCMessage::new_privmsg(channel_login, &self.to_string())?)
+    }
+}
+
+impl fmt::Display for ChatCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatCommand::Ban { user_login, reason } => match reason {
+                Some(reason) => write!(f, "/ban {} {}", user_login, reason),
+                None => write!(f, "/ban {}", user_login),
+            },
+            ChatCommand::Unban { user_login } => write!(f, "/unban {}", user_login),
+            ChatCommand::Timeout {
+                user_login,
+                duration,
+                reason,
+            } => match reason {
+                Some(reason) => write!(
+                    f,
+                    "/timeout {} {} {}",
+                    user_login,
+                    duration.as_secs(),
+                    reason
+                ),
+                None => write!(f, "/timeout {} {}", user_login, duration.as_secs()),
+            },
+            ChatCommand::Untimeout { user_login } => write!(f, "/untimeout {}", user_login),
+            ChatCommand::Delete { message_id } => write!(f, "/delete {}", message_id),
+            ChatCommand::Clear => f.write_str("/clear"),
+            ChatCommand::Slow(duration) => write!(f, "/slow {}", duration.as_secs()),
+            ChatCommand::SlowOff => f.write_str("/slowoff"),
+            ChatCommand::Followers(duration) => {
+                write!(f, "/followers {}m", duration.as_secs() / 60)
+            }
+            ChatCommand::FollowersOff => f.write_str("/followersoff"),
+            ChatCommand::EmoteOnly => f.write_str("/emoteonly"),
+            ChatCommand::EmoteOnlyOff => f.write_str("/emoteonlyoff"),
+            ChatCommand::Subscribers => f.write_str("/subscribers"),
+            ChatCommand::SubscribersOff => f.write_str("/subscribersoff"),
+            ChatCommand::UniqueChat => f.write_str("/uniquechat"),
+            ChatCommand::UniqueChatOff => f.write_str("/uniquechatoff"),
+            ChatCommand::Mod { user_login } => write!(f, "/mod {}", user_login),
+            ChatCommand::Unmod { user_login } => write!(f, "/unmod {}", user_login),
+            ChatCommand::Vip { user_login } => write!(f, "/vip {}", user_login),
+            ChatCommand::Unvip { user_login } => write!(f, "/unvip {}", user_login),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{AsRawIRC, ChatCommand, ChatCommandError, InvalidChannelLoginError};
+    use std::time::Duration;
+
+    fn serialize(command: ChatCommand) -> String {
+        command.to_irc_message("pajlada").unwrap().as_raw_irc()
+    }
+
+    #[test]
+    fn test_user_commands() {
+        assert_eq!(
+            serialize(ChatCommand::Ban {
+                user_login: "weeb123".to_owned(),
+                reason: None
+            }),
+            "PRIVMSG #pajlada :/ban weeb123"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Ban {
+                user_login: "weeb123".to_owned(),
+                reason: Some("being a weeb".to_owned())
+            }),
+            "PRIVMSG #pajlada :/ban weeb123 being a weeb"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Unban {
+                user_login: "weeb123".to_owned()
+            }),
+            "PRIVMSG #pajlada :/unban weeb123"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Timeout {
+                user_login: "fabzeef".to_owned(),
+                duration: Duration::from_millis(600_500),
+                reason: None
+            }),
+            "PRIVMSG #pajlada :/timeout fabzeef 600"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Timeout {
+                user_login: "fabzeef".to_owned(),
+                duration: Duration::from_secs(1),
+                reason: Some("spam".to_owned())
+            }),
+            "PRIVMSG #pajlada :/timeout fabzeef 1 spam"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Untimeout {
+                user_login: "fabzeef".to_owned()
+            }),
+            "PRIVMSG #pajlada :/untimeout fabzeef"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Delete {
+                message_id: "3c92014f-340a-4dc3-a9c9-e5cf182f4a84".to_owned()
+            }),
+            "PRIVMSG #pajlada :/delete 3c92014f-340a-4dc3-a9c9-e5cf182f4a84"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Mod {
+                user_login: "randers".to_owned()
+            }),
+            "PRIVMSG #pajlada :/mod randers"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Unmod {
+                user_login: "randers".to_owned()
+            }),
+            "PRIVMSG #pajlada :/unmod randers"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Vip {
+                user_login: "randers".to_owned()
+            }),
+            "PRIVMSG #pajlada :/vip randers"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Unvip {
+                user_login: "randers".to_owned()
+            }),
+            "PRIVMSG #pajlada :/unvip randers"
+        );
+    }
+
+    #[test]
+    fn test_room_mode_commands() {
+        assert_eq!(serialize(ChatCommand::Clear), "PRIVMSG #pajlada /clear");
+        assert_eq!(
+            serialize(ChatCommand::Slow(Duration::from_secs(30))),
+            "PRIVMSG #pajlada :/slow 30"
+        );
+        assert_eq!(serialize(ChatCommand::SlowOff), "PRIVMSG #pajlada /slowoff");
+        assert_eq!(
+            serialize(ChatCommand::Followers(Duration::from_secs(10 * 60 + 59))),
+            "PRIVMSG #pajlada :/followers 10m"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Followers(Duration::from_secs(0))),
+            "PRIVMSG #pajlada :/followers 0m"
+        );
+        assert_eq!(
+            serialize(ChatCommand::FollowersOff),
+            "PRIVMSG #pajlada /followersoff"
+        );
+        assert_eq!(
+            serialize(ChatCommand::EmoteOnly),
+            "PRIVMSG #pajlada /emoteonly"
+        );
+        assert_eq!(
+            serialize(ChatCommand::EmoteOnlyOff),
+            "PRIVMSG #pajlada /emoteonlyoff"
+        );
+        assert_eq!(
+            serialize(ChatCommand::Subscribers),
+            "PRIVMSG #pajlada /subscribers"
+        );
+        assert_eq!(
+            serialize(ChatCommand::SubscribersOff),
+            "PRIVMSG #pajlada /subscribersoff"
+        );
+        assert_eq!(
+            serialize(ChatCommand::UniqueChat),
+            "PRIVMSG #pajlada /uniquechat"
+        );
+        assert_eq!(
+            serialize(ChatCommand::UniqueChatOff),
+            "PRIVMSG #pajlada /uniquechatoff"
+        );
+    }
+
+    #[test]
+    fn test_invalid_channel() {
+        assert_eq!(
+            ChatCommand::Clear.to_irc_message("#pajlada"),
+            Err(ChatCommandError::InvalidChannelLogin(
+                InvalidChannelLoginError("#pajlada".to_owned())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let invalid_commands = vec![
+            ChatCommand::Ban {
+                user_login: "weeb123\r\nJOIN #forsen".to_owned(),
+                reason: None,
+            },
+            ChatCommand::Ban {
+                user_login: "weeb123".to_owned(),
+                reason: Some("spam\nPRIVMSG #forsen :hi".to_owned()),
+            },
+            ChatCommand::Ban {
+                user_login: "weeb123".to_owned(),
+                reason: Some("".to_owned()),
+            },
+            ChatCommand::Timeout {
+                user_login: "weeb123 1".to_owned(),
+                duration: Duration::from_secs(600),
+                reason: None,
+            },
+            ChatCommand::Timeout {
+                user_login: "weeb123".to_owned(),
+                duration: Duration::from_secs(600),
+                reason: Some("spam\r".to_owned()),
+            },
+            ChatCommand::Unban {
+                user_login: "".to_owned(),
+            },
+            ChatCommand::Vip {
+                user_login: "randers\n".to_owned(),
+            },
+            ChatCommand::Delete {
+                message_id: "".to_owned(),
+            },
+        ];
+
+        for command in invalid_commands {
+            assert!(
+                matches!(
+                    command.to_irc_message("pajlada"),
+                    Err(ChatCommandError::InvalidArgument(_))
+                ),
+                "{:?} should be rejected",
+                command
+            );
+        }
+    }
+}
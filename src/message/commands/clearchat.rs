@@ -1,8 +1,11 @@
 use crate::message::commands::IRCMessageParseExt;
 use crate::message::{IRCMessage, ServerMessageParseError};
+#[cfg(feature = "with-chrono")]
 use chrono::{DateTime, Utc};
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "with-serde")]
@@ -22,10 +25,20 @@ pub struct ClearChatMessage {
     /// chat being cleared. See `ClearChatAction` for details
     pub action: ClearChatAction,
     /// The time when the Twitch IRC server created this message
-    pub server_timestamp: DateTime<Utc>,
+    /// `None` if the `tmi-sent-ts` tag is missing or malformed.
+    ///
+    /// Only available with the `with-chrono` feature, see `server_timestamp_millis` for
+    /// an alternative.
+    #[cfg(feature = "with-chrono")]
+    pub server_timestamp: Option<DateTime<Utc>>,
+    /// Same as `server_timestamp`, as milliseconds since the Unix epoch (the value of the
+    /// `tmi-sent-ts` tag). `None` if the tag is missing or malformed.
+    ///
+    /// Unlike `server_timestamp`, this is also available without the `with-chrono` feature.
+    pub server_timestamp_millis: Option<i64>,
 
     /// The message that this `ClearChatMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 /// One of the three types of meaning a `CLEARCHAT` message can have.
@@ -102,15 +115,43 @@ impl TryFrom<IRCMessage> for ClearChatMessage {
             channel_login: source.try_get_channel_login()?.to_owned(),
             channel_id: source.try_get_nonempty_tag_value("room-id")?.to_owned(),
             action,
-            server_timestamp: source.try_get_timestamp("tmi-sent-ts")?,
-            source,
+            #[cfg(feature = "with-chrono")]
+            server_timestamp: source.get_optional_timestamp("tmi-sent-ts"),
+            server_timestamp_millis: source.get_optional_timestamp_millis("tmi-sent-ts"),
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<ClearChatMessage> for IRCMessage {
     fn from(msg: ClearChatMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line describing the action,
+/// e.g. `#pajlada fabzeef has been timed out for 1s`.
+impl fmt::Display for ClearChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            ClearChatAction::ChatCleared => {
+                write!(f, "#{} chat has been cleared", self.channel_login)
+            }
+            ClearChatAction::UserBanned { user_login, .. } => {
+                write!(f, "#{} {} has been banned", self.channel_login, user_login)
+            }
+            ClearChatAction::UserTimedOut {
+                user_login,
+                timeout_length,
+                ..
+            } => write!(
+                f,
+                "#{} {} has been timed out for {}s",
+                self.channel_login,
+                user_login,
+                timeout_length.as_secs()
+            ),
+        }
     }
 }
 
@@ -118,8 +159,10 @@ impl From<ClearChatMessage> for IRCMessage {
 mod tests {
     use crate::message::commands::clearchat::ClearChatAction;
     use crate::message::{ClearChatMessage, IRCMessage};
+    #[cfg(feature = "with-chrono")]
     use chrono::{TimeZone, Utc};
     use std::convert::TryFrom;
+    use std::sync::Arc;
     use std::time::Duration;
 
     #[test]
@@ -138,8 +181,10 @@ mod tests {
                     user_id: "148973258".to_owned(),
                     timeout_length: Duration::from_secs(1)
                 },
-                server_timestamp: Utc.timestamp_millis(1594553828245),
-                source: irc_message
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594553828245)),
+                server_timestamp_millis: Some(1594553828245),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -159,8 +204,10 @@ mod tests {
                     user_login: "weeb123".to_owned(),
                     user_id: "70948394".to_owned(),
                 },
-                server_timestamp: Utc.timestamp_millis(1594561360331),
-                source: irc_message
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594561360331)),
+                server_timestamp_millis: Some(1594561360331),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -177,9 +224,29 @@ mod tests {
                 channel_login: "randers".to_owned(),
                 channel_id: "40286300".to_owned(),
                 action: ClearChatAction::ChatCleared,
-                server_timestamp: Utc.timestamp_millis(1594561392337),
-                source: irc_message
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594561392337)),
+                server_timestamp_millis: Some(1594561392337),
+                source: Arc::new(irc_message)
             }
         )
     }
+
+    #[test]
+    pub fn test_display() {
+        let src = "@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef";
+        let msg = ClearChatMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.to_string(),
+            "#pajlada fabzeef has been timed out for 1s"
+        );
+
+        let src = "@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123";
+        let msg = ClearChatMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.to_string(), "#pajlada weeb123 has been banned");
+
+        let src = "@room-id=40286300;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #randers";
+        let msg = ClearChatMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.to_string(), "#randers chat has been cleared");
+    }
 }
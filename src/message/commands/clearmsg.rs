@@ -1,7 +1,10 @@
 use crate::message::commands::IRCMessageParseExt;
 use crate::message::{IRCMessage, ServerMessageParseError};
+#[cfg(feature = "with-chrono")]
 use chrono::{DateTime, Utc};
 use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -25,10 +28,20 @@ pub struct ClearMsgMessage {
     /// Whether the deleted message was an action (`/me`)
     pub is_action: bool,
     /// server timestamp for the time when the delete command was executed.
-    pub server_timestamp: DateTime<Utc>,
+    /// `None` if the `tmi-sent-ts` tag is missing or malformed.
+    ///
+    /// Only available with the `with-chrono` feature, see `server_timestamp_millis` for
+    /// an alternative.
+    #[cfg(feature = "with-chrono")]
+    pub server_timestamp: Option<DateTime<Utc>>,
+    /// Same as `server_timestamp`, as milliseconds since the Unix epoch (the value of the
+    /// `tmi-sent-ts` tag). `None` if the tag is missing or malformed.
+    ///
+    /// Unlike `server_timestamp`, this is also available without the `with-chrono` feature.
+    pub server_timestamp_millis: Option<i64>,
 
     /// The message that this `ClearMsgMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for ClearMsgMessage {
@@ -52,25 +65,41 @@ impl TryFrom<IRCMessage> for ClearMsgMessage {
             message_id: source
                 .try_get_nonempty_tag_value("target-msg-id")?
                 .to_owned(),
-            server_timestamp: source.try_get_timestamp("tmi-sent-ts")?,
+            #[cfg(feature = "with-chrono")]
+            server_timestamp: source.get_optional_timestamp("tmi-sent-ts"),
+            server_timestamp_millis: source.get_optional_timestamp_millis("tmi-sent-ts"),
             message_text: message_text.to_owned(),
             is_action,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<ClearMsgMessage> for IRCMessage {
     fn from(msg: ClearMsgMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line, e.g.
+/// `#pajlada message by randers has been deleted: test`.
+impl fmt::Display for ClearMsgMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} message by {} has been deleted: {}",
+            self.channel_login, self.sender_login, self.message_text
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::message::{ClearMsgMessage, IRCMessage};
+    #[cfg(feature = "with-chrono")]
     use chrono::{TimeZone, Utc};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_simple() {
@@ -86,8 +115,10 @@ mod tests {
                 message_id: "3c92014f-340a-4dc3-a9c9-e5cf182f4a84".to_owned(),
                 message_text: "NIGHT CUNT".to_owned(),
                 is_action: false,
-                server_timestamp: Utc.timestamp_millis(1594561955611),
-                source: irc_message
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594561955611)),
+                server_timestamp_millis: Some(1594561955611),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -106,8 +137,10 @@ mod tests {
                 message_id: "15e5164d-f8e6-4aec-baf4-2d6a330760c4".to_owned(),
                 message_text: "test".to_owned(),
                 is_action: true,
-                server_timestamp: Utc.timestamp_millis(1594562632383),
-                source: irc_message
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594562632383)),
+                server_timestamp_millis: Some(1594562632383),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -1,8 +1,8 @@
 use crate::message::commands::IRCMessageParseExt;
-use crate::message::twitch::{Badge, RGBColor};
+use crate::message::twitch::{Badge, EmoteSetId, RGBColor};
 use crate::message::{IRCMessage, ServerMessageParseError};
-use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -30,12 +30,14 @@ pub struct GlobalUserStateMessage {
     /// List of badges the logged in user has in all channels.
     pub badges: Vec<Badge>,
     /// List of emote set IDs the logged in user has available. This always contains at least 0.
-    pub emote_sets: HashSet<u64>,
+    ///
+    /// The list is sorted and contains no duplicates, see `parse_emote_sets()`.
+    pub emote_sets: Vec<EmoteSetId>,
     /// What name color the logged in user has chosen. The same color is used in all channels.
     pub name_color: Option<RGBColor>,
 
     /// The message that this `GlobalUserStateMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for GlobalUserStateMessage {
@@ -58,24 +60,23 @@ impl TryFrom<IRCMessage> for GlobalUserStateMessage {
             badges: source.try_get_badges("badges")?,
             emote_sets: source.try_get_emote_sets("emote-sets")?,
             name_color: source.try_get_color("color")?,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<GlobalUserStateMessage> for IRCMessage {
     fn from(msg: GlobalUserStateMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::twitch::{Badge, RGBColor};
+    use crate::message::twitch::{Badge, EmoteSetId, RGBColor};
     use crate::message::{GlobalUserStateMessage, IRCMessage};
-    use std::collections::HashSet;
     use std::convert::TryFrom;
-    use std::iter::FromIterator;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -90,13 +91,17 @@ mod tests {
                 user_name: "randers".to_owned(),
                 badge_info: vec![],
                 badges: vec![],
-                emote_sets: vec![0, 42, 237].into_iter().collect(),
+                emote_sets: vec![
+                    EmoteSetId::Numeric(0),
+                    EmoteSetId::Numeric(42),
+                    EmoteSetId::Numeric(237)
+                ],
                 name_color: Some(RGBColor {
                     r: 0x19,
                     g: 0xE6,
                     b: 0xE6
                 }),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -119,9 +124,9 @@ mod tests {
                     name: "premium".to_owned(),
                     version: "1".to_owned()
                 }],
-                emote_sets: HashSet::new(),
+                emote_sets: vec![],
                 name_color: None,
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -140,9 +145,9 @@ mod tests {
                 user_name: "randers811".to_owned(),
                 badge_info: vec![],
                 badges: vec![],
-                emote_sets: HashSet::from_iter(vec![0]),
+                emote_sets: vec![EmoteSetId::Numeric(0)],
                 name_color: None,
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -2,7 +2,9 @@ use crate::message::commands::IRCMessageParseExt;
 use crate::message::{IRCMessage, ServerMessageParseError};
 use itertools::Itertools;
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -18,7 +20,7 @@ pub struct HostTargetMessage {
     pub action: HostTargetAction,
 
     /// The message that this `HostTargetMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 /// The type of action that a `HOSTTARGET` signifies, either host mode was enabled (entered)
@@ -82,14 +84,34 @@ impl TryFrom<IRCMessage> for HostTargetMessage {
         Ok(HostTargetMessage {
             channel_login: source.try_get_channel_login()?.to_owned(),
             action,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<HostTargetMessage> for IRCMessage {
     fn from(msg: HostTargetMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line, e.g. `#randers is now hosting #leebaxd`
+/// or `#randers exited host mode`.
+impl fmt::Display for HostTargetMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            HostTargetAction::HostModeOn {
+                hosted_channel_login,
+                ..
+            } => write!(
+                f,
+                "#{} is now hosting #{}",
+                self.channel_login, hosted_channel_login
+            ),
+            HostTargetAction::HostModeOff { .. } => {
+                write!(f, "#{} exited host mode", self.channel_login)
+            }
+        }
     }
 }
 
@@ -98,6 +120,7 @@ mod tests {
     use crate::message::commands::hosttarget::HostTargetAction;
     use crate::message::{HostTargetMessage, IRCMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     fn test_fresh_host_on() {
@@ -113,7 +136,7 @@ mod tests {
                     hosted_channel_login: "leebaxd".to_owned(),
                     viewer_count: Some(0)
                 },
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
@@ -132,7 +155,7 @@ mod tests {
                     hosted_channel_login: "leebaxd".to_owned(),
                     viewer_count: None
                 },
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
@@ -150,7 +173,7 @@ mod tests {
                 action: HostTargetAction::HostModeOff {
                     viewer_count: Some(0)
                 },
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
@@ -166,7 +189,7 @@ mod tests {
             HostTargetMessage {
                 channel_login: "randers".to_owned(),
                 action: HostTargetAction::HostModeOff { viewer_count: None },
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
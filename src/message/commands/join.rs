@@ -1,6 +1,8 @@
 use crate::message::commands::{IRCMessageParseExt, ServerMessageParseError};
 use crate::message::IRCMessage;
 use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -16,7 +18,7 @@ pub struct JoinMessage {
     pub user_login: String,
 
     /// The message that this `JoinMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for JoinMessage {
@@ -30,14 +32,21 @@ impl TryFrom<IRCMessage> for JoinMessage {
         Ok(JoinMessage {
             channel_login: source.try_get_channel_login()?.to_owned(),
             user_login: source.try_get_prefix_nickname()?.to_owned(),
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<JoinMessage> for IRCMessage {
     fn from(msg: JoinMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line, e.g. `#pajlada randers811 joined`.
+impl fmt::Display for JoinMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} joined", self.channel_login, self.user_login)
     }
 }
 
@@ -45,6 +54,7 @@ impl From<JoinMessage> for IRCMessage {
 mod tests {
     use crate::message::{IRCMessage, JoinMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -57,7 +67,7 @@ mod tests {
             JoinMessage {
                 channel_login: "pajlada".to_owned(),
                 user_login: "randers811".to_owned(),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
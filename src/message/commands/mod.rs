@@ -12,6 +12,7 @@ pub mod reconnect;
 pub mod roomstate;
 pub mod usernotice;
 pub mod userstate;
+pub mod welcome;
 pub mod whisper;
 // TODO types: CLEARMSG, ROOMSTATE, USERSTATE, GLOBALUSERSTATE, WHISPER, HOSTTARGET, NOTICE, USERNOTICE
 
@@ -23,18 +24,26 @@ use crate::message::commands::ping::PingMessage;
 use crate::message::commands::pong::PongMessage;
 use crate::message::commands::reconnect::ReconnectMessage;
 use crate::message::commands::userstate::UserStateMessage;
+use crate::message::commands::welcome::WelcomeMessage;
 use crate::message::prefix::IRCPrefix;
-use crate::message::twitch::{Badge, Emote, RGBColor};
+use crate::message::twitch::{
+    parse_emote_sets, Badge, Emote, EmoteSetId, RGBColor, TwitchUserBasics,
+};
 use crate::message::{
-    AsRawIRC, ClearChatMessage, GlobalUserStateMessage, HostTargetMessage, IRCMessage,
-    NoticeMessage, PrivmsgMessage, RoomStateMessage, UserNoticeMessage, WhisperMessage,
+    AsRawIRC, ClearChatAction, ClearChatMessage, GlobalUserStateMessage, HostTargetMessage,
+    IRCMessage, IRCParseError, NoticeMessage, PrivmsgMessage, RoomStateMessage, UserNoticeEvent,
+    UserNoticeMessage, WhisperMessage,
 };
+#[cfg(feature = "with-bytes")]
+use bytes::Bytes;
+#[cfg(feature = "with-chrono")]
 use chrono::{DateTime, TimeZone, Utc};
 use itertools::Itertools;
-use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fmt;
 use std::ops::Range;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 #[cfg(feature = "with-serde")]
@@ -93,6 +102,24 @@ impl From<ServerMessageParseError> for IRCMessage {
     }
 }
 
+/// Error returned by [`ServerMessage::parse`](ServerMessage::parse), covering both steps of
+/// parsing a raw line into a `ServerMessage`.
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseServerMessageError {
+    /// The line could not be parsed as an `IRCMessage`.
+    #[error(transparent)]
+    IRCParseError(#[from] IRCParseError),
+    /// The line is a valid IRC message, but could not be parsed as a `ServerMessage`.
+    #[error(transparent)]
+    ServerMessageParseError(Box<ServerMessageParseError>),
+}
+
+impl From<ServerMessageParseError> for ParseServerMessageError {
+    fn from(err: ServerMessageParseError) -> ParseServerMessageError {
+        ParseServerMessageError::ServerMessageParseError(Box::new(err))
+    }
+}
+
 trait IRCMessageParseExt {
     fn try_get_param(&self, index: usize) -> Result<&str, ServerMessageParseError>;
     fn try_get_message_text(&self) -> Result<(&str, bool), ServerMessageParseError>;
@@ -117,7 +144,7 @@ trait IRCMessageParseExt {
     fn try_get_emote_sets(
         &self,
         tag_key: &'static str,
-    ) -> Result<HashSet<u64>, ServerMessageParseError>;
+    ) -> Result<Vec<EmoteSetId>, ServerMessageParseError>;
     fn try_get_badges(&self, tag_key: &'static str) -> Result<Vec<Badge>, ServerMessageParseError>;
     fn try_get_color(
         &self,
@@ -136,10 +163,14 @@ trait IRCMessageParseExt {
         &self,
         tag_key: &'static str,
     ) -> Result<Option<bool>, ServerMessageParseError>;
+    #[cfg(feature = "with-chrono")]
     fn try_get_timestamp(
         &self,
         tag_key: &'static str,
     ) -> Result<DateTime<Utc>, ServerMessageParseError>;
+    #[cfg(feature = "with-chrono")]
+    fn get_optional_timestamp(&self, tag_key: &'static str) -> Option<DateTime<Utc>>;
+    fn get_optional_timestamp_millis(&self, tag_key: &'static str) -> Option<i64>;
 }
 
 impl IRCMessageParseExt for IRCMessage {
@@ -263,7 +294,16 @@ impl IRCMessageParseExt for IRCMessage {
                 // twitch specifies the end index as inclusive, but in Rust (and most programming
                 // languages for that matter) it's very common to specify end indices as exclusive,
                 // so we add 1 here to make it exclusive.
-                let end = usize::from_str(end).map_err(|_| make_error())? + 1;
+                let end = usize::from_str(end)
+                    .map_err(|_| make_error())?
+                    .checked_add(1)
+                    .ok_or_else(make_error)?;
+
+                // these values come straight from the network, a range that ends before it
+                // starts must not make the subtraction below underflow.
+                if end <= start {
+                    return Err(make_error());
+                }
 
                 let code_length = end - start;
 
@@ -293,27 +333,11 @@ impl IRCMessageParseExt for IRCMessage {
     fn try_get_emote_sets(
         &self,
         tag_key: &'static str,
-    ) -> Result<HashSet<u64>, ServerMessageParseError> {
-        let src = self.try_get_nonempty_tag_value(tag_key)?;
-
-        if src.is_empty() {
-            Ok(HashSet::new())
-        } else {
-            let mut emote_sets = HashSet::new();
-
-            for emote_set in src.split(',') {
-                emote_sets
-                    .insert(u64::from_str(&emote_set).map_err(|_| {
-                        MalformedTagValue(self.to_owned(), tag_key, src.to_owned())
-                    })?);
-            }
-
-            Ok(emote_sets)
-        }
+    ) -> Result<Vec<EmoteSetId>, ServerMessageParseError> {
+        Ok(parse_emote_sets(self.try_get_nonempty_tag_value(tag_key)?))
     }
 
     fn try_get_badges(&self, tag_key: &'static str) -> Result<Vec<Badge>, ServerMessageParseError> {
-        // TODO same thing as above, could be optimized to not clone the tag value as well
         let tag_value = self.try_get_nonempty_tag_value(tag_key)?;
 
         if tag_value.is_empty() {
@@ -322,18 +346,23 @@ impl IRCMessageParseExt for IRCMessage {
 
         let mut badges = Vec::new();
 
-        let make_error = || MalformedTagValue(self.to_owned(), tag_key, tag_value.to_owned());
-
         // badges tag format:
         // admin/1,moderator/1,subscriber/12
         for src in tag_value.split(',') {
-            let (name, version) = src
-                .splitn(2, '/')
-                .map(|s| s.to_owned())
-                .next_tuple()
-                .ok_or_else(make_error)?;
+            // tolerate empty entries, e.g. caused by a trailing comma
+            if src.is_empty() {
+                continue;
+            }
 
-            badges.push(Badge { name, version })
+            // only split on the first slash, since the version can have further structure
+            // itself (e.g. predictions/blue-1). A badge without a version is kept with an
+            // empty version, instead of failing the entire message.
+            let (name, version) = src.split_once('/').unwrap_or((src, ""));
+
+            badges.push(Badge {
+                name: name.to_owned(),
+                version: version.to_owned(),
+            })
         }
 
         Ok(badges)
@@ -398,6 +427,7 @@ impl IRCMessageParseExt for IRCMessage {
         Ok(self.try_get_optional_number::<u8>(tag_key)?.map(|n| n > 0))
     }
 
+    #[cfg(feature = "with-chrono")]
     fn try_get_timestamp(
         &self,
         tag_key: &'static str,
@@ -410,6 +440,18 @@ impl IRCMessageParseExt for IRCMessage {
             .single()
             .ok_or_else(|| MalformedTagValue(self.to_owned(), tag_key, tag_value.to_owned()))
     }
+
+    #[cfg(feature = "with-chrono")]
+    fn get_optional_timestamp(&self, tag_key: &'static str) -> Option<DateTime<Utc>> {
+        // lenient variant of try_get_timestamp(): missing, empty and malformed values are
+        // all treated as absent
+        self.try_get_timestamp(tag_key).ok()
+    }
+
+    fn get_optional_timestamp_millis(&self, tag_key: &'static str) -> Option<i64> {
+        // same leniency as get_optional_timestamp()
+        self.try_get_number(tag_key).ok()
+    }
 }
 
 // makes it so users cannot match against Generic and get the underlying IRCMessage
@@ -435,6 +477,10 @@ pub struct HiddenIRCMessage(pub(self) IRCMessage);
 /// added parsing for the command you were trying to catch by matching against the `Unparsed`
 /// variant, your code would be broken without any compiler error.
 ///
+/// The `source` of each message is stored in an `Arc`, so it is shared between clones of a
+/// message instead of being copied, which makes handing out the same message to many
+/// consumers cheaper.
+///
 /// # Examples
 ///
 /// ```
@@ -490,10 +536,54 @@ pub enum ServerMessage {
     UserState(UserStateMessage),
     /// `WHISPER` message
     Whisper(WhisperMessage),
+    /// `001` (welcome) message
+    Welcome(WelcomeMessage),
     #[doc(hidden)]
     Generic(HiddenIRCMessage),
 }
 
+/// The type of a `ServerMessage`, without its contents. See `ServerMessage::kind()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum MessageKind {
+    /// `CLEARCHAT` message
+    ClearChat,
+    /// `CLEARMSG` message
+    ClearMsg,
+    /// `GLOBALUSERSTATE` message
+    GlobalUserState,
+    /// `HOSTTARGET` message
+    HostTarget,
+    /// `JOIN` message
+    Join,
+    /// `NOTICE` message
+    Notice,
+    /// `PART` message
+    Part,
+    /// `PING` message
+    Ping,
+    /// `PONG` message
+    Pong,
+    /// `PRIVMSG` message
+    Privmsg,
+    /// `RECONNECT` message
+    Reconnect,
+    /// `ROOMSTATE` message
+    RoomState,
+    /// `USERNOTICE` message
+    UserNotice,
+    /// `USERSTATE` message
+    UserState,
+    /// `WHISPER` message
+    Whisper,
+    /// `001` (welcome) message
+    Welcome,
+    /// Any message that is not parsed into one of the types above (e.g. `CAP` replies or
+    /// other numeric replies).
+    Other,
+}
+
 impl TryFrom<IRCMessage> for ServerMessage {
     type Error = ServerMessageParseError;
 
@@ -516,6 +606,7 @@ impl TryFrom<IRCMessage> for ServerMessage {
             "USERNOTICE" => UserNotice(UserNoticeMessage::try_from(source)?),
             "USERSTATE" => UserState(UserStateMessage::try_from(source)?),
             "WHISPER" => Whisper(WhisperMessage::try_from(source)?),
+            "001" => Welcome(WelcomeMessage::try_from(source)?),
             _ => Generic(HiddenIRCMessage(source)),
         })
     }
@@ -524,28 +615,57 @@ impl TryFrom<IRCMessage> for ServerMessage {
 impl From<ServerMessage> for IRCMessage {
     fn from(msg: ServerMessage) -> IRCMessage {
         match msg {
-            ServerMessage::ClearChat(msg) => msg.source,
-            ServerMessage::ClearMsg(msg) => msg.source,
-            ServerMessage::GlobalUserState(msg) => msg.source,
-            ServerMessage::HostTarget(msg) => msg.source,
-            ServerMessage::Join(msg) => msg.source,
-            ServerMessage::Notice(msg) => msg.source,
-            ServerMessage::Part(msg) => msg.source,
-            ServerMessage::Ping(msg) => msg.source,
-            ServerMessage::Pong(msg) => msg.source,
-            ServerMessage::Privmsg(msg) => msg.source,
-            ServerMessage::Reconnect(msg) => msg.source,
-            ServerMessage::RoomState(msg) => msg.source,
-            ServerMessage::UserNotice(msg) => msg.source,
-            ServerMessage::UserState(msg) => msg.source,
-            ServerMessage::Whisper(msg) => msg.source,
+            ServerMessage::ClearChat(msg) => msg.into(),
+            ServerMessage::ClearMsg(msg) => msg.into(),
+            ServerMessage::GlobalUserState(msg) => msg.into(),
+            ServerMessage::HostTarget(msg) => msg.into(),
+            ServerMessage::Join(msg) => msg.into(),
+            ServerMessage::Notice(msg) => msg.into(),
+            ServerMessage::Part(msg) => msg.into(),
+            ServerMessage::Ping(msg) => msg.into(),
+            ServerMessage::Pong(msg) => msg.into(),
+            ServerMessage::Privmsg(msg) => msg.into(),
+            ServerMessage::Reconnect(msg) => msg.into(),
+            ServerMessage::RoomState(msg) => msg.into(),
+            ServerMessage::UserNotice(msg) => msg.into(),
+            ServerMessage::UserState(msg) => msg.into(),
+            ServerMessage::Whisper(msg) => msg.into(),
+            ServerMessage::Welcome(msg) => msg.into(),
             ServerMessage::Generic(msg) => msg.0,
         }
     }
 }
 
+impl TryFrom<&str> for ServerMessage {
+    type Error = ParseServerMessageError;
+
+    fn try_from(source: &str) -> Result<ServerMessage, ParseServerMessageError> {
+        ServerMessage::parse(source)
+    }
+}
+
 // borrowed variant of the above
 impl ServerMessage {
+    /// Parse a single raw IRC line (without the trailing `\r\n`) into a `ServerMessage`.
+    ///
+    /// This is a shortcut for `IRCMessage::parse` followed by `ServerMessage::try_from`,
+    /// e.g. for processing chat logs line by line.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::ServerMessage;
+    ///
+    /// let message = ServerMessage::parse(":tmi.twitch.tv PING").unwrap();
+    /// assert!(matches!(message, ServerMessage::Ping(_)));
+    ///
+    /// assert!(ServerMessage::parse("").is_err());
+    /// ```
+    pub fn parse(source: &str) -> Result<ServerMessage, ParseServerMessageError> {
+        let irc_message = IRCMessage::parse(source)?;
+        Ok(ServerMessage::try_from(irc_message)?)
+    }
+
     /// Get a reference to the `IRCMessage` this `ServerMessage` was parsed from.
     pub fn source(&self) -> &IRCMessage {
         match self {
@@ -564,17 +684,769 @@ impl ServerMessage {
             ServerMessage::UserNotice(msg) => &msg.source,
             ServerMessage::UserState(msg) => &msg.source,
             ServerMessage::Whisper(msg) => &msg.source,
+            ServerMessage::Welcome(msg) => &msg.source,
             ServerMessage::Generic(msg) => &msg.0,
         }
     }
 
+    /// Format this message as a line to send over the wire, including the terminating `\r\n`,
+    /// e.g. for relaying messages received from Twitch to other IRC clients.
+    ///
+    /// This re-serializes the parsed `source()` of the message, it does **not** return the
+    /// exact bytes that were received. The line is the canonical IRC representation of
+    /// `source()`: Tags are sorted by key, tag values are escaped, and the last parameter is
+    /// only prefixed with `:` if required. Parsing the line again always results in an equal
+    /// message, and lines that are already in this form are reproduced byte for byte.
+    ///
+    /// Note that only the `source()` of the message is formatted, so to modify a message,
+    /// modify its source and parse it again.
+    ///
+    /// Only available with the `with-bytes` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::ServerMessage;
+    ///
+    /// let source = "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen.";
+    /// let message = ServerMessage::parse(source).unwrap();
+    /// assert_eq!(message.to_canonical_bytes(), format!("{}\r\n", source));
+    /// ```
+    #[cfg(feature = "with-bytes")]
+    pub fn to_canonical_bytes(&self) -> Bytes {
+        let mut line = self.as_raw_irc();
+        line.push_str("\r\n");
+        Bytes::from(line)
+    }
+
+    /// Get the time at which the Twitch IRC server created this message, for those types of
+    /// messages that carry a timestamp (`CLEARCHAT`, `CLEARMSG`, `PRIVMSG` and `USERNOTICE`).
+    /// Returns `None` for all other messages, and if the `tmi-sent-ts` tag is missing or
+    /// malformed.
+    ///
+    /// Only available with the `with-chrono` feature, see `server_time()` for an alternative.
+    #[cfg(feature = "with-chrono")]
+    pub fn server_timestamp(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_millis_opt(self.server_timestamp_millis()?)
+            .single()
+    }
+
+    /// Get the time at which the Twitch IRC server created this message as a `SystemTime`, for
+    /// the same messages as `server_timestamp()`. Returns `None` for all other messages.
+    ///
+    /// Unlike `server_timestamp()`, this is also available without the `with-chrono` feature.
+    /// With the feature, `SystemTime` and `DateTime<Utc>` can be converted into each other
+    /// using `From`.
+    pub fn server_time(&self) -> Option<SystemTime> {
+        let milliseconds_since_epoch = self.server_timestamp_millis()?;
+        let offset = Duration::from_millis(milliseconds_since_epoch.unsigned_abs());
+        if milliseconds_since_epoch >= 0 {
+            UNIX_EPOCH.checked_add(offset)
+        } else {
+            UNIX_EPOCH.checked_sub(offset)
+        }
+    }
+
+    /// The `server_timestamp_millis` field of those messages that carry a timestamp.
+    fn server_timestamp_millis(&self) -> Option<i64> {
+        match self {
+            ServerMessage::ClearChat(msg) => msg.server_timestamp_millis,
+            ServerMessage::ClearMsg(msg) => msg.server_timestamp_millis,
+            ServerMessage::Privmsg(msg) => msg.server_timestamp_millis,
+            ServerMessage::UserNotice(msg) => msg.server_timestamp_millis,
+            _ => None,
+        }
+    }
+
+    /// Get the user that sent this message, for those types of messages that are sent by a
+    /// user (`PRIVMSG`, `USERNOTICE` and `WHISPER`). Returns `None` for all other messages.
+    pub fn sender(&self) -> Option<&TwitchUserBasics> {
+        match self {
+            ServerMessage::Privmsg(msg) => Some(&msg.sender),
+            ServerMessage::UserNotice(msg) => Some(&msg.sender),
+            ServerMessage::Whisper(msg) => Some(&msg.sender),
+            _ => None,
+        }
+    }
+
+    /// Get the ID of the user this message is about, e.g. to key per-user state on, since
+    /// unlike login names, user IDs never change:
+    ///
+    /// * For `PRIVMSG`, `USERNOTICE` and `WHISPER`, this is the ID of the sender
+    ///   (see `sender()`).
+    /// * For `CLEARCHAT`, this is the ID of the user that was banned or timed out. Note that
+    ///   this is not the moderator that did it, Twitch does not tell who that was.
+    ///
+    /// Returns `None` for all other messages, for `CLEARCHAT` messages clearing the whole
+    /// chat, and for messages received without tags (where the ID is unknown).
+    pub fn user_id(&self) -> Option<&str> {
+        let user_id = match self {
+            ServerMessage::ClearChat(msg) => match &msg.action {
+                ClearChatAction::UserBanned { user_id, .. }
+                | ClearChatAction::UserTimedOut { user_id, .. } => user_id,
+                ClearChatAction::ChatCleared => return None,
+            },
+            _ => &self.sender()?.id,
+        };
+        Some(user_id.as_str()).filter(|user_id| !user_id.is_empty())
+    }
+
+    /// If this message announces a raid-related event, returns that event, see `RaidEvent`.
+    /// This combines the relevant `USERNOTICE` and `NOTICE` messages, so bots can handle raids
+    /// the same way no matter which of them Twitch sent. The event concerns the channel that the
+    /// message was sent to. Returns `None` for all other messages.
+    pub fn raid_event(&self) -> Option<RaidEvent> {
+        match self {
+            ServerMessage::UserNotice(msg) => match &msg.event {
+                UserNoticeEvent::Raid { viewer_count, .. } => Some(RaidEvent::Incoming {
+                    raider: msg.sender.clone(),
+                    viewer_count: *viewer_count,
+                }),
+                _ if msg.event_id == "unraid" => Some(RaidEvent::Cancelled),
+                _ => None,
+            },
+            ServerMessage::Notice(msg) => {
+                let message_id = msg.message_id.as_deref()?;
+                if message_id == "unraid_success" {
+                    Some(RaidEvent::Cancelled)
+                } else if message_id.starts_with("raid_error_") {
+                    Some(RaidEvent::StartFailed {
+                        message_id: message_id.to_owned(),
+                    })
+                } else if message_id.starts_with("unraid_error_") {
+                    Some(RaidEvent::CancelFailed {
+                        message_id: message_id.to_owned(),
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of this message, e.g. `MessageKind::Privmsg` for a `ServerMessage::Privmsg`.
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, MessageKind, ServerMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let irc_message = IRCMessage::parse(":tmi.twitch.tv PING").unwrap();
+    /// let server_message = ServerMessage::try_from(irc_message).unwrap();
+    /// assert_eq!(server_message.kind(), MessageKind::Ping);
+    /// ```
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            ServerMessage::ClearChat(_) => MessageKind::ClearChat,
+            ServerMessage::ClearMsg(_) => MessageKind::ClearMsg,
+            ServerMessage::GlobalUserState(_) => MessageKind::GlobalUserState,
+            ServerMessage::HostTarget(_) => MessageKind::HostTarget,
+            ServerMessage::Join(_) => MessageKind::Join,
+            ServerMessage::Notice(_) => MessageKind::Notice,
+            ServerMessage::Part(_) => MessageKind::Part,
+            ServerMessage::Ping(_) => MessageKind::Ping,
+            ServerMessage::Pong(_) => MessageKind::Pong,
+            ServerMessage::Privmsg(_) => MessageKind::Privmsg,
+            ServerMessage::Reconnect(_) => MessageKind::Reconnect,
+            ServerMessage::RoomState(_) => MessageKind::RoomState,
+            ServerMessage::UserNotice(_) => MessageKind::UserNotice,
+            ServerMessage::UserState(_) => MessageKind::UserState,
+            ServerMessage::Whisper(_) => MessageKind::Whisper,
+            ServerMessage::Welcome(_) => MessageKind::Welcome,
+            ServerMessage::Generic(_) => MessageKind::Other,
+        }
+    }
+
+    /// Whether this is a protocol-level message that keeps the connection working, rather than
+    /// chat content or channel state. These are `PING`, `PONG`, `RECONNECT`, the `001` welcome
+    /// message, and the messages that are only available as `Generic` messages: `CAP` replies
+    /// and all other numeric replies (e.g. `353` and `366` for the list of chatters).
+    ///
+    /// The client already handles all of these itself, so applications that only care about chat
+    /// can ignore messages for which this returns `true`.
+    pub fn is_control(&self) -> bool {
+        // no wildcard here, so new variants have to be classified explicitly
+        match self {
+            ServerMessage::Ping(_)
+            | ServerMessage::Pong(_)
+            | ServerMessage::Reconnect(_)
+            | ServerMessage::Welcome(_) => true,
+            ServerMessage::Generic(msg) => {
+                let command = msg.0.command.as_str();
+                command == "CAP"
+                    || (command.len() == 3 && command.bytes().all(|b| b.is_ascii_digit()))
+            }
+            ServerMessage::ClearChat(_)
+            | ServerMessage::ClearMsg(_)
+            | ServerMessage::GlobalUserState(_)
+            | ServerMessage::HostTarget(_)
+            | ServerMessage::Join(_)
+            | ServerMessage::Notice(_)
+            | ServerMessage::Part(_)
+            | ServerMessage::Privmsg(_)
+            | ServerMessage::RoomState(_)
+            | ServerMessage::UserNotice(_)
+            | ServerMessage::UserState(_)
+            | ServerMessage::Whisper(_) => false,
+        }
+    }
+
+    /// Sort the given messages chronologically by their `server_time()`, e.g. to merge
+    /// messages received from multiple connections that arrived slightly out of order.
+    ///
+    /// Messages without a timestamp are moved to the end. The sort is stable, so messages with
+    /// the same timestamp, and all messages without a timestamp, keep their relative order.
+    pub fn sort_by_server_timestamp(messages: &mut [ServerMessage]) {
+        messages.sort_by_key(|msg| {
+            let server_time = msg.server_time();
+            (server_time.is_none(), server_time)
+        });
+    }
+
+    /// Get the value of the tag `key` on the `source()` of this message.
+    /// See [`IRCMessage::get_tag`](IRCMessage::get_tag) for the exact semantics.
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.source().get_tag(key)
+    }
+
+    #[cfg(feature = "client")]
     pub(crate) fn new_generic(message: IRCMessage) -> ServerMessage {
         ServerMessage::Generic(HiddenIRCMessage(message))
     }
 }
 
+macro_rules! impl_get_tag {
+    ($($message_type:ty),+) => {
+        $(
+            impl $message_type {
+                /// Get the value of the tag `key` on the `source` of this message, including
+                /// tags that are not parsed into a field of this struct.
+                /// See [`IRCMessage::get_tag`](IRCMessage::get_tag) for the exact semantics.
+                pub fn get_tag(&self, key: &str) -> Option<&str> {
+                    self.source.get_tag(key)
+                }
+            }
+        )+
+    };
+}
+
+impl_get_tag!(
+    ClearChatMessage,
+    ClearMsgMessage,
+    GlobalUserStateMessage,
+    HostTargetMessage,
+    JoinMessage,
+    NoticeMessage,
+    PartMessage,
+    PingMessage,
+    PongMessage,
+    PrivmsgMessage,
+    ReconnectMessage,
+    RoomStateMessage,
+    UserNoticeMessage,
+    UserStateMessage,
+    WelcomeMessage,
+    WhisperMessage
+);
+
+macro_rules! impl_slice_accessor {
+    ($field:ident: $item_type:ty, $doc:literal, [$($message_type:ty),+]) => {
+        $(
+            impl $message_type {
+                #[doc = $doc]
+                ///
+                /// Prefer this over accessing the field directly if you only need to read the
+                /// list, since it does not depend on the concrete type of the field.
+                pub fn $field(&self) -> &[$item_type] {
+                    &self.$field
+                }
+            }
+        )+
+    };
+}
+
+impl_slice_accessor!(
+    badges: Badge,
+    "The badges of the user, in the order Twitch sent them in (which is the order they should be displayed in). See the `badges` field.",
+    [
+        GlobalUserStateMessage,
+        PrivmsgMessage,
+        UserNoticeMessage,
+        UserStateMessage,
+        WhisperMessage
+    ]
+);
+
+impl_slice_accessor!(
+    badge_info: Badge,
+    "Metadata related to the badges of the user, in the order Twitch sent it in. See the `badge_info` field.",
+    [
+        GlobalUserStateMessage,
+        PrivmsgMessage,
+        UserNoticeMessage,
+        UserStateMessage
+    ]
+);
+
+impl_slice_accessor!(
+    emotes: Emote,
+    "The emotes in the message text, sorted by their position in the text. See the `emotes` field.",
+    [PrivmsgMessage, UserNoticeMessage, WhisperMessage]
+);
+
+/// An event related to raids, see `ServerMessage::raid_event()`. Raids started from a channel
+/// are not announced in that channel, only failures to start them are.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum RaidEvent {
+    /// Another channel raided this channel (`USERNOTICE` with `msg-id=raid`, see
+    /// `UserNoticeEvent::Raid`).
+    Incoming {
+        /// The broadcaster of the channel that raided this channel.
+        raider: TwitchUserBasics,
+        /// How many viewers participated in the raid.
+        viewer_count: u64,
+    },
+    /// A raid from this channel was cancelled. Announced to the whole channel as a
+    /// `USERNOTICE` with `msg-id=unraid`, and sent as a `NOTICE` with `msg-id=unraid_success`
+    /// in reply to the `/unraid` command.
+    Cancelled,
+    /// A raid from this channel could not be started, e.g. because the target channel does
+    /// not exist. Sent as a `NOTICE` in reply to the `/raid` command.
+    StartFailed {
+        /// The `msg-id` of the notice, identifying the reason, e.g. `raid_error_self`.
+        message_id: String,
+    },
+    /// The raid from this channel could not be cancelled, e.g. because there was none.
+    /// Sent as a `NOTICE` in reply to the `/unraid` command.
+    CancelFailed {
+        /// The `msg-id` of the notice, identifying the reason, e.g.
+        /// `unraid_error_no_active_raid`.
+        message_id: String,
+    },
+}
+
 impl AsRawIRC for ServerMessage {
     fn format_as_raw_irc(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.source().format_as_raw_irc(f)
     }
 }
+
+/// Formats chat-like messages (e.g. `PRIVMSG`, `WHISPER` or `CLEARCHAT`) as a concise,
+/// human-readable line, see the `Display` implementations of the individual message types.
+/// All other messages are formatted as a short descriptor, e.g. `[ROOMSTATE #pajlada]` or `[PING]`.
+///
+/// This is intended for logging and debugging. Use `as_raw_irc()` to get the message
+/// in IRC format instead.
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::ClearChat(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::ClearMsg(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::HostTarget(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::Join(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::Notice(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::Part(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::Privmsg(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::UserNotice(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::Whisper(msg) => fmt::Display::fmt(msg, f),
+            ServerMessage::RoomState(msg) => write!(f, "[ROOMSTATE #{}]", msg.channel_login),
+            ServerMessage::UserState(msg) => write!(f, "[USERSTATE #{}]", msg.channel_login),
+            ServerMessage::GlobalUserState(_)
+            | ServerMessage::Ping(_)
+            | ServerMessage::Pong(_)
+            | ServerMessage::Reconnect(_)
+            | ServerMessage::Welcome(_)
+            | ServerMessage::Generic(_) => write!(f, "[{}]", self.source().command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::twitch::TwitchUserBasics;
+    use crate::message::{
+        AsRawIRC, IRCMessage, IRCParseError, ParseServerMessageError, RaidEvent, ServerMessage,
+        ServerMessageParseError,
+    };
+    #[cfg(feature = "with-chrono")]
+    use chrono::{TimeZone, Utc};
+    use std::convert::TryFrom;
+    #[cfg(feature = "with-chrono")]
+    use std::time::SystemTime;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn parse(src: &str) -> ServerMessage {
+        ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_from_str() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        assert_eq!(
+            ServerMessage::parse(src).unwrap().source(),
+            parse(src).source()
+        );
+        assert_eq!(
+            ServerMessage::try_from(src).unwrap().source(),
+            parse(src).source()
+        );
+    }
+
+    #[test]
+    fn test_parse_from_str_errors() {
+        assert_eq!(
+            ServerMessage::parse("").unwrap_err(),
+            ParseServerMessageError::IRCParseError(IRCParseError::MalformedCommand)
+        );
+
+        let src =
+            "@color=#0000FF :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        assert_eq!(
+            ServerMessage::try_from(src).unwrap_err(),
+            ParseServerMessageError::ServerMessageParseError(Box::new(
+                ServerMessageParseError::MissingTag(IRCMessage::parse(src).unwrap(), "room-id")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_server_time() {
+        let msg = parse("@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123");
+        assert_eq!(
+            msg.server_time(),
+            Some(UNIX_EPOCH + Duration::from_millis(1594561360331))
+        );
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(
+            msg.server_time(),
+            msg.server_timestamp().map(SystemTime::from)
+        );
+
+        let msg = parse(":tmi.twitch.tv PING");
+        assert_eq!(msg.server_time(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "with-chrono")]
+    fn test_server_timestamp() {
+        let msg = parse("@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123");
+        assert_eq!(
+            msg.server_timestamp(),
+            Some(Utc.timestamp_millis_opt(1594561360331).unwrap())
+        );
+
+        let msg = parse(":tmi.twitch.tv PING");
+        assert_eq!(msg.server_timestamp(), None);
+    }
+
+    #[test]
+    fn test_server_time_without_source() {
+        let msg = match parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam") {
+            ServerMessage::Privmsg(msg) => ServerMessage::Privmsg(msg.without_source()),
+            other => panic!("expected PRIVMSG, got {:?}", other),
+        };
+        assert_eq!(
+            msg.server_time(),
+            Some(UNIX_EPOCH + Duration::from_millis(1594545155039))
+        );
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(
+            msg.server_timestamp(),
+            Some(Utc.timestamp_millis_opt(1594545155039).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_sender() {
+        let msg = parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam");
+        assert_eq!(
+            msg.sender(),
+            Some(&TwitchUserBasics {
+                id: "29803735".to_owned(),
+                login: "jun1orrrr".to_owned(),
+                name: "JuN1oRRRR".to_owned(),
+            })
+        );
+
+        let msg = parse("@badges=;color=#19E6E6;display-name=randers;emotes=;message-id=7;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello");
+        assert_eq!(
+            msg.sender().map(|sender| sender.login.as_str()),
+            Some("randers")
+        );
+
+        let msg = parse(":randers811!randers811@randers811.tmi.twitch.tv JOIN #pajlada");
+        assert_eq!(msg.sender(), None);
+    }
+
+    #[test]
+    fn test_raid_event() {
+        let msg = parse("@badge-info=;badges=glhf-pledge/1;color=#FF69B4;display-name=iamelisabete;emotes=;flags=;id=bb99dda7-3736-4583-9114-52aa11b23d17;login=iamelisabete;mod=0;msg-id=raid;msg-param-displayName=iamelisabete;msg-param-login=iamelisabete;msg-param-profileImageURL=https://static-cdn.jtvnw.net/jtv_user_pictures/cae3ca63-510d-4715-b4ce-059dcf938978-profile_image-70x70.png;msg-param-viewerCount=430;room-id=71092938;subscriber=0;system-msg=430\\sraiders\\sfrom\\siamelisabete\\shave\\sjoined!;tmi-sent-ts=1594517796120;user-id=155874595;user-type= :tmi.twitch.tv USERNOTICE #xqcow");
+        assert_eq!(
+            msg.raid_event(),
+            Some(RaidEvent::Incoming {
+                raider: TwitchUserBasics {
+                    id: "155874595".to_owned(),
+                    login: "iamelisabete".to_owned(),
+                    name: "iamelisabete".to_owned(),
+                },
+                viewer_count: 430,
+            })
+        );
+
+        let msg = parse("@badge-info=;badges=broadcaster/1;color=#19E6E6;display-name=randers;emotes=;flags=;id=f5ba1d8b-c8d5-4bfd-a8b1-9e4d8c5e9a2a;login=randers;mod=0;msg-id=unraid;room-id=40286300;subscriber=0;system-msg=The\\sraid\\shas\\sbeen\\scancelled.;tmi-sent-ts=1594517796120;user-id=40286300;user-type= :tmi.twitch.tv USERNOTICE #randers");
+        assert_eq!(msg.raid_event(), Some(RaidEvent::Cancelled));
+
+        let cases = [
+            ("@msg-id=unraid_success :tmi.twitch.tv NOTICE #randers :You have successfully cancelled the raid.", Some(RaidEvent::Cancelled)),
+            ("@msg-id=raid_error_self :tmi.twitch.tv NOTICE #randers :A channel cannot raid itself.", Some(RaidEvent::StartFailed { message_id: "raid_error_self".to_owned() })),
+            ("@msg-id=unraid_error_no_active_raid :tmi.twitch.tv NOTICE #randers :You do not have an active raid.", Some(RaidEvent::CancelFailed { message_id: "unraid_error_no_active_raid".to_owned() })),
+            ("@msg-id=slow_off :tmi.twitch.tv NOTICE #randers :This room is no longer in slow mode.", None),
+            (":tmi.twitch.tv NOTICE * :Login authentication failed", None),
+            ("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam", None),
+        ];
+        for (source, raid_event) in &cases {
+            assert_eq!(parse(source).raid_event(), *raid_event, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_user_id() {
+        let cases = [
+            ("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam", Some("29803735")),
+            ("@badges=;color=#19E6E6;display-name=randers;emotes=;message-id=7;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello", Some("40286300")),
+            ("@badge-info=;badges=;color=;display-name=Clawsumer;emotes=;flags=;id=c7a8df3b-25a6-4ec3-9a13-4ba8e2fdfd8e;login=clawsumer;mod=0;msg-id=raid;msg-param-displayName=Clawsumer;msg-param-login=clawsumer;msg-param-profileImageURL=https://static-cdn.jtvnw.net/jtv_user_pictures/clawsumer-profile_image.png;msg-param-viewerCount=5;room-id=11148817;subscriber=0;system-msg=5\\sraiders\\sfrom\\sClawsumer\\shave\\sjoined!;tmi-sent-ts=1594549221106;user-id=91234856;user-type= :tmi.twitch.tv USERNOTICE #pajlada", Some("91234856")),
+            ("@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef", Some("148973258")),
+            ("@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123", Some("70948394")),
+            ("@room-id=11148817;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #pajlada", None),
+            (":jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam", None),
+            (":randers811!randers811@randers811.tmi.twitch.tv JOIN #pajlada", None),
+        ];
+        for (source, user_id) in &cases {
+            assert_eq!(parse(source).user_id(), *user_id, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_is_control() {
+        let cases = [
+            // protocol
+            ("PING :tmi.twitch.tv", true),
+            (":tmi.twitch.tv PONG tmi.twitch.tv :abc", true),
+            (":tmi.twitch.tv RECONNECT", true),
+            (":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!", true),
+            (":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags", true),
+            (":justinfan12345.tmi.twitch.tv 353 justinfan12345 = #pajlada :randers", true),
+            (":tmi.twitch.tv 421 justinfan12345 WHO :Unknown command", true),
+            // chat content
+            ("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam", false),
+            ("@room-id=11148817;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #pajlada", false),
+            ("@msg-id=slow_off :tmi.twitch.tv NOTICE #pajlada :This room is no longer in slow mode.", false),
+            // channel state and membership
+            ("@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=11148817;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #pajlada", false),
+            (":randers!randers@randers.tmi.twitch.tv JOIN #pajlada", false),
+            // unknown commands
+            (":tmi.twitch.tv SOMETHINGNEW #pajlada", false),
+        ];
+        for (src, expected) in &cases {
+            assert_eq!(parse(src).is_control(), *expected, "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_get_tag() {
+        let msg = parse("@badge-info=;badges=;client-nonce=abc\\sdef;color=#0000FF;display-name=randers;emotes=;first-msg=0;flags=;id=c6e9b4b8-7e3d-4ae2-b3a5-0e6a0a0e4a11;mod=0;returning-chatter=0;room-id=11148817;some-flag;subscriber=0;tmi-sent-ts=1594561360331;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :test");
+        let privmsg = match &msg {
+            ServerMessage::Privmsg(privmsg) => privmsg,
+            _ => panic!("expected PRIVMSG"),
+        };
+
+        // tags not modelled by PrivmsgMessage are still accessible
+        assert_eq!(privmsg.get_tag("returning-chatter"), Some("0"));
+        assert_eq!(privmsg.get_tag("client-nonce"), Some("abc def"));
+        // present with empty value, and present without value
+        assert_eq!(privmsg.get_tag("flags"), Some(""));
+        assert_eq!(privmsg.get_tag("some-flag"), Some(""));
+        // not present
+        assert_eq!(privmsg.get_tag("some-future-tag"), None);
+
+        assert_eq!(msg.get_tag("returning-chatter"), Some("0"));
+        assert_eq!(msg.get_tag("some-future-tag"), None);
+    }
+
+    /// Brings a raw line into the form that `as_raw_irc()` produces: tags are sorted by key,
+    /// and a trailing parameter only keeps its leading `:` if it is required, i.e. if the
+    /// parameter is empty, contains a space or starts with a `:` itself.
+    fn normalize_raw_irc(line: &str) -> String {
+        let mut output = String::new();
+        let mut rest = line;
+
+        if let Some(tags_and_rest) = rest.strip_prefix('@') {
+            let (tags, remainder) = tags_and_rest.split_once(' ').unwrap();
+            let mut tags = tags.split(';').collect::<Vec<_>>();
+            tags.sort_by_key(|tag| tag.split('=').next().unwrap());
+            output.push('@');
+            output.push_str(&tags.join(";"));
+            output.push(' ');
+            rest = remainder;
+        }
+
+        if rest.starts_with(':') {
+            let (prefix, remainder) = rest.split_once(' ').unwrap();
+            output.push_str(prefix);
+            output.push(' ');
+            rest = remainder;
+        }
+
+        match rest.split_once(" :") {
+            Some((middle, trailing))
+                if !trailing.is_empty()
+                    && !trailing.contains(' ')
+                    && !trailing.starts_with(':') =>
+            {
+                output.push_str(middle);
+                output.push(' ');
+                output.push_str(trailing);
+            }
+            _ => output.push_str(rest),
+        }
+
+        output
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let corpus = [
+            // CLEARCHAT
+            "@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef",
+            "@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123",
+            "@room-id=40286300;tmi-sent-ts=1594561392337 :tmi.twitch.tv CLEARCHAT #randers",
+            // CLEARMSG
+            "@login=alazymeme;room-id=;target-msg-id=3c92014f-340a-4dc3-a9c9-e5cf182f4a84;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :NIGHT CUNT",
+            "@login=randers;room-id=;target-msg-id=15e5164d-f8e6-4aec-baf4-2d6a330760c4;tmi-sent-ts=1594562632383 :tmi.twitch.tv CLEARMSG #pajlada :\u{0001}ACTION test\u{0001}",
+            // GLOBALUSERSTATE
+            "@badge-info=;badges=;color=#19E6E6;display-name=randers;emote-sets=0,42,237;user-id=40286300;user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+            "@badge-info=;badges=premium/1;color=;display-name=randers;emote-sets=;user-id=40286300;user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+            // HOSTTARGET
+            ":tmi.twitch.tv HOSTTARGET #randers :leebaxd 0",
+            ":tmi.twitch.tv HOSTTARGET #randers :- 0",
+            // JOIN, PART
+            ":randers811!randers811@randers811.tmi.twitch.tv JOIN #pajlada",
+            ":randers811!randers811@randers811.tmi.twitch.tv PART #pajlada",
+            // NOTICE
+            ":tmi.twitch.tv NOTICE * :Improperly formatted auth",
+            "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen.",
+            // PING, PONG
+            ":tmi.twitch.tv PING",
+            ":tmi.twitch.tv PING test :abc def",
+            "PONG :tmi.twitch.tv",
+            ":tmi.twitch.tv PONG tmi.twitch.tv :test",
+            // PRIVMSG
+            "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam",
+            "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :\u{0001}ACTION -tags\u{0001}",
+            "@rm-received-ts=1594554085918;historical=1;badge-info=;badges=;client-nonce=815810609edecdf4537bd9586994182b;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM",
+            "@badge-info=;badges=;color=;display-name=randers;emotes=;flags=;id=6f1f2b8a-3c2d-4e5f-8a9b-0c1d2e3f4a5b;mod=0;room-id=40286300;subscriber=0;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #randers ::)",
+            // USERNOTICE, with escaped spaces in tag values
+            "@badge-info=subscriber/0;badges=subscriber/0,premium/1;color=;display-name=fallenseraphhh;emotes=;flags=;id=2a9bea11-a80a-49a0-a498-1642d457f775;login=fallenseraphhh;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=Prime;room-id=71092938;subscriber=1;system-msg=fallenseraphhh\\ssubscribed\\swith\\sTwitch\\sPrime.;tmi-sent-ts=1582685713242;user-id=224005980;user-type= :tmi.twitch.tv USERNOTICE #xqcow",
+            // USERSTATE
+            "@badge-info=;badges=;color=#FF0000;display-name=TESTUSER;emote-sets=0;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE #randers",
+            // ROOMSTATE
+            "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=40286300;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #randers",
+            // RECONNECT
+            ":tmi.twitch.tv RECONNECT",
+            // WHISPER
+            "@badges=;color=#19E6E6;display-name=randers;emotes=25:22-26;message-id=1;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello, this is a test Kappa",
+            // 001
+            ":tmi.twitch.tv 001 randers811 :Welcome, GLHF!",
+            // generic messages, with all escape sequences and a tag without value
+            ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags",
+            ":tmi.twitch.tv 353 randers811 = #pajlada :randers811",
+            "@a=semi\\:colon;b=back\\\\slash;c=line\\r\\nbreak;d;e=with\\sspace :tmi.twitch.tv SOMECOMMAND #pajlada :",
+        ];
+
+        for line in corpus.iter() {
+            let irc_message = IRCMessage::parse(line).unwrap();
+            let server_message = ServerMessage::try_from(irc_message.clone()).unwrap();
+            let round_tripped = IRCMessage::from(server_message);
+
+            assert_eq!(round_tripped, irc_message);
+            assert_eq!(round_tripped.as_raw_irc(), normalize_raw_irc(line));
+        }
+    }
+
+    #[test]
+    fn test_sort_by_server_timestamp() {
+        let original = vec![
+            parse(":tmi.twitch.tv PING"),
+            parse("@room-id=11148817;target-user-id=70948394;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #pajlada :weeb123"),
+            parse(":randers811!randers811@randers811.tmi.twitch.tv JOIN #pajlada"),
+            parse("@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef"),
+            parse("@room-id=40286300;tmi-sent-ts=1594561360331 :tmi.twitch.tv CLEARCHAT #randers"),
+        ];
+
+        let mut messages = original.clone();
+        ServerMessage::sort_by_server_timestamp(&mut messages);
+
+        // timestamped messages in chronological order (messages 1 and 4 have equal timestamps
+        // and keep their order), then the messages without timestamp in their original order
+        let expected_order = [3, 1, 4, 0, 2];
+        assert_eq!(
+            messages.iter().map(|m| m.source()).collect::<Vec<_>>(),
+            expected_order
+                .iter()
+                .map(|i| original[*i].source())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "with-bytes")]
+    fn test_to_canonical_bytes() {
+        // real messages, already in canonical form
+        let corpus = [
+            "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam",
+            "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=40286300;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #randers",
+            "@badges=;color=#19E6E6;display-name=randers;emotes=25:22-26;message-id=1;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello, this is a test Kappa",
+            "@login=alazymeme;room-id=;target-msg-id=3c92014f-340a-4dc3-a9c9-e5cf182f4a84;tmi-sent-ts=1594561955611 :tmi.twitch.tv CLEARMSG #pajlada :NIGHT CUNT",
+            "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen.",
+            ":randers!randers@randers.tmi.twitch.tv JOIN #pajlada",
+            ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            ":tmi.twitch.tv RECONNECT",
+        ];
+        for src in corpus.iter() {
+            let message = parse(src);
+            let bytes = message.to_canonical_bytes();
+            assert_eq!(bytes, format!("{}\r\n", src).as_bytes());
+            assert_eq!(IRCMessage::parse(src).unwrap(), *message.source());
+        }
+
+        // not in canonical form, but equal after parsing again
+        for (src, expected) in [
+            (
+                "@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada :fabzeef",
+                "@ban-duration=1;room-id=11148817;target-user-id=148973258;tmi-sent-ts=1594553828245 :tmi.twitch.tv CLEARCHAT #pajlada fabzeef\r\n",
+            ),
+            // Twitch does not always send the tags in sorted order
+            (
+                "@badge-info=subscriber/0;badges=subscriber/0,premium/1;color=;display-name=fallenseraphhh;emotes=;flags=;id=2a9bea11-a80a-49a0-a498-1642d457f775;login=fallenseraphhh;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=Prime;room-id=71092938;subscriber=1;system-msg=fallenseraphhh\\ssubscribed\\swith\\sTwitch\\sPrime.;tmi-sent-ts=1582685713242;user-id=224005980;user-type= :tmi.twitch.tv USERNOTICE #xqcow",
+                "@badge-info=subscriber/0;badges=subscriber/0,premium/1;color=;display-name=fallenseraphhh;emotes=;flags=;id=2a9bea11-a80a-49a0-a498-1642d457f775;login=fallenseraphhh;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-months=0;msg-param-should-share-streak=0;msg-param-sub-plan=Prime;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);room-id=71092938;subscriber=1;system-msg=fallenseraphhh\\ssubscribed\\swith\\sTwitch\\sPrime.;tmi-sent-ts=1582685713242;user-id=224005980;user-type= :tmi.twitch.tv USERNOTICE #xqcow\r\n",
+            ),
+            ("PING :tmi.twitch.tv", "PING tmi.twitch.tv\r\n"),
+            (
+                "@slow=0;room-id=40286300;emote-only=0 :tmi.twitch.tv ROOMSTATE #randers",
+                "@emote-only=0;room-id=40286300;slow=0 :tmi.twitch.tv ROOMSTATE #randers\r\n",
+            ),
+        ]
+        .iter()
+        {
+            let message = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+            let bytes = message.to_canonical_bytes();
+            assert_eq!(bytes, expected.as_bytes());
+            assert_eq!(
+                IRCMessage::parse(expected.trim_end_matches("\r\n")).unwrap(),
+                *message.source()
+            );
+        }
+    }
+}
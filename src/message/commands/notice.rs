@@ -1,6 +1,8 @@
 use crate::message::commands::IRCMessageParseExt;
 use crate::message::{IRCMessage, ServerMessageParseError};
 use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -21,7 +23,7 @@ pub struct NoticeMessage {
     pub message_id: Option<String>,
 
     /// The message that this `NoticeMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for NoticeMessage {
@@ -40,21 +42,125 @@ impl TryFrom<IRCMessage> for NoticeMessage {
             message_id: source
                 .try_get_optional_nonempty_tag_value("msg-id")?
                 .map(|s| s.to_owned()),
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<NoticeMessage> for IRCMessage {
     fn from(msg: NoticeMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Reason why Twitch refused to deliver a chat message sent to a channel,
+/// see `NoticeMessage::send_failure_reason()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum SendFailureReason {
+    /// The channel is in emote-only mode, and the message contained text (`msg_emoteonly`).
+    EmoteOnly,
+    /// The channel is in subscribers-only mode (`msg_subsonly`).
+    SubscribersOnly,
+    /// The channel is in followers-only mode, and the user does not follow the channel (long
+    /// enough) (`msg_followersonly`, `msg_followersonly_zero` and `msg_followersonly_followed`).
+    FollowersOnly,
+    /// The channel is in slow mode, and the user sent messages too quickly (`msg_slowmode`).
+    SlowMode,
+    /// The channel is in unique-chat mode, and the message was not unique (`msg_r9k`).
+    UniqueChat,
+    /// The message was identical to the previous message sent by the user (`msg_duplicate`).
+    DuplicateMessage,
+    /// The user is permanently banned from the channel (`msg_banned`).
+    Banned,
+    /// The user is timed out in the channel (`msg_timedout`).
+    TimedOut,
+    /// The user sent messages too quickly (`msg_ratelimit`).
+    RateLimited,
+    /// The channel is suspended (`msg_channel_suspended`).
+    ChannelSuspended,
+}
+
+impl NoticeMessage {
+    /// If this notice is Twitch's response to a chat message that could not be delivered
+    /// (e.g. because the channel is in emote-only mode), returns the reason for that.
+    /// Returns `None` for all other notices.
+    ///
+    /// Note that the notice does not identify which message failed to be delivered,
+    /// see `ClientConfig::on_send_failure` for a best-effort way of finding that out.
+    pub fn send_failure_reason(&self) -> Option<SendFailureReason> {
+        match self.message_id.as_deref()? {
+            "msg_emoteonly" => Some(SendFailureReason::EmoteOnly),
+            "msg_subsonly" => Some(SendFailureReason::SubscribersOnly),
+            "msg_followersonly" | "msg_followersonly_zero" | "msg_followersonly_followed" => {
+                Some(SendFailureReason::FollowersOnly)
+            }
+            "msg_slowmode" => Some(SendFailureReason::SlowMode),
+            "msg_r9k" => Some(SendFailureReason::UniqueChat),
+            "msg_duplicate" => Some(SendFailureReason::DuplicateMessage),
+            "msg_banned" => Some(SendFailureReason::Banned),
+            "msg_timedout" => Some(SendFailureReason::TimedOut),
+            "msg_ratelimit" => Some(SendFailureReason::RateLimited),
+            "msg_channel_suspended" => Some(SendFailureReason::ChannelSuspended),
+            _ => None,
+        }
+    }
+}
+
+/// Formats the notice as a single line, e.g. `#pajlada NOTICE: This room is no longer in slow mode.`
+/// (without the channel part for notices that were not sent to a channel).
+impl fmt::Display for NoticeMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(channel_login) = &self.channel_login {
+            write!(f, "#{} ", channel_login)?;
+        }
+        write!(f, "NOTICE: {}", self.message_text)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::{IRCMessage, NoticeMessage};
+    use crate::message::{IRCMessage, NoticeMessage, SendFailureReason};
     use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_send_failure_reason() {
+        let cases = [
+            ("msg_emoteonly", "This room is in emote-only mode.", Some(SendFailureReason::EmoteOnly)),
+            ("msg_subsonly", "This room is in subscribers-only mode. To talk, purchase a channel subscription at https://www.twitch.tv/products/pajlada/ticket?ref=subscriber_only_mode_chat.", Some(SendFailureReason::SubscribersOnly)),
+            ("msg_followersonly", "This room is in 10 minutes followers-only mode. Follow pajlada to join the community!", Some(SendFailureReason::FollowersOnly)),
+            ("msg_followersonly_zero", "This room is in followers-only mode. Follow pajlada to join the community!", Some(SendFailureReason::FollowersOnly)),
+            ("msg_followersonly_followed", "This room is in 10 minutes followers-only mode. You have been following for 5 minutes. Continue following to chat!", Some(SendFailureReason::FollowersOnly)),
+            ("msg_slowmode", "This room is in slow mode and you are sending messages too quickly. You will be able to talk again in 5 seconds.", Some(SendFailureReason::SlowMode)),
+            ("msg_r9k", "This room is in unique-chat mode and the message you attempted to send is not unique.", Some(SendFailureReason::UniqueChat)),
+            ("msg_duplicate", "Your message was not sent because it is identical to the previous one you sent, less than 30 seconds ago.", Some(SendFailureReason::DuplicateMessage)),
+            ("msg_banned", "You are permanently banned from talking in pajlada.", Some(SendFailureReason::Banned)),
+            ("msg_timedout", "You are timed out for 593 more seconds.", Some(SendFailureReason::TimedOut)),
+            ("msg_ratelimit", "Your message was not sent because you are sending messages too quickly.", Some(SendFailureReason::RateLimited)),
+            ("msg_channel_suspended", "This channel has been suspended.", Some(SendFailureReason::ChannelSuspended)),
+            ("emote_only_on", "This room is now in emote-only mode.", None),
+        ];
+
+        for (message_id, message_text, expected_reason) in cases.iter() {
+            let src = format!(
+                "@msg-id={} :tmi.twitch.tv NOTICE #pajlada :{}",
+                message_id, message_text
+            );
+            let msg = NoticeMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+            assert_eq!(
+                msg.send_failure_reason(),
+                *expected_reason,
+                "{}",
+                message_id
+            );
+        }
+
+        let src = ":tmi.twitch.tv NOTICE * :Improperly formatted auth";
+        let msg = NoticeMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.send_failure_reason(), None);
+    }
 
     #[test]
     pub fn test_basic() {
@@ -68,7 +174,7 @@ mod tests {
                 channel_login: Some("forsen".to_owned()),
                 message_text: "You are permanently banned from talking in forsen.".to_owned(),
                 message_id: Some("msg_banned".to_owned()),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -86,8 +192,22 @@ mod tests {
                 channel_login: None,
                 message_text: "Improperly formatted auth".to_owned(),
                 message_id: None,
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
+
+    #[test]
+    pub fn test_display() {
+        let src = "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen.";
+        let msg = NoticeMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.to_string(),
+            "#forsen NOTICE: You are permanently banned from talking in forsen."
+        );
+
+        let src = ":tmi.twitch.tv NOTICE * :Improperly formatted auth";
+        let msg = NoticeMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.to_string(), "NOTICE: Improperly formatted auth");
+    }
 }
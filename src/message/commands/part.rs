@@ -1,6 +1,8 @@
 use crate::message::commands::{IRCMessageParseExt, ServerMessageParseError};
 use crate::message::IRCMessage;
 use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -15,7 +17,7 @@ pub struct PartMessage {
     /// which is the logged in user).
     pub user_login: String,
     /// The message that this `PartMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for PartMessage {
@@ -29,14 +31,21 @@ impl TryFrom<IRCMessage> for PartMessage {
         Ok(PartMessage {
             channel_login: source.try_get_channel_login()?.to_owned(),
             user_login: source.try_get_prefix_nickname()?.to_owned(),
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<PartMessage> for IRCMessage {
     fn from(msg: PartMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line, e.g. `#pajlada randers811 parted`.
+impl fmt::Display for PartMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} parted", self.channel_login, self.user_login)
     }
 }
 
@@ -44,6 +53,7 @@ impl From<PartMessage> for IRCMessage {
 mod tests {
     use crate::message::{IRCMessage, PartMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -56,7 +66,7 @@ mod tests {
             PartMessage {
                 channel_login: "pajlada".to_owned(),
                 user_login: "randers811".to_owned(),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -1,6 +1,8 @@
+use crate::irc;
 use crate::message::commands::ServerMessageParseError;
 use crate::message::IRCMessage;
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -9,8 +11,13 @@ use {serde::Deserialize, serde::Serialize};
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct PingMessage {
+    /// The token carried by the `PING` (its last parameter), which has to be echoed by the `PONG`
+    /// replying to it. Twitch sends `PING :tmi.twitch.tv`, so this is usually `tmi.twitch.tv`.
+    /// `None` if the `PING` has no parameters.
+    pub token: Option<String>,
+
     /// The message that this `PingMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for PingMessage {
@@ -21,20 +28,38 @@ impl TryFrom<IRCMessage> for PingMessage {
             return Err(ServerMessageParseError::MismatchedCommand(source));
         }
 
-        Ok(PingMessage { source })
+        Ok(PingMessage {
+            token: source.params.last().cloned(),
+            source: Arc::new(source),
+        })
+    }
+}
+
+impl PingMessage {
+    /// Creates the `PONG` to reply to this `PING` with. As required by IRC, the `PONG` echoes
+    /// the last parameter of the `PING` (Twitch sends `PING :tmi.twitch.tv`). If the `PING`
+    /// has no parameters, `tmi.twitch.tv` is used instead.
+    ///
+    /// The client automatically replies to `PING`s, so you only need this when implementing
+    /// your own connection handling.
+    pub fn to_pong(&self) -> IRCMessage {
+        let token = self.token.as_deref().unwrap_or("tmi.twitch.tv");
+        irc!["PONG", token]
     }
 }
 
 impl From<PingMessage> for IRCMessage {
     fn from(msg: PingMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::irc;
     use crate::message::{IRCMessage, PingMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -45,7 +70,23 @@ mod tests {
         assert_eq!(
             msg,
             PingMessage {
-                source: irc_message
+                token: None,
+                source: Arc::new(irc_message)
+            }
+        )
+    }
+
+    #[test]
+    pub fn test_with_token() {
+        let src = "PING :tmi.twitch.tv";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PingMessage::try_from(irc_message.clone()).unwrap();
+
+        assert_eq!(
+            msg,
+            PingMessage {
+                token: Some("tmi.twitch.tv".to_owned()),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -60,8 +101,25 @@ mod tests {
         assert_eq!(
             msg,
             PingMessage {
-                source: irc_message
+                token: Some("abc def".to_owned()),
+                source: Arc::new(irc_message)
             }
         )
     }
+
+    #[test]
+    pub fn test_to_pong() {
+        let pong = |src: &str| {
+            PingMessage::try_from(IRCMessage::parse(src).unwrap())
+                .unwrap()
+                .to_pong()
+        };
+        assert_eq!(pong("PING :tmi.twitch.tv"), irc!["PONG", "tmi.twitch.tv"]);
+        assert_eq!(pong("PING :some-token"), irc!["PONG", "some-token"]);
+        assert_eq!(
+            pong(":tmi.twitch.tv PING test :abc def"),
+            irc!["PONG", "abc def"]
+        );
+        assert_eq!(pong(":tmi.twitch.tv PING"), irc!["PONG", "tmi.twitch.tv"]);
+    }
 }
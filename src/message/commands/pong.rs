@@ -1,6 +1,7 @@
 use crate::message::commands::ServerMessageParseError;
 use crate::message::IRCMessage;
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -9,8 +10,14 @@ use {serde::Deserialize, serde::Serialize};
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct PongMessage {
+    /// The token carried by the `PONG` (its last parameter), which echoes the token of the
+    /// `PING` it replies to. E.g. Twitch answers `PING test` with
+    /// `:tmi.twitch.tv PONG tmi.twitch.tv :test`, which carries the token `test`.
+    /// `None` if the `PONG` has no parameters.
+    pub token: Option<String>,
+
     /// The message that this `PongMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for PongMessage {
@@ -21,13 +28,16 @@ impl TryFrom<IRCMessage> for PongMessage {
             return Err(ServerMessageParseError::MismatchedCommand(source));
         }
 
-        Ok(PongMessage { source })
+        Ok(PongMessage {
+            token: source.params.last().cloned(),
+            source: Arc::new(source),
+        })
     }
 }
 
 impl From<PongMessage> for IRCMessage {
     fn from(msg: PongMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
     }
 }
 
@@ -35,6 +45,7 @@ impl From<PongMessage> for IRCMessage {
 mod tests {
     use crate::message::{IRCMessage, PongMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -46,7 +57,8 @@ mod tests {
         assert_eq!(
             msg,
             PongMessage {
-                source: irc_message
+                token: Some("tmi.twitch.tv".to_owned()),
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -61,7 +73,23 @@ mod tests {
         assert_eq!(
             msg,
             PongMessage {
-                source: irc_message
+                token: Some("test".to_owned()),
+                source: Arc::new(irc_message)
+            }
+        )
+    }
+
+    #[test]
+    pub fn test_without_token() {
+        let src = ":tmi.twitch.tv PONG";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PongMessage::try_from(irc_message.clone()).unwrap();
+
+        assert_eq!(
+            msg,
+            PongMessage {
+                token: None,
+                source: Arc::new(irc_message)
             }
         )
     }
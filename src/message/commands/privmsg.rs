@@ -1,13 +1,26 @@
 use crate::message::commands::IRCMessageParseExt;
-use crate::message::twitch::{Badge, Emote, RGBColor, TwitchUserBasics};
+use crate::message::twitch::{
+    group_emotes_by_id, subscriber_months, Badge, Emote, PermissionLevel, RGBColor,
+    TwitchUserBasics,
+};
 use crate::message::{IRCMessage, ServerMessageParseError};
+#[cfg(feature = "with-chrono")]
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
 
 /// A regular Twitch chat message.
+///
+/// Messages received without the `twitch.tv/tags` capability carry no tags at all. They are
+/// still parsed, but only `channel_login`, `sender.login`, `message_text` and `is_action` are
+/// known then: `sender.name` is set to the login name, `channel_id`, `sender.id` and
+/// `message_id` are empty, and all other fields are empty, `None` or `false`.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct PrivmsgMessage {
@@ -50,10 +63,190 @@ pub struct PrivmsgMessage {
     /// delete single messages (see also the `CLEARMSG` message type)
     pub message_id: String,
     /// Timestamp of when this message was sent.
-    pub server_timestamp: DateTime<Utc>,
+    /// `None` if the `tmi-sent-ts` tag is missing or malformed.
+    ///
+    /// Only available with the `with-chrono` feature, see `server_timestamp_millis` for
+    /// an alternative.
+    #[cfg(feature = "with-chrono")]
+    pub server_timestamp: Option<DateTime<Utc>>,
+    /// Same as `server_timestamp`, as milliseconds since the Unix epoch (the value of the
+    /// `tmi-sent-ts` tag). `None` if the tag is missing or malformed.
+    ///
+    /// Unlike `server_timestamp`, this is also available without the `with-chrono` feature.
+    pub server_timestamp_millis: Option<i64>,
+    /// If this message was sent in a different channel that is part of the same
+    /// shared chat session as `channel_login`, this holds the details about that channel.
+    /// `None` for messages sent directly in this channel.
+    pub source_channel: Option<SharedChatSource>,
+    /// Whether this message should only be displayed in the channel it was sent in, not in
+    /// the other channels of the shared chat session (tagged `source-only=1`). Together with
+    /// `source_channel`, this identifies copies of such messages received in the other
+    /// channels, see `ClientConfig::drop_source_only_shared_messages`. `false` if the tag
+    /// is absent.
+    pub source_only: bool,
+    /// If present, this message was sent by redeeming the channel points reward with this ID
+    /// (a custom reward that requires the user to enter text).
+    ///
+    /// Note that messages highlighted using the built-in "Highlight My Message" reward are
+    /// marked using `is_highlighted` instead.
+    pub custom_reward_id: Option<String>,
+    /// Whether this message was highlighted by redeeming the built-in "Highlight My Message"
+    /// channel points reward (tagged with `msg-id=highlighted-message`).
+    pub is_highlighted: bool,
+    /// Whether this message is a replayed copy of an older message (tagged `historical=1`),
+    /// as opposed to a message that was just sent. Replayed messages are e.g. injected by
+    /// services providing the recent chat history after (re)joining a channel, so they
+    /// should usually not trigger bot commands again. `false` if the tag is absent.
+    pub is_historical: bool,
+    /// Whether the message is tagged `vip=1`, which Twitch sends for VIPs of the channel in
+    /// addition to the `vip` badge, also if the VIP chose to display a different badge.
+    /// `false` if the tag is absent. See `is_vip()`, which also takes the badges into account.
+    pub vip: bool,
+    /// For replayed messages (see `is_historical`), when the message was originally received
+    /// by the service replaying it (`rm-received-ts` tag). `None` if the tag is absent or
+    /// malformed.
+    ///
+    /// Only available with the `with-chrono` feature.
+    #[cfg(feature = "with-chrono")]
+    pub historical_received_at: Option<DateTime<Utc>>,
+    /// If present, this message is a paid pinned message (Hype Chat). `None` if the
+    /// `pinned-chat-paid-*` tags are absent, incomplete or malformed.
+    pub hype_chat: Option<HypeChat>,
+    /// If this message is a reply, the message that was directly replied to. `None` if the
+    /// `reply-parent-*` tags are absent or incomplete.
+    pub reply_parent: Option<ReplyParent>,
+    /// If this message is a reply, the first message of the reply thread it is part of.
+    /// This is the same message as `reply_parent` if the message directly replies to
+    /// the start of the thread. `None` if the `reply-thread-parent-*` tags are absent or
+    /// incomplete.
+    pub reply_thread: Option<ReplyThread>,
 
     /// The message that this `PrivmsgMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
+}
+
+/// Identifies the channel a message was originally sent in, for messages that were
+/// shared from another channel during a shared chat session.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct SharedChatSource {
+    /// ID of the channel that the message was originally sent to.
+    pub channel_id: String,
+    /// ID of the message in the channel that it was originally sent to.
+    /// This differs from the `message_id` of the shared message.
+    pub message_id: Option<String>,
+    /// Metadata related to the chat badges the sender has in the source channel,
+    /// see `PrivmsgMessage::badge_info`.
+    pub badge_info: Vec<Badge>,
+    /// List of badges the sender has in the source channel.
+    pub badges: Vec<Badge>,
+}
+
+/// Details about a paid pinned message (Hype Chat).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct HypeChat {
+    /// The amount that was paid, in the minor unit of the currency (e.g. cents).
+    /// The actual value is `amount / 10^exponent`.
+    pub amount: u64,
+    /// How many decimal places the `amount` is shifted by, e.g. `2` for US dollars.
+    pub exponent: u32,
+    /// ISO 4217 code of the currency that was paid in, e.g. `USD`.
+    pub currency: String,
+    /// The level of the Hype Chat, which determines how long the message is pinned,
+    /// from `ONE` to `TEN`.
+    pub level: String,
+    /// Whether the message was generated by Twitch rather than typed by the user.
+    pub is_system_message: bool,
+}
+
+impl HypeChat {
+    // the Hype Chat details are not essential to the message, so if some of the tags are
+    // missing or malformed, the message is still parsed, with `hype_chat` set to `None`.
+    fn parse_from_tags(source: &IRCMessage) -> Option<HypeChat> {
+        Some(HypeChat {
+            amount: source.try_get_number("pinned-chat-paid-amount").ok()?,
+            exponent: source.try_get_number("pinned-chat-paid-exponent").ok()?,
+            currency: source
+                .try_get_nonempty_tag_value("pinned-chat-paid-currency")
+                .ok()?
+                .to_owned(),
+            level: source
+                .try_get_nonempty_tag_value("pinned-chat-paid-level")
+                .ok()?
+                .to_owned(),
+            is_system_message: source
+                .try_get_optional_bool("pinned-chat-paid-is-system-message")
+                .ok()?
+                .unwrap_or(false),
+        })
+    }
+
+    /// The paid amount in the major unit of the currency (e.g. dollars), for display purposes.
+    pub fn amount_as_f64(&self) -> f64 {
+        self.amount as f64 / 10f64.powi(self.exponent as i32)
+    }
+}
+
+/// The message that a reply was sent in response to, see `PrivmsgMessage::reply_parent`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct ReplyParent {
+    /// ID of the message that was replied to.
+    pub message_id: String,
+    /// The user that sent the message that was replied to.
+    pub sender: TwitchUserBasics,
+    /// The text of the message that was replied to.
+    pub message_text: String,
+}
+
+impl ReplyParent {
+    // like for `HypeChat`, a reply with missing or empty `reply-parent-*` tags is still
+    // parsed as a message, with `reply_parent` set to `None`.
+    fn parse_from_tags(source: &IRCMessage) -> Option<ReplyParent> {
+        Some(ReplyParent {
+            message_id: get_nonempty_reply_tag(source, "reply-parent-msg-id")?,
+            sender: TwitchUserBasics {
+                id: get_nonempty_reply_tag(source, "reply-parent-user-id")?,
+                login: get_nonempty_reply_tag(source, "reply-parent-user-login")?,
+                name: get_nonempty_reply_tag(source, "reply-parent-display-name")?,
+            },
+            message_text: source
+                .try_get_tag_value("reply-parent-msg-body")
+                .ok()?
+                .unwrap_or_default()
+                .to_owned(),
+        })
+    }
+}
+
+/// The first message of a reply thread, see `PrivmsgMessage::reply_thread`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct ReplyThread {
+    /// ID of the first message of the thread.
+    pub message_id: String,
+    /// Login name of the user that sent the first message of the thread.
+    pub sender_login: String,
+}
+
+impl ReplyThread {
+    // see `ReplyParent::parse_from_tags()`
+    fn parse_from_tags(source: &IRCMessage) -> Option<ReplyThread> {
+        Some(ReplyThread {
+            message_id: get_nonempty_reply_tag(source, "reply-thread-parent-msg-id")?,
+            sender_login: get_nonempty_reply_tag(source, "reply-thread-parent-user-login")?,
+        })
+    }
+}
+
+/// The value of the tag `key`, or `None` if the tag is missing or its value is empty.
+fn get_nonempty_reply_tag(source: &IRCMessage, key: &'static str) -> Option<String> {
+    source
+        .try_get_nonempty_tag_value(key)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_owned())
 }
 
 impl TryFrom<IRCMessage> for PrivmsgMessage {
@@ -66,9 +259,76 @@ impl TryFrom<IRCMessage> for PrivmsgMessage {
 
         let (message_text, is_action) = source.try_get_message_text()?;
 
+        // without the twitch.tv/tags capability, only the data from the prefix and parameters
+        // is available, see the documentation of the struct.
+        if source.tags.0.is_empty() {
+            let sender_login = source.try_get_prefix_nickname()?.to_owned();
+            return Ok(PrivmsgMessage {
+                channel_login: source.try_get_channel_login()?.to_owned(),
+                channel_id: String::new(),
+                sender: TwitchUserBasics {
+                    id: String::new(),
+                    name: sender_login.clone(),
+                    login: sender_login,
+                },
+                badge_info: vec![],
+                badges: vec![],
+                bits: None,
+                name_color: None,
+                emotes: vec![],
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: None,
+                server_timestamp_millis: None,
+                message_id: String::new(),
+                source_channel: None,
+                source_only: false,
+                custom_reward_id: None,
+                is_highlighted: false,
+                is_historical: false,
+                vip: false,
+                #[cfg(feature = "with-chrono")]
+                historical_received_at: None,
+                hype_chat: None,
+                reply_parent: None,
+                reply_thread: None,
+                message_text: message_text.to_owned(),
+                is_action,
+                source: Arc::new(source),
+            });
+        }
+
+        let channel_id = source.try_get_nonempty_tag_value("room-id")?;
+
+        // Twitch also sends the source-* tags (with the message's own channel as the source
+        // channel) for messages sent directly in a channel that is part of a shared chat session.
+        let source_channel = match source.try_get_optional_nonempty_tag_value("source-room-id")? {
+            Some(source_channel_id) if source_channel_id != channel_id => Some(SharedChatSource {
+                channel_id: source_channel_id.to_owned(),
+                message_id: source
+                    .try_get_optional_nonempty_tag_value("source-id")?
+                    .map(|s| s.to_owned()),
+                badge_info: if source.tags.0.contains_key("source-badge-info") {
+                    source.try_get_badges("source-badge-info")?
+                } else {
+                    vec![]
+                },
+                badges: if source.tags.0.contains_key("source-badges") {
+                    source.try_get_badges("source-badges")?
+                } else {
+                    vec![]
+                },
+            }),
+            _ => None,
+        };
+
+        let hype_chat = HypeChat::parse_from_tags(&source);
+
+        let reply_parent = ReplyParent::parse_from_tags(&source);
+        let reply_thread = ReplyThread::parse_from_tags(&source);
+
         Ok(PrivmsgMessage {
             channel_login: source.try_get_channel_login()?.to_owned(),
-            channel_id: source.try_get_nonempty_tag_value("room-id")?.to_owned(),
+            channel_id: channel_id.to_owned(),
             sender: TwitchUserBasics {
                 id: source.try_get_nonempty_tag_value("user-id")?.to_owned(),
                 login: source.try_get_prefix_nickname()?.to_owned(),
@@ -81,29 +341,289 @@ impl TryFrom<IRCMessage> for PrivmsgMessage {
             bits: source.try_get_optional_number("bits")?,
             name_color: source.try_get_color("color")?,
             emotes: source.try_get_emotes("emotes", &message_text)?,
-            server_timestamp: source.try_get_timestamp("tmi-sent-ts")?,
+            #[cfg(feature = "with-chrono")]
+            server_timestamp: source.get_optional_timestamp("tmi-sent-ts"),
+            server_timestamp_millis: source.get_optional_timestamp_millis("tmi-sent-ts"),
             message_id: source.try_get_nonempty_tag_value("id")?.to_owned(),
+            source_channel,
+            source_only: source
+                .try_get_optional_bool("source-only")?
+                .unwrap_or(false),
+            custom_reward_id: source
+                .try_get_optional_nonempty_tag_value("custom-reward-id")?
+                .map(|s| s.to_owned()),
+            is_highlighted: matches!(
+                source.tags.0.get("msg-id"),
+                Some(Some(msg_id)) if msg_id == "highlighted-message"
+            ),
+            is_historical: source.try_get_optional_bool("historical")?.unwrap_or(false),
+            vip: matches!(source.tags.0.get("vip"), Some(Some(vip)) if vip == "1"),
+            #[cfg(feature = "with-chrono")]
+            historical_received_at: source.get_optional_timestamp("rm-received-ts"),
+            hype_chat,
+            reply_parent,
+            reply_thread,
             message_text: message_text.to_owned(),
             is_action,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<PrivmsgMessage> for IRCMessage {
     fn from(msg: PrivmsgMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Maximum length of a Twitch login name.
+const MAX_LOGIN_LENGTH: usize = 25;
+
+fn is_login_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl PrivmsgMessage {
+    /// Returns the `emotes` of this message grouped by emote ID, with the character ranges of
+    /// all occurrences of each emote. `emotes` is left unchanged, see `group_emotes_by_id()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=25:0-4,12-16;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :Kappa hello Kappa").unwrap()).unwrap();
+    ///
+    /// assert_eq!(message.emotes_by_id()["25"].len(), 2);
+    /// ```
+    pub fn emotes_by_id(&self) -> HashMap<String, Vec<Range<usize>>> {
+        group_emotes_by_id(&self.emotes)
+    }
+
+    /// Returns this message with the retained `source` replaced by an empty `IRCMessage`
+    /// (no tags, no prefix, empty command and no parameters).
+    ///
+    /// `source` holds all tags of the original message as strings, so it typically takes up
+    /// more memory than all other (parsed) fields combined. Use this before storing many
+    /// messages for a long time if you don't need `source` later. Note that after this,
+    /// `as_raw_irc()` and the conversion back to an `IRCMessage` only produce the empty
+    /// message, since they are based on `source`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam").unwrap()).unwrap();
+    /// let message = message.without_source();
+    ///
+    /// assert_eq!(message.message_text, "dank cam");
+    /// assert!(message.source.tags.0.is_empty());
+    /// ```
+    pub fn without_source(mut self) -> PrivmsgMessage {
+        self.source = Arc::new(IRCMessage::new_simple(String::new(), vec![]));
+        self
+    }
+
+    /// Returns the highest permission level the sender has in the channel, based on the
+    /// `badges` of this message. If the sender has multiple of the relevant badges (e.g. a
+    /// moderator that is also subscribed), the highest level wins.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PermissionLevel, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :!ban someone").unwrap()).unwrap();
+    ///
+    /// assert!(message.permission_level() >= PermissionLevel::Moderator);
+    /// ```
+    pub fn permission_level(&self) -> PermissionLevel {
+        let badge_level = self
+            .badges
+            .iter()
+            .map(|badge| match badge.name.as_str() {
+                "broadcaster" => PermissionLevel::Broadcaster,
+                "moderator" | "lead_moderator" => PermissionLevel::Moderator,
+                "vip" => PermissionLevel::Vip,
+                "subscriber" | "founder" => PermissionLevel::Subscriber,
+                _ => PermissionLevel::Everyone,
+            })
+            .max()
+            .unwrap_or(PermissionLevel::Everyone);
+        if self.is_vip() {
+            badge_level.max(PermissionLevel::Vip)
+        } else {
+            badge_level
+        }
+    }
+
+    /// Returns whether the sender is a VIP of the channel, based on the `vip` badge, or the
+    /// `vip=1` tag that Twitch sends in addition (see the `vip` field, e.g. if the VIP chose
+    /// to display a different badge).
+    ///
+    /// Moderators are never VIPs at the same time, so unlike `permission_level()`, this
+    /// can be used to treat VIPs and moderators differently.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=;badges=vip/1;color=#19E6E6;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type=;vip=1 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :hello").unwrap()).unwrap();
+    ///
+    /// assert!(message.is_vip());
+    /// ```
+    pub fn is_vip(&self) -> bool {
+        self.vip || self.badges.iter().any(|badge| badge.name == "vip")
+    }
+
+    /// Returns the exact number of months the sender has been subscribed to the channel, as
+    /// found in the `badge_info`. Returns `None` if the sender is not subscribed.
+    ///
+    /// Founders carry their month count on the `founder` entry instead of `subscriber`,
+    /// which is read as well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :hello").unwrap()).unwrap();
+    ///
+    /// assert_eq!(message.subscriber_months(), Some(22));
+    /// ```
+    pub fn subscriber_months(&self) -> Option<u64> {
+        subscriber_months(&self.badge_info)
+    }
+
+    /// Returns the user logins that were `@`-mentioned in the `message_text`, in the order
+    /// they appear in the message, without the leading `@`.
+    ///
+    /// A mention is an `@` that is not directly preceded by a letter, digit or underscore
+    /// (so e-mail addresses like `user@example.com` are not matched), followed by up to 25 of
+    /// the characters Twitch allows in login names (ASCII letters, digits and underscores).
+    /// Any punctuation after the name (e.g. `@randers,` or `@randers!`) ends the mention.
+    ///
+    /// The logins are returned as they were written in the message, so they might not be
+    /// lowercase. Duplicate mentions are returned once per occurrence.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :@pajlada, @Randers: hi").unwrap()).unwrap();
+    ///
+    /// assert_eq!(message.mentions(), vec!["pajlada", "Randers"]);
+    /// ```
+    pub fn mentions(&self) -> Vec<&str> {
+        let text = self.message_text.as_str();
+        let mut mentions = vec![];
+        let mut previous_char = None;
+
+        for (at_index, c) in text.char_indices() {
+            let preceded_by_login_char = matches!(previous_char, Some(c) if is_login_char(c));
+            previous_char = Some(c);
+            if c != '@' || preceded_by_login_char {
+                continue;
+            }
+
+            let login_start = at_index + 1;
+            let login_end = text[login_start..]
+                .find(|c: char| !is_login_char(c))
+                .map_or(text.len(), |offset| login_start + offset);
+            let login = &text[login_start..login_end];
+            if !login.is_empty() && login.len() <= MAX_LOGIN_LENGTH {
+                mentions.push(login);
+            }
+        }
+
+        mentions
+    }
+}
+
+/// Formats the message as a single chat-log-style line, e.g. `#pajlada Randers: Kappa`.
+/// `/me` messages are formatted as `#pajlada * Randers Kappa` instead.
+impl fmt::Display for PrivmsgMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_action {
+            write!(
+                f,
+                "#{} * {} {}",
+                self.channel_login, self.sender.name, self.message_text
+            )
+        } else {
+            write!(
+                f,
+                "#{} {}: {}",
+                self.channel_login, self.sender.name, self.message_text
+            )
+        }
+    }
+}
+
+/// The information needed to reply to a `PrivmsgMessage`, see `TwitchIRCClient::reply()`.
+///
+/// This is much smaller than the full `PrivmsgMessage` (which also keeps its `source`), so it
+/// is cheap to store when replies are sent later, e.g. after some asynchronous work or when
+/// queueing many replies.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::{IRCMessage, PrivmsgMessage, ReplyContext};
+/// use std::convert::TryFrom;
+///
+/// let message = PrivmsgMessage::try_from(IRCMessage::parse("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam").unwrap()).unwrap();
+///
+/// let reply_context = ReplyContext::from(&message);
+/// assert_eq!(reply_context.channel_login, "pajlada");
+/// assert_eq!(reply_context.message_id, "e9d998c3-36f1-430f-89ec-6b887c28af36");
+/// assert_eq!(reply_context.sender_login, "jun1orrrr");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct ReplyContext {
+    /// Login name of the channel the original message was sent to.
+    pub channel_login: String,
+    /// ID of the original message, see `PrivmsgMessage::message_id`. Empty if the original
+    /// message was received without tags, `TwitchIRCClient::reply()` then sends a normal
+    /// message instead of a reply.
+    pub message_id: String,
+    /// Login name of the user that sent the original message.
+    pub sender_login: String,
+}
+
+impl From<&PrivmsgMessage> for ReplyContext {
+    fn from(msg: &PrivmsgMessage) -> ReplyContext {
+        ReplyContext {
+            channel_login: msg.channel_login.clone(),
+            message_id: msg.message_id.clone(),
+            sender_login: msg.sender.login.clone(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::twitch::{Badge, Emote, RGBColor, TwitchUserBasics};
-    use crate::message::{IRCMessage, PrivmsgMessage};
-    use chrono::offset::TimeZone;
-    use chrono::Utc;
+    use crate::message::twitch::{Badge, Emote, PermissionLevel, RGBColor, TwitchUserBasics};
+    use crate::message::{
+        AsRawIRC, HypeChat, IRCMessage, PrivmsgMessage, ReplyParent, ReplyThread, ServerMessage,
+        ServerMessageParseError, SharedChatSource,
+    };
+    #[cfg(feature = "with-chrono")]
+    use chrono::{TimeZone, Utc};
+    use maplit::hashmap;
     use std::convert::TryFrom;
     use std::ops::Range;
+    use std::sync::Arc;
 
     #[test]
     fn test_basic_example() {
@@ -132,10 +652,23 @@ mod tests {
                     b: 0xFF
                 }),
                 emotes: vec![],
-                server_timestamp: Utc.timestamp_millis(1594545155039),
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594545155039)),
+                server_timestamp_millis: Some(1594545155039),
                 message_id: "e9d998c3-36f1-430f-89ec-6b887c28af36".to_owned(),
+                source_channel: None,
+                source_only: false,
+                custom_reward_id: None,
+                is_highlighted: false,
+                is_historical: false,
+                vip: false,
+                #[cfg(feature = "with-chrono")]
+                historical_received_at: None,
+                hype_chat: None,
+                reply_parent: None,
+                reply_thread: None,
 
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
@@ -179,10 +712,23 @@ mod tests {
                     b: 0xE6
                 }),
                 emotes: vec![],
-                server_timestamp: Utc.timestamp_millis(1594555275886),
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594555275886)),
+                server_timestamp_millis: Some(1594555275886),
                 message_id: "d831d848-b7c7-4559-ae3a-2cb88f4dbfed".to_owned(),
+                source_channel: None,
+                source_only: false,
+                custom_reward_id: None,
+                is_highlighted: false,
+                is_historical: false,
+                vip: false,
+                #[cfg(feature = "with-chrono")]
+                historical_received_at: None,
+                hype_chat: None,
+                reply_parent: None,
+                reply_thread: None,
 
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
@@ -210,14 +756,103 @@ mod tests {
                 bits: None,
                 name_color: None,
                 emotes: vec![],
-                server_timestamp: Utc.timestamp_millis(1594554085753),
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1594554085753)),
+                server_timestamp_millis: Some(1594554085753),
                 message_id: "c9b941d9-a0ab-4534-9903-971768fcdf10".to_owned(),
+                source_channel: None,
+                source_only: false,
+                custom_reward_id: None,
+                is_highlighted: false,
+                is_historical: true,
+                vip: false,
+                #[cfg(feature = "with-chrono")]
+                historical_received_at: Some(Utc.timestamp_millis_opt(1594554085918).unwrap()),
+                hype_chat: None,
+                reply_parent: None,
+                reply_thread: None,
 
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         );
     }
 
+    #[test]
+    fn test_historical() {
+        // replayed message
+        let src = "@rm-received-ts=1594554085918;historical=1;badge-info=;badges=;client-nonce=815810609edecdf4537bd9586994182b;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(msg.is_historical);
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(
+            msg.historical_received_at,
+            Some(Utc.timestamp_millis_opt(1594554085918).unwrap())
+        );
+
+        // live message
+        let src = "@badge-info=;badges=;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(!msg.is_historical);
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(msg.historical_received_at, None);
+
+        // malformed replay timestamp
+        let src = "@rm-received-ts=yesterday;historical=1;badge-info=;badges=;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(msg.is_historical);
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(msg.historical_received_at, None);
+        assert_eq!(msg.message_text, "NaM");
+    }
+
+    #[test]
+    fn test_missing_server_timestamp() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        let msg = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        #[cfg(feature = "with-chrono")]
+        assert_eq!(msg.server_timestamp(), None);
+        assert_eq!(msg.server_time(), None);
+        match msg {
+            ServerMessage::Privmsg(msg) => {
+                #[cfg(feature = "with-chrono")]
+                assert_eq!(msg.server_timestamp, None);
+                assert_eq!(msg.server_timestamp_millis, None);
+                assert_eq!(msg.message_text, "dank cam");
+            }
+            _ => panic!("expected a PRIVMSG"),
+        }
+    }
+
+    #[test]
+    fn test_malformed_server_timestamp() {
+        for tag_value in &["", "yesterday", "99999999999999999999"] {
+            let src = format!("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts={};turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam", tag_value);
+            let msg = PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+            #[cfg(feature = "with-chrono")]
+            assert_eq!(msg.server_timestamp, None);
+            assert_eq!(msg.server_timestamp_millis, None);
+            assert_eq!(msg.message_text, "dank cam");
+        }
+    }
+
+    #[test]
+    fn test_malformed_timestamps_not_generic() {
+        let src = "@rm-received-ts=yesterday;historical=1;badge-info=;badges=;color=;display-name=CarvedTaleare;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=today;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM";
+        let msg = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.server_time(), None);
+        match msg {
+            ServerMessage::Privmsg(msg) => {
+                assert!(msg.is_historical);
+                #[cfg(feature = "with-chrono")]
+                assert_eq!(msg.server_timestamp, None);
+                #[cfg(feature = "with-chrono")]
+                assert_eq!(msg.historical_received_at, None);
+                assert_eq!(msg.message_text, "NaM");
+            }
+            _ => panic!("expected a PRIVMSG"),
+        }
+    }
+
     #[test]
     fn test_display_name_with_trailing_space() {
         let src = "@rm-received-ts=1594554085918;historical=1;badge-info=;badges=;client-nonce=815810609edecdf4537bd9586994182b;color=;display-name=CarvedTaleare\\s;emotes=;flags=;id=c9b941d9-a0ab-4534-9903-971768fcdf10;mod=0;room-id=22484632;subscriber=0;tmi-sent-ts=1594554085753;turbo=0;user-id=467684514;user-type= :carvedtaleare!carvedtaleare@carvedtaleare.tmi.twitch.tv PRIVMSG #forsen :NaM";
@@ -300,6 +935,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_emotes_by_id() {
+        let src = "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=1902:6-10,29-33,35-39/499:45-46,48-49/490:51-52/25:0-4,12-16,18-22;flags=;id=f9c5774b-faa7-4378-b1af-c4e08b532dc2;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594556065407;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa Keepo Kappa Kappa test Keepo Keepo 123 :) :) :P";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message).unwrap();
+
+        assert_eq!(
+            msg.emotes_by_id(),
+            hashmap! {
+                "25".to_owned() => vec![0..5, 12..17, 18..23],
+                "1902".to_owned() => vec![6..11, 29..34, 35..40],
+                "499".to_owned() => vec![45..47, 48..50],
+                "490".to_owned() => vec![51..53],
+            }
+        );
+        // the flat list is still available
+        assert_eq!(msg.emotes.len(), 9);
+    }
+
+    #[test]
+    fn test_emotes_by_id_no_emotes() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(msg.emotes_by_id().is_empty());
+    }
+
     #[test]
     fn test_emote_non_numeric_id() {
         // emote tag specifies an index that's out of bounds.
@@ -428,4 +1089,559 @@ mod tests {
             }]
         );
     }
+
+    #[test]
+    fn test_emote_end_before_start() {
+        let src = r"@badge-info=;badges=;color=;display-name=randers;emotes=25:4-0;flags=;id=744f9c58-b180-4f46-bd9e-b515b5ef75c1;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1566335866017;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let result = PrivmsgMessage::try_from(irc_message.clone());
+
+        assert_eq!(
+            result,
+            Err(ServerMessageParseError::MalformedTagValue(
+                irc_message,
+                "emotes",
+                "25:4-0".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_emote_end_index_overflow() {
+        let src = format!(
+            "@badge-info=;badges=;color=;display-name=randers;emotes=25:0-{};flags=;id=744f9c58-b180-4f46-bd9e-b515b5ef75c1;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1566335866017;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa",
+            usize::MAX
+        );
+        let irc_message = IRCMessage::parse(&src).unwrap();
+        let result = PrivmsgMessage::try_from(irc_message);
+
+        assert!(matches!(
+            result,
+            Err(ServerMessageParseError::MalformedTagValue(_, "emotes", _))
+        ));
+    }
+
+    #[test]
+    fn test_emotes_random_tag_values_never_panic() {
+        // feeds a large number of pseudo-random (but deterministic) emotes tag values through
+        // the parser. The parse may fail, but only ever with a MalformedTagValue error.
+        let src = "@badge-info=;badges=;color=;display-name=randers;emotes=;flags=;id=744f9c58-b180-4f46-bd9e-b515b5ef75c1;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1566335866017;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa 👉 Keepo";
+        let base_message = IRCMessage::parse(src).unwrap();
+
+        let alphabet: Vec<char> = "0123456789:-,/_9".chars().collect();
+        // xorshift64, see https://en.wikipedia.org/wiki/Xorshift
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..10_000 {
+            let length = (next_random() % 24) as usize;
+            let tag_value: String = (0..length)
+                .map(|_| alphabet[(next_random() % alphabet.len() as u64) as usize])
+                .collect();
+
+            let mut irc_message = base_message.clone();
+            irc_message
+                .tags
+                .0
+                .insert("emotes".to_owned(), Some(tag_value.clone()));
+
+            match PrivmsgMessage::try_from(irc_message) {
+                Ok(msg) => {
+                    for emote in msg.emotes {
+                        assert!(emote.char_range.start < emote.char_range.end);
+                    }
+                }
+                Err(ServerMessageParseError::MalformedTagValue(_, "emotes", value)) => {
+                    assert_eq!(value, tag_value)
+                }
+                Err(e) => panic!("unexpected error for tag value {:?}: {}", tag_value, e),
+            }
+        }
+    }
+
+    fn parse_with_text(message_text: &str) -> PrivmsgMessage {
+        let src = format!("@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :{}", message_text);
+        PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_mentions() {
+        let cases: &[(&str, &[&str])] = &[
+            ("no mentions here", &[]),
+            ("@pajlada", &["pajlada"]),
+            ("@pajlada hi", &["pajlada"]),
+            ("hi @pajlada", &["pajlada"]),
+            (
+                "@pajlada @Randers @pajlada",
+                &["pajlada", "Randers", "pajlada"],
+            ),
+            (
+                "@pajlada, @randers: @user_1! (@zneix) @x.",
+                &["pajlada", "randers", "user_1", "zneix", "x"],
+            ),
+            ("@pajlada's stream", &["pajlada"]),
+            ("mail me at randers@example.com", &[]),
+            ("user_@randers x@y 1@z", &[]),
+            ("@ alone @@pajlada @", &["pajlada"]),
+            ("@pajlada@randers", &["pajlada"]),
+            ("KappaHD@pajlada", &[]),
+            ("äöü@pajlada", &["pajlada"]),
+            ("@pajladaä", &["pajlada"]),
+            ("@abcdefghijklmnopqrstuvwxy", &["abcdefghijklmnopqrstuvwxy"]),
+            ("@abcdefghijklmnopqrstuvwxyz", &[]),
+        ];
+
+        for (message_text, expected) in cases {
+            let msg = parse_with_text(message_text);
+            assert_eq!(
+                msg.mentions(),
+                *expected,
+                "message text: {:?}",
+                message_text
+            );
+        }
+    }
+
+    #[test]
+    fn test_mentions_in_action() {
+        let src = "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :\u{0001}ACTION waves at @pajlada\u{0001}";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.mentions(), vec!["pajlada"]);
+    }
+
+    #[test]
+    fn test_custom_reward_id() {
+        let src = "@badge-info=;badges=;color=#0000FF;custom-reward-id=be22f712-8fd9-426a-90df-c13eae6cf6f4;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :my redemption text";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.custom_reward_id,
+            Some("be22f712-8fd9-426a-90df-c13eae6cf6f4".to_owned())
+        );
+        assert_eq!(msg.message_text, "my redemption text");
+        assert!(!msg.is_highlighted);
+    }
+
+    #[test]
+    fn test_highlighted_message() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;msg-id=highlighted-message;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :look at me";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(msg.is_highlighted);
+        assert_eq!(msg.custom_reward_id, None);
+        assert_eq!(msg.message_text, "look at me");
+    }
+
+    #[test]
+    fn test_hype_chat() {
+        let src = "@badge-info=;badges=glhf-pledge/1;color=#FF69B4;display-name=ExampleUser;emotes=;first-msg=0;flags=;id=f6d4d2a3-0d5e-4a6c-9f45-4e8e4a1b2c3d;mod=0;pinned-chat-paid-amount=500;pinned-chat-paid-canonical-amount=5;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;pinned-chat-paid-is-system-message=0;pinned-chat-paid-level=ONE;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1687471984304;turbo=0;user-id=40286300;user-type= :exampleuser!exampleuser@exampleuser.tmi.twitch.tv PRIVMSG #pajlada :hype chat test";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        let hype_chat = msg.hype_chat.unwrap();
+        assert_eq!(
+            hype_chat,
+            HypeChat {
+                amount: 500,
+                exponent: 2,
+                currency: "USD".to_owned(),
+                level: "ONE".to_owned(),
+                is_system_message: false,
+            }
+        );
+        assert!((hype_chat.amount_as_f64() - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_no_hype_chat() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.hype_chat, None);
+    }
+
+    #[test]
+    fn test_incomplete_hype_chat() {
+        // currency and level missing
+        let src = "@badge-info=;badges=glhf-pledge/1;color=#FF69B4;display-name=ExampleUser;emotes=;first-msg=0;flags=;id=f6d4d2a3-0d5e-4a6c-9f45-4e8e4a1b2c3d;mod=0;pinned-chat-paid-amount=500;pinned-chat-paid-exponent=2;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1687471984304;turbo=0;user-id=40286300;user-type= :exampleuser!exampleuser@exampleuser.tmi.twitch.tv PRIVMSG #pajlada :hype chat test";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.hype_chat, None);
+        assert_eq!(msg.message_text, "hype chat test");
+
+        // malformed amount
+        let src = "@badge-info=;badges=glhf-pledge/1;color=#FF69B4;display-name=ExampleUser;emotes=;first-msg=0;flags=;id=f6d4d2a3-0d5e-4a6c-9f45-4e8e4a1b2c3d;mod=0;pinned-chat-paid-amount=five;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;pinned-chat-paid-is-system-message=0;pinned-chat-paid-level=ONE;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1687471984304;turbo=0;user-id=40286300;user-type= :exampleuser!exampleuser@exampleuser.tmi.twitch.tv PRIVMSG #pajlada :hype chat test";
+        let msg = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        match msg {
+            ServerMessage::Privmsg(msg) => assert_eq!(msg.hype_chat, None),
+            _ => panic!("expected a PRIVMSG"),
+        }
+    }
+
+    #[test]
+    fn test_incomplete_reply_tags() {
+        // reply-parent-user-login missing, reply-thread-parent-user-login empty
+        let src = "@badge-info=;badges=;color=#FF0000;display-name=Randers;emotes=;first-msg=0;flags=;id=3f5a3b0e-7a1c-4a5e-9a39-1f0bd1e52a8b;mod=0;reply-parent-display-name=pajlada;reply-parent-msg-body=@Randers\\sI\\sdisagree;reply-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;reply-parent-user-id=11148817;reply-thread-parent-msg-id=6e2ccb1f-01ed-44d0-85b6-edf762524475;reply-thread-parent-user-login=;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :@pajlada why?";
+        let msg = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        match msg {
+            ServerMessage::Privmsg(msg) => {
+                assert_eq!(msg.reply_parent, None);
+                assert_eq!(msg.reply_thread, None);
+                assert_eq!(msg.message_text, "@pajlada why?");
+            }
+            _ => panic!("expected a PRIVMSG"),
+        }
+
+        // only the thread tags are incomplete
+        let src = "@badge-info=;badges=;color=#FF0000;display-name=Randers;emotes=;first-msg=0;flags=;id=3f5a3b0e-7a1c-4a5e-9a39-1f0bd1e52a8b;mod=0;reply-parent-display-name=pajlada;reply-parent-msg-body=@Randers\\sI\\sdisagree;reply-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;reply-parent-user-id=11148817;reply-parent-user-login=pajlada;reply-thread-parent-msg-id=6e2ccb1f-01ed-44d0-85b6-edf762524475;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :@pajlada why?";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.reply_parent.map(|parent| parent.message_id),
+            Some("b34ccfc7-4977-403a-8a94-33c6bac34fb8".to_owned())
+        );
+        assert_eq!(msg.reply_thread, None);
+    }
+
+    #[test]
+    fn test_reply_in_thread() {
+        let src = "@badge-info=;badges=;color=#FF0000;display-name=Randers;emotes=;first-msg=0;flags=;id=3f5a3b0e-7a1c-4a5e-9a39-1f0bd1e52a8b;mod=0;reply-parent-display-name=pajlada;reply-parent-msg-body=@Randers\\sI\\sdisagree;reply-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;reply-parent-user-id=11148817;reply-parent-user-login=pajlada;reply-thread-parent-display-name=Randers;reply-thread-parent-msg-id=6e2ccb1f-01ed-44d0-85b6-edf762524475;reply-thread-parent-user-id=40286300;reply-thread-parent-user-login=randers;returning-chatter=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :@pajlada why?";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        assert_eq!(
+            msg.reply_parent,
+            Some(ReplyParent {
+                message_id: "b34ccfc7-4977-403a-8a94-33c6bac34fb8".to_owned(),
+                sender: TwitchUserBasics {
+                    id: "11148817".to_owned(),
+                    login: "pajlada".to_owned(),
+                    name: "pajlada".to_owned(),
+                },
+                message_text: "@Randers I disagree".to_owned(),
+            })
+        );
+        assert_eq!(
+            msg.reply_thread,
+            Some(ReplyThread {
+                message_id: "6e2ccb1f-01ed-44d0-85b6-edf762524475".to_owned(),
+                sender_login: "randers".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_not_a_reply() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.reply_parent, None);
+        assert_eq!(msg.reply_thread, None);
+    }
+
+    #[test]
+    fn test_shared_chat_message() {
+        let src = "@badge-info=;badges=staff/1,raging-wolf-helm/1;color=#DAA520;display-name=lahoooo;emotes=;first-msg=0;flags=;id=01cd601f-bc3f-49d5-ab4b-136fa9d6ec22;mod=0;returning-chatter=0;room-id=11148817;source-badge-info=subscriber/3;source-badges=moderator/1,subscriber/3;source-id=4083dadc-9f20-40f9-ba92-949ebf6bc294;source-room-id=1025594235;subscriber=0;tmi-sent-ts=1726118378465;turbo=0;user-id=612865661;user-type=staff :lahoooo!lahoooo@lahoooo.tmi.twitch.tv PRIVMSG #pajlada :guys im so proud of this show";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message).unwrap();
+
+        assert_eq!(msg.channel_id, "11148817");
+        assert_eq!(
+            msg.source_channel,
+            Some(SharedChatSource {
+                channel_id: "1025594235".to_owned(),
+                message_id: Some("4083dadc-9f20-40f9-ba92-949ebf6bc294".to_owned()),
+                badge_info: vec![Badge {
+                    name: "subscriber".to_owned(),
+                    version: "3".to_owned(),
+                }],
+                badges: vec![
+                    Badge {
+                        name: "moderator".to_owned(),
+                        version: "1".to_owned(),
+                    },
+                    Badge {
+                        name: "subscriber".to_owned(),
+                        version: "3".to_owned(),
+                    },
+                ],
+            })
+        );
+        assert!(!msg.source_only);
+    }
+
+    #[test]
+    fn test_source_only() {
+        let src = "@badge-info=;badges=;color=#DAA520;display-name=lahoooo;emotes=;flags=;id=01cd601f-bc3f-49d5-ab4b-136fa9d6ec22;mod=0;room-id=11148817;source-badge-info=;source-badges=;source-id=4083dadc-9f20-40f9-ba92-949ebf6bc294;source-only=1;source-room-id=1025594235;subscriber=0;tmi-sent-ts=1726118378465;turbo=0;user-id=612865661;user-type= :lahoooo!lahoooo@lahoooo.tmi.twitch.tv PRIVMSG #pajlada :hi";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert!(msg.source_only);
+        assert_eq!(
+            msg.source_channel
+                .map(|source_channel| source_channel.channel_id),
+            Some("1025594235".to_owned())
+        );
+
+        let src = src.replace("source-only=1", "source-only=0");
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+        assert!(!msg.source_only);
+    }
+
+    #[test]
+    fn test_shared_chat_message_from_own_channel() {
+        // during a shared chat session, messages sent directly in the channel also carry
+        // the source tags, pointing to the channel itself
+        let src = "@badge-info=;badges=;color=#DAA520;display-name=lahoooo;emotes=;flags=;id=01cd601f-bc3f-49d5-ab4b-136fa9d6ec22;mod=0;room-id=11148817;source-badge-info=;source-badges=;source-id=01cd601f-bc3f-49d5-ab4b-136fa9d6ec22;source-room-id=11148817;subscriber=0;tmi-sent-ts=1726118378465;turbo=0;user-id=612865661;user-type= :lahoooo!lahoooo@lahoooo.tmi.twitch.tv PRIVMSG #pajlada :hi";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.source_channel, None);
+    }
+
+    #[test]
+    fn test_shared_chat_message_partial_tags() {
+        let src = "@badge-info=;badges=;color=#DAA520;display-name=lahoooo;emotes=;flags=;id=01cd601f-bc3f-49d5-ab4b-136fa9d6ec22;mod=0;room-id=11148817;source-room-id=1025594235;subscriber=0;tmi-sent-ts=1726118378465;turbo=0;user-id=612865661;user-type= :lahoooo!lahoooo@lahoooo.tmi.twitch.tv PRIVMSG #pajlada :hi";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.source_channel,
+            Some(SharedChatSource {
+                channel_id: "1025594235".to_owned(),
+                message_id: None,
+                badge_info: vec![],
+                badges: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_without_tags() {
+        let src = ":randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :\u{0001}ACTION hello world\u{0001}";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        assert_eq!(msg.channel_login, "pajlada");
+        assert_eq!(msg.channel_id, "");
+        assert_eq!(
+            msg.sender,
+            TwitchUserBasics {
+                id: "".to_owned(),
+                login: "randers".to_owned(),
+                name: "randers".to_owned(),
+            }
+        );
+        assert_eq!(msg.message_text, "hello world");
+        assert!(msg.is_action);
+        assert_eq!(msg.message_id, "");
+        assert_eq!(msg.badges, vec![]);
+        assert_eq!(msg.emotes, vec![]);
+        assert_eq!(msg.name_color, None);
+        assert_eq!(msg.permission_level(), PermissionLevel::Everyone);
+
+        // messages with tags still need all required tags
+        let src = "@color=#19E6E6 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :hello";
+        assert!(matches!(
+            PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()),
+            Err(ServerMessageParseError::MissingTag(_, "room-id"))
+        ));
+    }
+
+    #[test]
+    fn test_slice_accessors() {
+        let src = "@badge-info=subscriber/22;badges=subscriber/12,moderator/1;color=#19E6E6;display-name=randers;emotes=1902:6-10,29-33,35-39/499:45-46,48-49/490:51-52/25:0-4,12-16,18-22;flags=;id=f9c5774b-faa7-4378-b1af-c4e08b532dc2;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594556065407;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa Keepo Kappa Kappa test Keepo Keepo 123 :) :) :P";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        // badges are kept in tag order, and not sorted
+        assert_eq!(
+            msg.badges()
+                .iter()
+                .map(|badge| badge.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["subscriber", "moderator"]
+        );
+        assert_eq!(msg.badge_info(), msg.badge_info.as_slice());
+        assert_eq!(
+            msg.emotes()
+                .iter()
+                .map(|emote| emote.char_range.start)
+                .collect::<Vec<_>>(),
+            vec![0, 6, 12, 18, 29, 35, 45, 48, 51]
+        );
+    }
+
+    #[test]
+    fn test_is_vip() {
+        for (badges, vip_tag, is_vip, expected_level) in [
+            ("vip/1", "", true, PermissionLevel::Vip),
+            ("vip/1,subscriber/12", ";vip=1", true, PermissionLevel::Vip),
+            // VIP with a different badge displayed
+            ("subscriber/12", ";vip=1", true, PermissionLevel::Vip),
+            (
+                "moderator/1,subscriber/12",
+                "",
+                false,
+                PermissionLevel::Moderator,
+            ),
+            (
+                "subscriber/12",
+                ";vip=0",
+                false,
+                PermissionLevel::Subscriber,
+            ),
+            ("", "", false, PermissionLevel::Everyone),
+        ]
+        .iter()
+        {
+            let src = format!("@badge-info=;badges={};color=;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;room-id=11148817;tmi-sent-ts=1594545155039;user-id=40286300{} :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :!command", badges, vip_tag);
+            let msg = PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+            // the tag is kept after dropping the source
+            for msg in &[msg.clone(), msg.without_source()] {
+                assert_eq!(msg.is_vip(), *is_vip, "badges: {} {}", badges, vip_tag);
+                assert_eq!(
+                    msg.permission_level(),
+                    *expected_level,
+                    "badges: {} {}",
+                    badges,
+                    vip_tag
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_subscriber_months() {
+        for (badge_info, expected) in [
+            ("", None),
+            ("subscriber/14", Some(14)),
+            ("predictions/blue-1,subscriber/3", Some(3)),
+            ("founder/47", Some(47)),
+            ("predictions/pink-2", None),
+        ]
+        .iter()
+        {
+            let src = format!("@badge-info={};badges=;color=;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;room-id=11148817;tmi-sent-ts=1594545155039;user-id=40286300 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :test", badge_info);
+            let msg = PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+            assert_eq!(
+                msg.subscriber_months(),
+                *expected,
+                "badge-info: {}",
+                badge_info
+            );
+        }
+    }
+
+    #[test]
+    fn test_without_source() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=25:0-4;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :Kappa cam";
+        let msg = PrivmsgMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        let light = msg.clone().without_source();
+
+        assert_eq!(*light.source, IRCMessage::new_simple(String::new(), vec![]));
+        assert_eq!(light.source.as_raw_irc(), "");
+        // all parsed fields are kept
+        assert_eq!(
+            light,
+            PrivmsgMessage {
+                source: light.source.clone(),
+                ..msg
+            }
+        );
+    }
+
+    #[test]
+    fn test_permission_level() {
+        for (badges, expected) in [
+            ("", PermissionLevel::Everyone),
+            ("premium/1,glhf-pledge/1", PermissionLevel::Everyone),
+            ("subscriber/12", PermissionLevel::Subscriber),
+            ("founder/0", PermissionLevel::Subscriber),
+            ("vip/1,subscriber/3000", PermissionLevel::Vip),
+            ("moderator/1,subscriber/12", PermissionLevel::Moderator),
+            ("lead_moderator/1", PermissionLevel::Moderator),
+            ("broadcaster/1,subscriber/0", PermissionLevel::Broadcaster),
+        ]
+        .iter()
+        {
+            let src = format!("@badge-info=;badges={};color=;display-name=randers;emotes=;flags=;id=7be20040-bd47-48f9-9e52-70c06a1a7046;room-id=11148817;tmi-sent-ts=1594545155039;user-id=40286300 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :!command", badges);
+            let msg = PrivmsgMessage::try_from(IRCMessage::parse(&src).unwrap()).unwrap();
+            assert_eq!(msg.permission_level(), *expected, "badges: {}", badges);
+        }
+
+        assert!(PermissionLevel::Everyone < PermissionLevel::Subscriber);
+        assert!(PermissionLevel::Subscriber < PermissionLevel::Vip);
+        assert!(PermissionLevel::Vip < PermissionLevel::Moderator);
+        assert!(PermissionLevel::Moderator < PermissionLevel::Broadcaster);
+    }
+
+    #[test]
+    fn test_display() {
+        let src = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message.clone()).unwrap();
+        assert_eq!(msg.to_string(), "#pajlada JuN1oRRRR: dank cam");
+
+        let server_message = ServerMessage::try_from(irc_message).unwrap();
+        assert_eq!(server_message.to_string(), "#pajlada JuN1oRRRR: dank cam");
+    }
+
+    #[test]
+    fn test_display_action() {
+        let src = "@badge-info=subscriber/22;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :\u{0001}ACTION -tags\u{0001}";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message).unwrap();
+        assert_eq!(msg.to_string(), "#pajlada * randers -tags");
+    }
+
+    #[test]
+    fn test_prediction_and_newer_badges() {
+        let src = "@badge-info=predictions/Yes\\sKEKW;badges=predictions/blue-1,vip/1,artist-badge/1,glitchcon2020/1;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :test";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message).unwrap();
+
+        assert_eq!(
+            msg.badge_info,
+            vec![Badge {
+                name: "predictions".to_owned(),
+                version: "Yes KEKW".to_owned(),
+            }]
+        );
+        assert_eq!(
+            msg.badges,
+            vec![
+                Badge {
+                    name: "predictions".to_owned(),
+                    version: "blue-1".to_owned(),
+                },
+                Badge {
+                    name: "vip".to_owned(),
+                    version: "1".to_owned(),
+                },
+                Badge {
+                    name: "artist-badge".to_owned(),
+                    version: "1".to_owned(),
+                },
+                Badge {
+                    name: "glitchcon2020".to_owned(),
+                    version: "1".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unusual_badge_formats() {
+        // empty version, missing version, a version with additional slashes, and a trailing comma
+        let src = "@badge-info=;badges=vip/,partner,predictions/pink-2/extra,;color=#19E6E6;display-name=randers;emotes=;flags=;id=d831d848-b7c7-4559-ae3a-2cb88f4dbfed;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594555275886;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :test";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = PrivmsgMessage::try_from(irc_message).unwrap();
+
+        assert_eq!(
+            msg.badges,
+            vec![
+                Badge {
+                    name: "vip".to_owned(),
+                    version: "".to_owned(),
+                },
+                Badge {
+                    name: "partner".to_owned(),
+                    version: "".to_owned(),
+                },
+                Badge {
+                    name: "predictions".to_owned(),
+                    version: "pink-2/extra".to_owned(),
+                },
+            ]
+        );
+    }
 }
@@ -2,6 +2,7 @@ use crate::message::commands::ServerMessageParseError;
 use crate::message::commands::ServerMessageParseError::MismatchedCommand;
 use crate::message::IRCMessage;
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -11,7 +12,7 @@ use {serde::Deserialize, serde::Serialize};
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct ReconnectMessage {
     /// The message that this `ReconnectMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for ReconnectMessage {
@@ -19,7 +20,9 @@ impl TryFrom<IRCMessage> for ReconnectMessage {
 
     fn try_from(source: IRCMessage) -> Result<ReconnectMessage, ServerMessageParseError> {
         if source.command == "RECONNECT" {
-            Ok(ReconnectMessage { source })
+            Ok(ReconnectMessage {
+                source: Arc::new(source),
+            })
         } else {
             Err(MismatchedCommand(source))
         }
@@ -28,7 +31,7 @@ impl TryFrom<IRCMessage> for ReconnectMessage {
 
 impl From<ReconnectMessage> for IRCMessage {
     fn from(msg: ReconnectMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
     }
 }
 
@@ -36,6 +39,7 @@ impl From<ReconnectMessage> for IRCMessage {
 mod tests {
     use crate::message::{IRCMessage, ReconnectMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -46,7 +50,7 @@ mod tests {
         assert_eq!(
             msg,
             ReconnectMessage {
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
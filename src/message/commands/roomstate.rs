@@ -1,6 +1,7 @@
 use crate::message::commands::IRCMessageParseExt;
-use crate::message::{IRCMessage, ServerMessageParseError};
+use crate::message::{IRCMessage, NoticeMessage, ServerMessageParseError};
 use std::convert::TryFrom;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "with-serde")]
@@ -60,7 +61,7 @@ pub struct RoomStateMessage {
     pub subscribers_only: Option<bool>,
 
     /// The message that this `RoomStateMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 /// Specifies the followers-only mode a chat is in or was put in.
@@ -117,22 +118,133 @@ impl TryFrom<IRCMessage> for RoomStateMessage {
                 .try_get_optional_number::<u64>("slow")?
                 .map(Duration::from_secs),
             subscribers_only: source.try_get_optional_bool("subs-only")?,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<RoomStateMessage> for IRCMessage {
     fn from(msg: RoomStateMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// The combined "room state" of a channel, built by merging all `ROOMSTATE` messages
+/// received for that channel.
+///
+/// The first `ROOMSTATE` after joining a channel contains all settings, later ones only
+/// contain the settings that changed. `update()` applies such a partial update onto the
+/// previously known state. A setting is only `None` if no `ROOMSTATE` message so far has
+/// contained it.
+///
+/// Mode changes are also confirmed by `NOTICE`s (e.g. `slow_on`), which `update_from_notice()`
+/// applies. `ROOMSTATE` takes precedence: It always sets the exact value, while a `NOTICE`
+/// only switches a mode on or off if the known state disagrees. This way a `NOTICE` can't
+/// overwrite the details (e.g. the slow-mode duration) a `ROOMSTATE` already provided, no matter
+/// in which order the two messages arrive. If a `NOTICE` enables slow mode or followers-only
+/// mode while the known state says it is disabled, the duration is unknown and the setting
+/// becomes `None` until the next `ROOMSTATE` contains it.
+///
+/// See `TwitchIRCClient::room_state()` to get the room state the client has cached for
+/// a joined channel.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct RoomState {
+    /// Login name of the channel.
+    pub channel_login: String,
+    /// ID of the channel.
+    pub channel_id: String,
+    /// Whether emote-only mode is enabled, see `RoomStateMessage::emote_only`.
+    pub emote_only: Option<bool>,
+    /// The followers-only mode of the channel, see `RoomStateMessage::follwers_only`.
+    pub followers_only: Option<FollowersOnlyMode>,
+    /// Whether r9k mode is enabled, see `RoomStateMessage::r9k`.
+    pub r9k: Option<bool>,
+    /// The slow-mode setting of the channel (0 seconds if disabled),
+    /// see `RoomStateMessage::slow_mode`.
+    pub slow_mode: Option<Duration>,
+    /// Whether subscribers-only mode is enabled, see `RoomStateMessage::subscribers_only`.
+    pub subscribers_only: Option<bool>,
+}
+
+impl RoomState {
+    /// Apply the settings present in `msg` onto this room state. Settings not present in `msg`
+    /// keep their previous value.
+    pub fn update(&mut self, msg: &RoomStateMessage) {
+        self.channel_login = msg.channel_login.clone();
+        self.channel_id = msg.channel_id.clone();
+        if let Some(emote_only) = msg.emote_only {
+            self.emote_only = Some(emote_only);
+        }
+        if let Some(ref followers_only) = msg.follwers_only {
+            self.followers_only = Some(followers_only.clone());
+        }
+        if let Some(r9k) = msg.r9k {
+            self.r9k = Some(r9k);
+        }
+        if let Some(slow_mode) = msg.slow_mode {
+            self.slow_mode = Some(slow_mode);
+        }
+        if let Some(subscribers_only) = msg.subscribers_only {
+            self.subscribers_only = Some(subscribers_only);
+        }
+    }
+
+    /// Apply the mode change confirmed by the given `NOTICE` (e.g. `emote_only_on` or
+    /// `slow_off`) onto this room state, see the type documentation for how this interacts
+    /// with `ROOMSTATE` messages. Notices that don't confirm a mode change, or that were sent
+    /// to a different channel, are ignored.
+    pub fn update_from_notice(&mut self, notice: &NoticeMessage) {
+        if notice.channel_login.as_ref() != Some(&self.channel_login) {
+            return;
+        }
+        let message_id = match &notice.message_id {
+            Some(message_id) => message_id.as_str(),
+            None => return,
+        };
+
+        match message_id {
+            "emote_only_on" => self.emote_only = Some(true),
+            "emote_only_off" => self.emote_only = Some(false),
+            "r9k_on" => self.r9k = Some(true),
+            "r9k_off" => self.r9k = Some(false),
+            "subs_on" => self.subscribers_only = Some(true),
+            "subs_off" => self.subscribers_only = Some(false),
+            // the NOTICEs enabling slow mode or followers-only mode don't carry the duration
+            "slow_on" if self.slow_mode == Some(Duration::from_secs(0)) => self.slow_mode = None,
+            "slow_off" => self.slow_mode = Some(Duration::from_secs(0)),
+            "followers_onzero" => {
+                self.followers_only = Some(FollowersOnlyMode::Enabled(Duration::from_secs(0)))
+            }
+            "followers_on" if self.followers_only == Some(FollowersOnlyMode::Disabled) => {
+                self.followers_only = None
+            }
+            "followers_off" => self.followers_only = Some(FollowersOnlyMode::Disabled),
+            _ => {}
+        }
+    }
+}
+
+impl From<&RoomStateMessage> for RoomState {
+    fn from(msg: &RoomStateMessage) -> RoomState {
+        RoomState {
+            channel_login: msg.channel_login.clone(),
+            channel_id: msg.channel_id.clone(),
+            emote_only: msg.emote_only,
+            followers_only: msg.follwers_only.clone(),
+            r9k: msg.r9k,
+            slow_mode: msg.slow_mode,
+            subscribers_only: msg.subscribers_only,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::message::commands::roomstate::FollowersOnlyMode;
-    use crate::message::{IRCMessage, RoomStateMessage};
+    use crate::message::{IRCMessage, NoticeMessage, RoomState, RoomStateMessage, ServerMessage};
     use std::convert::TryFrom;
+    use std::sync::Arc;
     use std::time::Duration;
 
     #[test]
@@ -151,7 +263,7 @@ mod tests {
                 r9k: Some(false),
                 slow_mode: Some(Duration::from_secs(0)),
                 subscribers_only: Some(false),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -172,7 +284,7 @@ mod tests {
                 r9k: Some(true),
                 slow_mode: Some(Duration::from_secs(5)),
                 subscribers_only: Some(true),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -205,7 +317,7 @@ mod tests {
                 r9k: None,
                 slow_mode: Some(Duration::from_secs(5)),
                 subscribers_only: None,
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
@@ -226,8 +338,111 @@ mod tests {
                 r9k: None,
                 slow_mode: None,
                 subscribers_only: None,
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
+
+    #[test]
+    pub fn test_display_as_server_message() {
+        let src = "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=40286300;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #randers";
+        let msg = ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.to_string(), "[ROOMSTATE #randers]");
+    }
+
+    #[test]
+    pub fn test_room_state_merges_partial_updates() {
+        let parse =
+            |src: &str| RoomStateMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        let mut state = RoomState::from(&parse("@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=40286300;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #randers"));
+        state.update(&parse(
+            "@emote-only=1;room-id=40286300 :tmi.twitch.tv ROOMSTATE #randers",
+        ));
+        state.update(&parse(
+            "@followers-only=10;room-id=40286300 :tmi.twitch.tv ROOMSTATE #randers",
+        ));
+        state.update(&parse(
+            "@room-id=40286300;slow=5 :tmi.twitch.tv ROOMSTATE #randers",
+        ));
+
+        assert_eq!(
+            state,
+            RoomState {
+                channel_login: "randers".to_owned(),
+                channel_id: "40286300".to_owned(),
+                emote_only: Some(true),
+                followers_only: Some(FollowersOnlyMode::Enabled(Duration::from_secs(600))),
+                r9k: Some(false),
+                slow_mode: Some(Duration::from_secs(5)),
+                subscribers_only: Some(false),
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_room_state_from_partial_update() {
+        let msg = RoomStateMessage::try_from(
+            IRCMessage::parse("@emote-only=1;room-id=40286300 :tmi.twitch.tv ROOMSTATE #randers")
+                .unwrap(),
+        )
+        .unwrap();
+        let state = RoomState::from(&msg);
+
+        assert_eq!(state.emote_only, Some(true));
+        assert_eq!(state.followers_only, None);
+        assert_eq!(state.r9k, None);
+        assert_eq!(state.slow_mode, None);
+        assert_eq!(state.subscribers_only, None);
+    }
+
+    #[test]
+    pub fn test_update_from_notice() {
+        let full = "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=40286300;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #randers";
+        let mut room_state =
+            RoomState::from(&RoomStateMessage::try_from(IRCMessage::parse(full).unwrap()).unwrap());
+        let notice = |msg_id: &str, channel: &str| {
+            NoticeMessage::try_from(
+                IRCMessage::parse(&format!(
+                    "@msg-id={} :tmi.twitch.tv NOTICE #{} :mode changed",
+                    msg_id, channel
+                ))
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        room_state.update_from_notice(&notice("emote_only_on", "randers"));
+        room_state.update_from_notice(&notice("r9k_on", "randers"));
+        room_state.update_from_notice(&notice("subs_on", "randers"));
+        room_state.update_from_notice(&notice("followers_onzero", "randers"));
+        assert_eq!(room_state.emote_only, Some(true));
+        assert_eq!(room_state.r9k, Some(true));
+        assert_eq!(room_state.subscribers_only, Some(true));
+        assert_eq!(
+            room_state.followers_only,
+            Some(FollowersOnlyMode::Enabled(Duration::from_secs(0)))
+        );
+
+        // the duration is not known from the NOTICE alone
+        room_state.update_from_notice(&notice("slow_on", "randers"));
+        assert_eq!(room_state.slow_mode, None);
+
+        // a ROOMSTATE provides the duration, and a later NOTICE doesn't overwrite it
+        let slow = "@room-id=40286300;slow=30 :tmi.twitch.tv ROOMSTATE #randers";
+        room_state.update(&RoomStateMessage::try_from(IRCMessage::parse(slow).unwrap()).unwrap());
+        room_state.update_from_notice(&notice("slow_on", "randers"));
+        assert_eq!(room_state.slow_mode, Some(Duration::from_secs(30)));
+
+        room_state.update_from_notice(&notice("slow_off", "randers"));
+        room_state.update_from_notice(&notice("followers_off", "randers"));
+        assert_eq!(room_state.slow_mode, Some(Duration::from_secs(0)));
+        assert_eq!(room_state.followers_only, Some(FollowersOnlyMode::Disabled));
+
+        // other channels and unrelated notices are ignored
+        let before = room_state.clone();
+        room_state.update_from_notice(&notice("emote_only_off", "pajlada"));
+        room_state.update_from_notice(&notice("msg_banned", "randers"));
+        assert_eq!(room_state, before);
+    }
 }
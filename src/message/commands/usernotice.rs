@@ -1,8 +1,14 @@
 use crate::message::commands::IRCMessageParseExt;
-use crate::message::twitch::{Badge, Emote, RGBColor, TwitchUserBasics};
+use crate::message::twitch::{
+    subscriber_months, Badge, Emote, RGBColor, SubPlan, TwitchUserBasics,
+};
 use crate::message::{IRCMessage, ServerMessageParseError};
+#[cfg(feature = "with-chrono")]
 use chrono::{DateTime, Utc};
-use std::convert::TryFrom;
+use std::convert::{Infallible, TryFrom};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -34,8 +40,8 @@ pub struct UserNoticeMessage {
     /// If present, an optional message the user sent alongside the notification. Not all types
     /// of events can have message text.
     ///
-    /// Currently the only event that can a message is a `resub`, where this message text is the
-    /// message the user shared with the streamer alongside the resub message.
+    /// For example, for a `resub` this is the message the user shared with the streamer
+    /// alongside the resub message, and for an `announcement` it is the announcement itself.
     pub message_text: Option<String>,
     /// A system message that is always present and represents a user-presentable message
     /// of what this event is, for example "FuchsGewand subscribed with Twitch Prime.
@@ -79,10 +85,20 @@ pub struct UserNoticeMessage {
     pub message_id: String,
 
     /// Timestamp of when this message was sent.
-    pub server_timestamp: DateTime<Utc>,
+    /// `None` if the `tmi-sent-ts` tag is missing or malformed.
+    ///
+    /// Only available with the `with-chrono` feature, see `server_timestamp_millis` for
+    /// an alternative.
+    #[cfg(feature = "with-chrono")]
+    pub server_timestamp: Option<DateTime<Utc>>,
+    /// Same as `server_timestamp`, as milliseconds since the Unix epoch (the value of the
+    /// `tmi-sent-ts` tag). `None` if the tag is missing or malformed.
+    ///
+    /// Unlike `server_timestamp`, this is also available without the `with-chrono` feature.
+    pub server_timestamp_millis: Option<i64>,
 
     /// The message that this `UserNoticeMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 /// Additionally present on `giftpaidupgrade` and `anongiftpaidupgrade` messages
@@ -117,6 +133,69 @@ impl SubGiftPromo {
     }
 }
 
+/// The color a moderator or the broadcaster picked for an announcement, see
+/// `UserNoticeEvent::Announcement`.
+///
+/// Twitch sends the color as `PRIMARY`, `BLUE`, `GREEN`, `ORANGE` or `PURPLE`. `Display` formats
+/// the color back into this raw value.
+///
+/// ```
+/// use twitch_irc::message::AnnouncementColor;
+///
+/// assert_eq!(AnnouncementColor::from("PURPLE"), AnnouncementColor::Purple);
+/// assert_eq!(AnnouncementColor::Blue.to_string(), "BLUE");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum AnnouncementColor {
+    /// The channel's accent color (`PRIMARY`). This is the default.
+    Primary,
+    /// `BLUE`
+    Blue,
+    /// `GREEN`
+    Green,
+    /// `ORANGE`
+    Orange,
+    /// `PURPLE`
+    Purple,
+    /// Any other value, in case Twitch introduces new colors.
+    Other(String),
+}
+
+impl From<&str> for AnnouncementColor {
+    fn from(raw: &str) -> AnnouncementColor {
+        match raw {
+            "PRIMARY" => AnnouncementColor::Primary,
+            "BLUE" => AnnouncementColor::Blue,
+            "GREEN" => AnnouncementColor::Green,
+            "ORANGE" => AnnouncementColor::Orange,
+            "PURPLE" => AnnouncementColor::Purple,
+            other => AnnouncementColor::Other(other.to_owned()),
+        }
+    }
+}
+
+impl FromStr for AnnouncementColor {
+    type Err = Infallible;
+
+    fn from_str(raw: &str) -> Result<AnnouncementColor, Infallible> {
+        Ok(AnnouncementColor::from(raw))
+    }
+}
+
+impl fmt::Display for AnnouncementColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AnnouncementColor::Primary => "PRIMARY",
+            AnnouncementColor::Blue => "BLUE",
+            AnnouncementColor::Green => "GREEN",
+            AnnouncementColor::Orange => "ORANGE",
+            AnnouncementColor::Purple => "PURPLE",
+            AnnouncementColor::Other(raw) => raw,
+        })
+    }
+}
+
 /// A type of event that a `UserNoticeMessage` represents.
 ///
 /// The `USERNOTICE` command is used for a wide variety of different "rich events" on
@@ -158,8 +237,8 @@ pub enum UserNoticeEvent {
         cumulative_months: u64,
         /// Consecutive number of months the sending user has subscribed to this channel.
         streak_months: Option<u64>,
-        /// `Prime`, `1000`, `2000` or `3000`, referring to Prime or tier 1, 2 or 3 subs respectively.
-        sub_plan: String,
+        /// Whether this is a Prime or a tier 1, 2 or 3 subscription.
+        sub_plan: SubPlan,
         /// A name the broadcaster configured for this sub plan, e.g. `The Ninjas` or
         /// `Channel subscription (nymn_hs)`
         sub_plan_name: String,
@@ -193,13 +272,18 @@ pub enum UserNoticeEvent {
         cumulative_months: u64,
         /// The user that received this gifted subscription or resubscription.
         recipient: TwitchUserBasics,
-        /// `1000`, `2000` or `3000`, referring to tier 1, 2 or 3 subs respectively.
-        sub_plan: String,
+        /// Whether the gifted subscription is tier 1, 2 or 3.
+        sub_plan: SubPlan,
         /// A name the broadcaster configured for this sub plan, e.g. `The Ninjas` or
         /// `Channel subscription (nymn_hs)`
         sub_plan_name: String,
         /// number of months in a single multi-month gift.
         num_gifted_months: u64,
+        /// If this gift is part of a mass gift, this is the ID of that mass gift. The preceding
+        /// `SubMysteryGift` or `AnonSubMysteryGift` event carries the same ID, which can be used
+        /// to attribute this gift to the mass gift (and avoid counting it twice).
+        /// `None` for gifts that are not part of a mass gift.
+        community_gift_id: Option<String>,
     },
 
     /// This event precedes a wave of `subgift`/`anonsubgift` messages.
@@ -219,9 +303,11 @@ pub enum UserNoticeEvent {
         /// number of gifts in this `submysterygift` or `anonsubmysterygift`.
         /// Note tha
         sender_total_gifts: u64,
-        /// The type of sub plan the recipients were gifted.
-        /// `1000`, `2000` or `3000`, referring to tier 1, 2 or 3 subs respectively.
-        sub_plan: String,
+        /// The type of sub plan the recipients were gifted (tier 1, 2 or 3).
+        sub_plan: SubPlan,
+        /// ID of this mass gift, shared with the `SubGift` events that follow this event.
+        /// `None` if Twitch did not send an ID.
+        community_gift_id: Option<String>,
     },
 
     /// This event precedes a wave of `subgift`/`anonsubgift` messages.
@@ -236,9 +322,11 @@ pub enum UserNoticeEvent {
     AnonSubMysteryGift {
         /// Number of gifts the sender just gifted.
         mass_gift_count: u64,
-        /// The type of sub plan the recipients were gifted.
-        /// `1000`, `2000` or `3000`, referring to tier 1, 2 or 3 subs respectively.
-        sub_plan: String,
+        /// The type of sub plan the recipients were gifted (tier 1, 2 or 3).
+        sub_plan: SubPlan,
+        /// ID of this mass gift, shared with the `SubGift` events that follow this event.
+        /// `None` if Twitch did not send an ID.
+        community_gift_id: Option<String>,
     },
 
     /// Occurs when a user continues their gifted subscription they got from a non-anonymous
@@ -287,12 +375,30 @@ pub enum UserNoticeEvent {
         threshold: u64,
     },
 
+    /// A moderator or the broadcaster sent an announcement (`/announce`), which is highlighted
+    /// in chat. The user sending this `USERNOTICE` is the user making the announcement, and
+    /// the `message_text` is the announcement itself.
+    Announcement {
+        /// The color the announcement is highlighted with.
+        color: AnnouncementColor,
+    },
+
     // this is hidden so users don't match on it. Instead they should match on _
     // so their code still works the same when new variants are added here.
     #[doc(hidden)]
     Unknown,
 }
 
+impl UserNoticeMessage {
+    /// Returns the exact number of months the `sender` has been subscribed to the channel, as
+    /// found in the `badge_info`. Returns `None` if the sender is not subscribed.
+    ///
+    /// See also `PrivmsgMessage::subscriber_months()`.
+    pub fn subscriber_months(&self) -> Option<u64> {
+        subscriber_months(&self.badge_info)
+    }
+}
+
 impl TryFrom<IRCMessage> for UserNoticeMessage {
     type Error = ServerMessageParseError;
 
@@ -318,7 +424,7 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
 
         // the `msg-id` tag specifies the type of event this usernotice conveys. According to twitch,
         // the value can be one of:
-        // sub, resub, raid, subgift, anonsubgift, anongiftpaidupgrade, giftpaidupgrade, ritual, bitsbadgetier
+        // sub, resub, raid, subgift, anonsubgift, anongiftpaidupgrade, giftpaidupgrade, ritual, bitsbadgetier, announcement
         // more types are often added by Twitch ad-hoc without prior notice as part
         // of seasonal events.
         // TODO msg-id's that have been seen but are not documented:
@@ -343,9 +449,7 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
                 } else {
                     None
                 },
-                sub_plan: source
-                    .try_get_nonempty_tag_value("msg-param-sub-plan")?
-                    .to_owned(),
+                sub_plan: SubPlan::from(source.try_get_nonempty_tag_value("msg-param-sub-plan")?),
                 sub_plan_name: source
                     .try_get_nonempty_tag_value("msg-param-sub-plan-name")?
                     .to_owned(),
@@ -371,6 +475,8 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
             // msg-param-sub-plan (1000, 2000 or 3000 for the three sub tiers)
             // msg-param-sub-plan-name (e.g. "The Ninjas")
             // msg-param-gift-months (number of months in a single multi-month gift)
+            // msg-param-community-gift-id (only if part of a mass gift, same ID as the
+            //   submysterygift/anonsubmysterygift preceding it)
             "subgift" | "anonsubgift" => UserNoticeEvent::SubGift {
                 // 274598607 is the user ID of "AnAnonymousGifter"
                 is_sender_anonymous: event_id == "anonsubgift" || sender.id == "274598607",
@@ -386,13 +492,14 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
                         .try_get_nonempty_tag_value("msg-param-recipient-display-name")?
                         .to_owned(),
                 },
-                sub_plan: source
-                    .try_get_nonempty_tag_value("msg-param-sub-plan")?
-                    .to_owned(),
+                sub_plan: SubPlan::from(source.try_get_nonempty_tag_value("msg-param-sub-plan")?),
                 sub_plan_name: source
                     .try_get_nonempty_tag_value("msg-param-sub-plan-name")?
                     .to_owned(),
                 num_gifted_months: source.try_get_number("msg-param-gift-months")?,
+                community_gift_id: source
+                    .try_get_optional_nonempty_tag_value("msg-param-community-gift-id")?
+                    .map(|s| s.to_owned()),
             },
             // submysterygift, anonsubmysterygift:
             // this precedes a wave of subgift/anonsubgift messages.
@@ -402,6 +509,7 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
             // msg-param-sender-count - total amount gifted, e.g. 5688 above
             //  - this seems to be missing if sender
             // msg-param-sub-plan (1000, 2000 or 3000 for the three sub tiers)
+            // msg-param-community-gift-id - ID that the subgift messages of this bulk share
 
             // 274598607 is the user ID of "AnAnonymousGifter"
             // the dorky syntax here instead of a normal match is to accomodate the special case
@@ -411,18 +519,22 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
             {
                 UserNoticeEvent::AnonSubMysteryGift {
                     mass_gift_count: source.try_get_number("msg-param-mass-gift-count")?,
-                    sub_plan: source
-                        .try_get_nonempty_tag_value("msg-param-sub-plan")?
-                        .to_owned(),
+                    sub_plan: SubPlan::from(
+                        source.try_get_nonempty_tag_value("msg-param-sub-plan")?,
+                    ),
+                    community_gift_id: source
+                        .try_get_optional_nonempty_tag_value("msg-param-community-gift-id")?
+                        .map(|s| s.to_owned()),
                 }
             }
             // this takes over all other cases of submysterygift.
             "submysterygift" => UserNoticeEvent::SubMysteryGift {
                 mass_gift_count: source.try_get_number("msg-param-mass-gift-count")?,
                 sender_total_gifts: source.try_get_number("msg-param-sender-count")?,
-                sub_plan: source
-                    .try_get_nonempty_tag_value("msg-param-sub-plan")?
-                    .to_owned(),
+                sub_plan: SubPlan::from(source.try_get_nonempty_tag_value("msg-param-sub-plan")?),
+                community_gift_id: source
+                    .try_get_optional_nonempty_tag_value("msg-param-community-gift-id")?
+                    .map(|s| s.to_owned()),
             },
             // giftpaidupgrade, anongiftpaidupgrade:
             // When a user commits to continue the gift sub by another user (or an anonymous gifter).
@@ -471,6 +583,16 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
                     .to_owned(),
             },
 
+            // announcement
+            // A moderator or the broadcaster used /announce. The announcement is the message text,
+            // and the system-msg is empty.
+            // msg-param-color - PRIMARY, BLUE, GREEN, ORANGE or PURPLE
+            "announcement" => UserNoticeEvent::Announcement {
+                color: source
+                    .try_get_optional_nonempty_tag_value("msg-param-color")?
+                    .map_or(AnnouncementColor::Primary, AnnouncementColor::from),
+            },
+
             // there are more events that are just not documented and not implemented yet. see above.
             _ => UserNoticeEvent::Unknown,
         };
@@ -495,25 +617,44 @@ impl TryFrom<IRCMessage> for UserNoticeMessage {
             emotes,
             name_color: source.try_get_color("color")?,
             message_id: source.try_get_nonempty_tag_value("id")?.to_owned(),
-            server_timestamp: source.try_get_timestamp("tmi-sent-ts")?.to_owned(),
-            source,
+            #[cfg(feature = "with-chrono")]
+            server_timestamp: source.get_optional_timestamp("tmi-sent-ts"),
+            server_timestamp_millis: source.get_optional_timestamp_millis("tmi-sent-ts"),
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<UserNoticeMessage> for IRCMessage {
     fn from(msg: UserNoticeMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the message as a single line made up of the channel and the system message, followed
+/// by the user's own message if they sent one, e.g.
+/// `#xqcow Kali_Linux_User subscribed with Prime. - Kali_Linux_User: hi`.
+impl fmt::Display for UserNoticeMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}", self.channel_login, self.system_message)?;
+        if let Some(message_text) = &self.message_text {
+            write!(f, " - {}: {}", self.sender.name, message_text)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::twitch::{Badge, Emote, RGBColor, TwitchUserBasics};
-    use crate::message::{IRCMessage, SubGiftPromo, UserNoticeEvent, UserNoticeMessage};
+    use crate::message::twitch::{Badge, Emote, RGBColor, SubPlan, TwitchUserBasics};
+    use crate::message::{
+        AnnouncementColor, IRCMessage, SubGiftPromo, UserNoticeEvent, UserNoticeMessage,
+    };
+    #[cfg(feature = "with-chrono")]
     use chrono::{TimeZone, Utc};
     use std::convert::TryFrom;
     use std::ops::Range;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_sub() {
@@ -537,7 +678,7 @@ mod tests {
                     is_resub: false,
                     cumulative_months: 1,
                     streak_months: None,
-                    sub_plan: "Prime".to_owned(),
+                    sub_plan: SubPlan::Prime,
                     sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 },
                 event_id: "sub".to_owned(),
@@ -558,8 +699,10 @@ mod tests {
                 emotes: vec![],
                 name_color: None,
                 message_id: "2a9bea11-a80a-49a0-a498-1642d457f775".to_owned(),
-                server_timestamp: Utc.timestamp_millis(1582685713242),
-                source: irc_message,
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1582685713242)),
+                server_timestamp_millis: Some(1582685713242),
+                source: Arc::new(irc_message),
             }
         )
     }
@@ -586,7 +729,7 @@ mod tests {
                     is_resub: true,
                     cumulative_months: 2,
                     streak_months: Some(2),
-                    sub_plan: "1000".to_owned(),
+                    sub_plan: SubPlan::Tier1,
                     sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 },
                 event_id: "resub".to_owned(),
@@ -617,8 +760,10 @@ mod tests {
                     b: 0xFF,
                 }),
                 message_id: "e0975c76-054c-4954-8cb0-91b8867ec1ca".to_owned(),
-                server_timestamp: Utc.timestamp_millis(1581713640019),
-                source: irc_message,
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1581713640019)),
+                server_timestamp_millis: Some(1581713640019),
+                source: Arc::new(irc_message),
             }
         )
     }
@@ -647,7 +792,7 @@ mod tests {
                     is_resub: true,
                     cumulative_months: 11,
                     streak_months: None,
-                    sub_plan: "Prime".to_owned(),
+                    sub_plan: SubPlan::Prime,
                     sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 },
                 event_id: "resub".to_owned(),
@@ -663,8 +808,10 @@ mod tests {
                     b: 0xE2,
                 }),
                 message_id: "ca1f02fb-77ec-487d-a9b3-bc4bfef2fe8b".to_owned(),
-                server_timestamp: Utc.timestamp_millis(1590628650446),
-                source: irc_message,
+                #[cfg(feature = "with-chrono")]
+                server_timestamp: Some(Utc.timestamp_millis(1590628650446)),
+                server_timestamp_millis: Some(1590628650446),
+                source: Arc::new(irc_message),
             }
         )
     }
@@ -705,9 +852,10 @@ mod tests {
                     login: "qatarking24xd".to_owned(),
                     name: "qatarking24xd".to_owned(),
                 },
-                sub_plan: "1000".to_owned(),
+                sub_plan: SubPlan::Tier1,
                 sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 num_gifted_months: 1,
+                community_gift_id: None,
             }
         )
     }
@@ -728,9 +876,10 @@ mod tests {
                     login: "dot0422".to_owned(),
                     name: "Dot0422".to_owned(),
                 },
-                sub_plan: "1000".to_owned(),
+                sub_plan: SubPlan::Tier1,
                 sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 num_gifted_months: 1,
+                community_gift_id: None,
             }
         )
     }
@@ -753,9 +902,10 @@ mod tests {
                     login: "qatarking24xd".to_owned(),
                     name: "qatarking24xd".to_owned(),
                 },
-                sub_plan: "1000".to_owned(),
+                sub_plan: SubPlan::Tier1,
                 sub_plan_name: "Channel Subscription (xqcow)".to_owned(),
                 num_gifted_months: 1,
+                community_gift_id: None,
             }
         )
     }
@@ -771,7 +921,8 @@ mod tests {
             UserNoticeEvent::SubMysteryGift {
                 mass_gift_count: 20,
                 sender_total_gifts: 100,
-                sub_plan: "1000".to_owned(),
+                sub_plan: SubPlan::Tier1,
+                community_gift_id: None,
             }
         )
     }
@@ -786,7 +937,8 @@ mod tests {
             msg.event,
             UserNoticeEvent::AnonSubMysteryGift {
                 mass_gift_count: 10,
-                sub_plan: "1000".to_owned(),
+                sub_plan: SubPlan::Tier1,
+                community_gift_id: None,
             }
         )
     }
@@ -803,11 +955,55 @@ mod tests {
             msg.event,
             UserNoticeEvent::AnonSubMysteryGift {
                 mass_gift_count: 15,
-                sub_plan: "2000".to_owned(),
+                sub_plan: SubPlan::Tier2,
+                community_gift_id: None,
             }
         )
     }
 
+    #[test]
+    pub fn test_community_gift_id() {
+        let srcs = [
+            "@badge-info=;badges=sub-gifter/50;color=;display-name=AdamAtReflectStudios;emotes=;flags=;id=049e6371-7023-4fca-8605-7dec60e72e12;login=adamatreflectstudios;mod=0;msg-id=submysterygift;msg-param-community-gift-id=4757298626717165963;msg-param-mass-gift-count=2;msg-param-origin-id=1f\\sbe\\sbb\\s4a\\s81\\s9a\\s65\\sd1\\s4b\\s77\\sf5\\s23\\s16\\s4a\\sd3\\s13\\s09\\se7\\sbe\\s55;msg-param-sender-count=100;msg-param-sub-plan=1000;room-id=71092938;subscriber=0;system-msg=AdamAtReflectStudios\\sis\\sgifting\\s2\\sTier\\s1\\sSubs\\sto\\sxQcOW's\\scommunity!\\sThey've\\sgifted\\sa\\stotal\\sof\\s100\\sin\\sthe\\schannel!;tmi-sent-ts=1594583777669;user-id=211711554;user-type= :tmi.twitch.tv USERNOTICE #xqcow",
+            "@badge-info=;badges=sub-gifter/50;color=;display-name=AdamAtReflectStudios;emotes=;flags=;id=e21409b1-d25d-4a1a-b5cf-ef27d8b7030e;login=adamatreflectstudios;mod=0;msg-id=subgift;msg-param-community-gift-id=4757298626717165963;msg-param-gift-months=1;msg-param-months=2;msg-param-origin-id=1f\\sbe\\sbb\\s4a\\s81\\s9a\\s65\\sd1\\s4b\\s77\\sf5\\s23\\s16\\s4a\\sd3\\s13\\s09\\se7\\sbe\\s55;msg-param-recipient-display-name=qatarking24xd;msg-param-recipient-id=236653628;msg-param-recipient-user-name=qatarking24xd;msg-param-sender-count=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=1000;room-id=71092938;subscriber=0;system-msg=AdamAtReflectStudios\\sgifted\\sa\\sTier\\s1\\ssub\\sto\\sqatarking24xd!;tmi-sent-ts=1594583782376;user-id=211711554;user-type= :tmi.twitch.tv USERNOTICE #xqcow",
+            "@badge-info=;badges=sub-gifter/50;color=;display-name=AdamAtReflectStudios;emotes=;flags=;id=3b2b9a06-76e5-4f2c-8f3e-c9c4f4b8e1a0;login=adamatreflectstudios;mod=0;msg-id=subgift;msg-param-community-gift-id=4757298626717165963;msg-param-gift-months=1;msg-param-months=7;msg-param-origin-id=1f\\sbe\\sbb\\s4a\\s81\\s9a\\s65\\sd1\\s4b\\s77\\sf5\\s23\\s16\\s4a\\sd3\\s13\\s09\\se7\\sbe\\s55;msg-param-recipient-display-name=Dot0422;msg-param-recipient-id=151784015;msg-param-recipient-user-name=dot0422;msg-param-sender-count=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=1000;room-id=71092938;subscriber=0;system-msg=AdamAtReflectStudios\\sgifted\\sa\\sTier\\s1\\ssub\\sto\\sDot0422!;tmi-sent-ts=1594583782401;user-id=211711554;user-type= :tmi.twitch.tv USERNOTICE #xqcow",
+        ];
+        let events = srcs
+            .iter()
+            .map(|src| {
+                UserNoticeMessage::try_from(IRCMessage::parse(src).unwrap())
+                    .unwrap()
+                    .event
+            })
+            .collect::<Vec<_>>();
+
+        let mass_gift_id = match &events[0] {
+            UserNoticeEvent::SubMysteryGift {
+                mass_gift_count: 2,
+                community_gift_id: Some(community_gift_id),
+                ..
+            } => community_gift_id,
+            other => panic!("unexpected event: {:?}", other),
+        };
+        assert_eq!(mass_gift_id, "4757298626717165963");
+
+        let recipients = events[1..]
+            .iter()
+            .map(|event| match event {
+                UserNoticeEvent::SubGift {
+                    recipient,
+                    community_gift_id,
+                    ..
+                } => {
+                    assert_eq!(community_gift_id.as_ref(), Some(mass_gift_id));
+                    recipient.login.as_str()
+                }
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(recipients, ["qatarking24xd", "dot0422"]);
+    }
+
     #[test]
     pub fn test_giftpaidupgrade_no_promo() {
         let src = "@badge-info=subscriber/2;badges=subscriber/2;color=#00FFF5;display-name=CrazyCrackAnimal;emotes=;flags=;id=7006f242-a45c-4e07-83b3-11f9c6d1ee28;login=crazycrackanimal;mod=0;msg-id=giftpaidupgrade;msg-param-sender-login=stridezgum;msg-param-sender-name=Stridezgum;room-id=71092938;subscriber=1;system-msg=CrazyCrackAnimal\\sis\\scontinuing\\sthe\\sGift\\sSub\\sthey\\sgot\\sfrom\\sStridezgum!;tmi-sent-ts=1594518849459;user-id=86082877;user-type= :tmi.twitch.tv USERNOTICE #xqcow";
@@ -905,6 +1101,40 @@ mod tests {
         )
     }
 
+    #[test]
+    pub fn test_announcement() {
+        let src = "@badge-info=;badges=moderator/1;color=#19E6E6;display-name=randers;emotes=;flags=;id=c3c9dc43-1fe6-4b8d-8b63-a25f3fd6c4b2;login=randers;mod=1;msg-id=announcement;msg-param-color=PURPLE;room-id=11148817;subscriber=0;system-msg=;tmi-sent-ts=1661092316513;user-id=40286300;user-type=mod :tmi.twitch.tv USERNOTICE #pajlada :Stream starts in 5 minutes!";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = UserNoticeMessage::try_from(irc_message).unwrap();
+
+        assert_eq!(
+            msg.event,
+            UserNoticeEvent::Announcement {
+                color: AnnouncementColor::Purple
+            }
+        );
+        assert_eq!(msg.event_id, "announcement");
+        assert_eq!(msg.system_message, "");
+        assert_eq!(
+            msg.message_text,
+            Some("Stream starts in 5 minutes!".to_owned())
+        );
+
+        for (raw, color) in [
+            ("PRIMARY", AnnouncementColor::Primary),
+            ("BLUE", AnnouncementColor::Blue),
+            ("GREEN", AnnouncementColor::Green),
+            ("ORANGE", AnnouncementColor::Orange),
+            ("PURPLE", AnnouncementColor::Purple),
+            ("RAINBOW", AnnouncementColor::Other("RAINBOW".to_owned())),
+        ]
+        .iter()
+        {
+            assert_eq!(&raw.parse::<AnnouncementColor>().unwrap(), color);
+            assert_eq!(&color.to_string(), raw);
+        }
+    }
+
     #[test]
     pub fn test_unknown() {
         // just an example of an undocumented type of message that we don't parse currently.
@@ -947,4 +1177,14 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    pub fn test_display() {
+        let src = "@badge-info=subscriber/2;badges=subscriber/0,battlerite_1/1;color=#0000FF;display-name=Gutrin;emotes=1035663:0-3;flags=;id=e0975c76-054c-4954-8cb0-91b8867ec1ca;login=gutrin;mod=0;msg-id=resub;msg-param-cumulative-months=2;msg-param-months=0;msg-param-should-share-streak=1;msg-param-streak-months=2;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=1000;room-id=71092938;subscriber=1;system-msg=Gutrin\\ssubscribed\\sat\\sTier\\s1.;tmi-sent-ts=1581713640019;user-id=21156217;user-type= :tmi.twitch.tv USERNOTICE #xqcow :xqcL";
+        let msg = UserNoticeMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(
+            msg.to_string(),
+            "#xqcow Gutrin subscribed at Tier 1. - Gutrin: xqcL"
+        );
+    }
 }
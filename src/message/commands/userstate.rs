@@ -1,8 +1,8 @@
 use crate::message::commands::IRCMessageParseExt;
-use crate::message::twitch::{Badge, RGBColor};
+use crate::message::twitch::{subscriber_months, Badge, EmoteSetId, RGBColor};
 use crate::message::{IRCMessage, ServerMessageParseError};
-use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -30,12 +30,24 @@ pub struct UserStateMessage {
     /// List of badges the logged in user has in this channel.
     pub badges: Vec<Badge>,
     /// List of emote set IDs the logged in user has available. This always contains at least 0.
-    pub emote_sets: HashSet<u64>,
+    ///
+    /// The list is sorted and contains no duplicates, see `parse_emote_sets()`.
+    pub emote_sets: Vec<EmoteSetId>,
     /// What name color the logged in user has chosen. The same color is used in all channels.
     pub name_color: Option<RGBColor>,
 
     /// The message that this `UserStateMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
+}
+
+impl UserStateMessage {
+    /// Returns the exact number of months the logged in user has been subscribed to the channel,
+    /// as found in the `badge_info`. Returns `None` if the user is not subscribed.
+    ///
+    /// See also `PrivmsgMessage::subscriber_months()`.
+    pub fn subscriber_months(&self) -> Option<u64> {
+        subscriber_months(&self.badge_info)
+    }
 }
 
 impl TryFrom<IRCMessage> for UserStateMessage {
@@ -55,23 +67,24 @@ impl TryFrom<IRCMessage> for UserStateMessage {
             badges: source.try_get_badges("badges")?,
             emote_sets: source.try_get_emote_sets("emote-sets")?,
             name_color: source.try_get_color("color")?,
-            source,
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<UserStateMessage> for IRCMessage {
     fn from(msg: UserStateMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::message::commands::userstate::UserStateMessage;
-    use crate::message::twitch::RGBColor;
+    use crate::message::twitch::{EmoteSetId, RGBColor};
     use crate::message::IRCMessage;
     use std::convert::TryFrom;
+    use std::sync::Arc;
 
     #[test]
     pub fn test_basic() {
@@ -86,14 +99,25 @@ mod tests {
                 user_name: "TESTUSER".to_owned(),
                 badge_info: vec![],
                 badges: vec![],
-                emote_sets: vec![0].into_iter().collect(),
+                emote_sets: vec![EmoteSetId::Numeric(0)],
                 name_color: Some(RGBColor {
                     r: 0xFF,
                     g: 0x00,
                     b: 0x00
                 }),
-                source: irc_message
+                source: Arc::new(irc_message)
             }
         )
     }
+
+    #[test]
+    pub fn test_subscriber_months() {
+        let src = "@badge-info=subscriber/14;badges=subscriber/12;color=#FF0000;display-name=TESTUSER;emote-sets=0;mod=0;subscriber=1;user-type= :tmi.twitch.tv USERSTATE #randers";
+        let msg = UserStateMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.subscriber_months(), Some(14));
+
+        let src = "@badge-info=;badges=;color=#FF0000;display-name=TESTUSER;emote-sets=0;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE #randers";
+        let msg = UserStateMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.subscriber_months(), None);
+    }
 }
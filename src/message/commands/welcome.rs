@@ -0,0 +1,95 @@
+use crate::message::commands::{IRCMessageParseExt, ServerMessageParseError};
+use crate::message::IRCMessage;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+#[cfg(feature = "with-serde")]
+use {serde::Deserialize, serde::Serialize};
+
+/// The `001` (`RPL_WELCOME`) message, sent by the server once a connection has successfully
+/// logged in.
+///
+/// Twitch follows it up with the other numeric welcome replies (`002` to `004`) and
+/// the message of the day (`375`, `372` and `376`), which are not parsed
+/// into a specific type.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct WelcomeMessage {
+    /// Login name of the user the connection is logged in as, as acknowledged by the server,
+    /// e.g. `justinfan12345` for anonymous connections.
+    pub user_login: String,
+
+    /// The message that this `WelcomeMessage` was parsed from.
+    pub source: Arc<IRCMessage>,
+}
+
+impl TryFrom<IRCMessage> for WelcomeMessage {
+    type Error = ServerMessageParseError;
+
+    fn try_from(source: IRCMessage) -> Result<WelcomeMessage, ServerMessageParseError> {
+        if source.command != "001" {
+            return Err(ServerMessageParseError::MismatchedCommand(source));
+        }
+
+        Ok(WelcomeMessage {
+            user_login: source.try_get_param(0)?.to_owned(),
+            source: Arc::new(source),
+        })
+    }
+}
+
+impl From<WelcomeMessage> for IRCMessage {
+    fn from(msg: WelcomeMessage) -> IRCMessage {
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{IRCMessage, ServerMessage, WelcomeMessage};
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_basic() {
+        let src = ":tmi.twitch.tv 001 randers811 :Welcome, GLHF!";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = WelcomeMessage::try_from(irc_message.clone()).unwrap();
+
+        assert_eq!(
+            msg,
+            WelcomeMessage {
+                user_login: "randers811".to_owned(),
+                source: Arc::new(irc_message)
+            }
+        )
+    }
+
+    #[test]
+    pub fn test_welcome_block() {
+        // what Twitch sends right after logging in
+        let welcome_block = [
+            ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+            ":tmi.twitch.tv 002 justinfan12345 :Your host is tmi.twitch.tv",
+            ":tmi.twitch.tv 003 justinfan12345 :This server is rather new",
+            ":tmi.twitch.tv 004 justinfan12345 :-",
+            ":tmi.twitch.tv 375 justinfan12345 :-",
+            ":tmi.twitch.tv 372 justinfan12345 :You are in a maze of twisty passages, all alike.",
+            ":tmi.twitch.tv 376 justinfan12345 :>",
+        ];
+
+        let messages = welcome_block
+            .iter()
+            .map(|src| ServerMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        match &messages[0] {
+            ServerMessage::Welcome(msg) => assert_eq!(msg.user_login, "justinfan12345"),
+            other => panic!("expected welcome message, got {:?}", other),
+        }
+        // the rest of the block is not parsed into a specific type
+        for msg in &messages[1..] {
+            assert!(matches!(msg, ServerMessage::Generic(_)));
+        }
+    }
+}
@@ -2,10 +2,16 @@ use crate::message::commands::IRCMessageParseExt;
 use crate::message::twitch::{Badge, Emote, RGBColor, TwitchUserBasics};
 use crate::message::{IRCMessage, ServerMessageParseError};
 use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
 /// A incoming whisper message (a private user-to-user message).
+///
+/// Whispers received without the `twitch.tv/tags` capability carry no tags. They are still
+/// parsed, with `sender.name` set to the login name, an empty `sender.id`, `thread_id` and
+/// `message_id`, and no name color, badges or emotes.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct WhisperMessage {
@@ -22,9 +28,15 @@ pub struct WhisperMessage {
     /// A list of emotes in this message. Each emote replaces a part of the `message_text`.
     /// These emotes are sorted in the order that they appear in the message.
     pub emotes: Vec<Emote>,
+    /// Identifies the conversation between the two users that this whisper is part of
+    /// (`thread-id` tag), made up of the IDs of both users, e.g. `40286300_553170741`.
+    pub thread_id: String,
+    /// Identifies this whisper within its thread (`message-id` tag), e.g. to detect duplicates.
+    /// Only unique together with the `thread_id`.
+    pub message_id: String,
 
     /// The message that this `WhisperMessage` was parsed from.
-    pub source: IRCMessage,
+    pub source: Arc<IRCMessage>,
 }
 
 impl TryFrom<IRCMessage> for WhisperMessage {
@@ -39,13 +51,35 @@ impl TryFrom<IRCMessage> for WhisperMessage {
         // @badges=;color=#19E6E6;display-name=randers;emotes=25:22-26;message-id=1;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello, this is a test Kappa
 
         let message_text = source.try_get_param(1)?.to_owned();
+        let sender_login = source.try_get_prefix_nickname()?.to_owned();
+
+        // without the twitch.tv/tags capability, only the data from the prefix and parameters
+        // is available
+        if source.tags.0.is_empty() {
+            return Ok(WhisperMessage {
+                recipient_login: source.try_get_param(0)?.to_owned(),
+                sender: TwitchUserBasics {
+                    id: String::new(),
+                    name: sender_login.clone(),
+                    login: sender_login,
+                },
+                message_text,
+                name_color: None,
+                badges: vec![],
+                emotes: vec![],
+                thread_id: String::new(),
+                message_id: String::new(),
+                source: Arc::new(source),
+            });
+        }
+
         let emotes = source.try_get_emotes("emotes", &message_text)?;
 
         Ok(WhisperMessage {
             recipient_login: source.try_get_param(0)?.to_owned(),
             sender: TwitchUserBasics {
                 id: source.try_get_nonempty_tag_value("user-id")?.to_owned(),
-                login: source.try_get_prefix_nickname()?.to_owned(),
+                login: sender_login,
                 name: source
                     .try_get_nonempty_tag_value("display-name")?
                     .to_owned(),
@@ -54,23 +88,58 @@ impl TryFrom<IRCMessage> for WhisperMessage {
             name_color: source.try_get_color("color")?,
             badges: source.try_get_badges("badges")?,
             emotes,
-            source,
+            thread_id: source.try_get_nonempty_tag_value("thread-id")?.to_owned(),
+            message_id: source.try_get_nonempty_tag_value("message-id")?.to_owned(),
+            source: Arc::new(source),
         })
     }
 }
 
 impl From<WhisperMessage> for IRCMessage {
     fn from(msg: WhisperMessage) -> IRCMessage {
-        msg.source
+        Arc::unwrap_or_clone(msg.source)
+    }
+}
+
+/// Formats the whisper as a single line, e.g. `Randers -> randers811: hello`.
+impl fmt::Display for WhisperMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {}: {}",
+            self.sender.name, self.recipient_login, self.message_text
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::message::twitch::{Emote, RGBColor, TwitchUserBasics};
-    use crate::message::{IRCMessage, WhisperMessage};
+    use crate::message::{IRCMessage, ServerMessageParseError, WhisperMessage};
     use std::convert::TryFrom;
     use std::ops::Range;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_without_tags() {
+        let src = ":randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello, this is a test Kappa";
+        let msg = WhisperMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        assert_eq!(msg.recipient_login, "randers811");
+        assert_eq!(
+            msg.sender,
+            TwitchUserBasics {
+                id: "".to_owned(),
+                login: "randers".to_owned(),
+                name: "randers".to_owned(),
+            }
+        );
+        assert_eq!(msg.message_text, "hello, this is a test Kappa");
+        assert_eq!(msg.name_color, None);
+        assert_eq!(msg.emotes, vec![]);
+        assert_eq!(msg.thread_id, "");
+        assert_eq!(msg.message_id, "");
+    }
 
     #[test]
     pub fn test_basic() {
@@ -99,11 +168,39 @@ mod tests {
                     char_range: Range { start: 22, end: 27 },
                     code: "Kappa".to_owned()
                 }],
-                source: irc_message
+                thread_id: "40286300_553170741".to_owned(),
+                message_id: "1".to_owned(),
+                source: Arc::new(irc_message)
             },
         )
     }
 
+    #[test]
+    pub fn test_thread_id_and_message_id() {
+        let src = "@badges=premium/1;color=#8A2BE2;display-name=Zonianer;emotes=;message-id=15;thread-id=40286300_123456789;turbo=0;user-id=123456789;user-type= :zonianer!zonianer@zonianer.tmi.twitch.tv WHISPER randers :are you there?";
+        let msg = WhisperMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+        assert_eq!(msg.thread_id, "40286300_123456789");
+        assert_eq!(msg.message_id, "15");
+
+        let src = "@badges=premium/1;color=#8A2BE2;display-name=Zonianer;emotes=;message-id=15;turbo=0;user-id=123456789;user-type= :zonianer!zonianer@zonianer.tmi.twitch.tv WHISPER randers :are you there?";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        assert_eq!(
+            WhisperMessage::try_from(irc_message.clone()).unwrap_err(),
+            ServerMessageParseError::MissingTag(irc_message, "thread-id")
+        );
+    }
+
     // note, I have tested and there is no support for \u0001ACTION <message>\u0001 style actions
     // via whispers. (the control character gets filtered.) - so there is no special case to test
+
+    #[test]
+    pub fn test_display() {
+        let src = "@badges=;color=#19E6E6;display-name=randers;emotes=25:22-26;message-id=1;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers811 :hello, this is a test Kappa";
+        let irc_message = IRCMessage::parse(src).unwrap();
+        let msg = WhisperMessage::try_from(irc_message).unwrap();
+        assert_eq!(
+            msg.to_string(),
+            "randers -> randers811: hello, this is a test Kappa"
+        );
+    }
 }
@@ -1,26 +1,35 @@
 //! Generic and Twitch-specific IRC messages.
 
+pub(crate) mod chat_command;
 pub(crate) mod commands;
 pub(crate) mod prefix;
 pub(crate) mod tags;
 pub(crate) mod twitch;
 
+pub use chat_command::{ChatCommand, ChatCommandError};
 pub use commands::clearchat::{ClearChatAction, ClearChatMessage};
 pub use commands::clearmsg::ClearMsgMessage;
 pub use commands::globaluserstate::GlobalUserStateMessage;
 pub use commands::hosttarget::{HostTargetAction, HostTargetMessage};
 pub use commands::join::JoinMessage;
-pub use commands::notice::NoticeMessage;
+pub use commands::notice::{NoticeMessage, SendFailureReason};
 pub use commands::part::PartMessage;
 pub use commands::ping::PingMessage;
 pub use commands::pong::PongMessage;
-pub use commands::privmsg::PrivmsgMessage;
+pub use commands::privmsg::{
+    HypeChat, PrivmsgMessage, ReplyContext, ReplyParent, ReplyThread, SharedChatSource,
+};
 pub use commands::reconnect::ReconnectMessage;
-pub use commands::roomstate::{FollowersOnlyMode, RoomStateMessage};
-pub use commands::usernotice::{SubGiftPromo, UserNoticeEvent, UserNoticeMessage};
+pub use commands::roomstate::{FollowersOnlyMode, RoomState, RoomStateMessage};
+pub use commands::usernotice::{
+    AnnouncementColor, SubGiftPromo, UserNoticeEvent, UserNoticeMessage,
+};
 pub use commands::userstate::UserStateMessage;
+pub use commands::welcome::WelcomeMessage;
 pub use commands::whisper::WhisperMessage;
-pub use commands::{ServerMessage, ServerMessageParseError};
+pub use commands::{
+    MessageKind, ParseServerMessageError, RaidEvent, ServerMessage, ServerMessageParseError,
+};
 pub use prefix::IRCPrefix;
 pub use tags::IRCTags;
 pub use twitch::*;
@@ -28,6 +37,8 @@ pub use twitch::*;
 use itertools::Itertools;
 use std::fmt;
 use std::fmt::Write;
+use std::num::ParseIntError;
+use std::str::FromStr;
 use thiserror::Error;
 
 #[cfg(feature = "with-serde")]
@@ -57,6 +68,112 @@ pub enum IRCParseError {
     /// Newlines are not permitted in raw IRC messages
     #[error("Newlines are not permitted in raw IRC messages")]
     NewlinesInMessage,
+    /// More tags than permitted by `IRCParseOptions::max_tags`
+    #[error("More tags than permitted by the parse options")]
+    TooManyTags,
+    /// Tags longer than permitted by `IRCParseOptions::max_tags_len`
+    #[error("Tags longer than permitted by the parse options")]
+    TagsTooLong,
+}
+
+/// Limits to apply while parsing an `IRCMessage`, see `IRCMessage::parse_with_options`.
+///
+/// The default options don't limit anything, which is what `IRCMessage::parse` uses.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::{IRCMessage, IRCParseError, IRCParseOptions};
+///
+/// let options = IRCParseOptions {
+///     max_tags: Some(2),
+///     ..IRCParseOptions::default()
+/// };
+/// let result = IRCMessage::parse_with_options("@a=1;b=2;c=3 :tmi.twitch.tv PING", &options);
+/// assert_eq!(result, Err(IRCParseError::TooManyTags));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IRCParseOptions {
+    /// If set, messages with more tags than this are rejected with
+    /// `IRCParseError::TooManyTags`.
+    pub max_tags: Option<usize>,
+    /// If set, messages whose tags part (everything between the leading `@` and the
+    /// following space) is longer than this many bytes are rejected with
+    /// `IRCParseError::TagsTooLong`.
+    pub max_tags_len: Option<usize>,
+}
+
+/// Error returned when a channel login passed to one of the outgoing message constructors
+/// (e.g. [`IRCMessage::new_privmsg`](IRCMessage::new_privmsg)) is not usable.
+///
+/// The channel login must be the plain login name of the channel (without the leading `#`),
+/// must not be empty and must not contain any whitespace.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Invalid channel login `{0}`: must be non-empty, without leading # and without whitespace")]
+pub struct InvalidChannelLoginError(pub String);
+
+fn validate_channel_login(channel_login: &str) -> Result<(), InvalidChannelLoginError> {
+    if channel_login.is_empty()
+        || channel_login.starts_with('#')
+        || channel_login.chars().any(char::is_whitespace)
+    {
+        return Err(InvalidChannelLoginError(channel_login.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// A channel login that has been validated and normalized to lowercase, as expected by
+/// Twitch when joining or parting channels (`JOIN #Pajlada` would not join `#pajlada`).
+///
+/// The same validation rules as for [`InvalidChannelLoginError`](InvalidChannelLoginError)
+/// apply.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::ChannelLogin;
+///
+/// assert_eq!(ChannelLogin::new("Pajlada").unwrap().as_str(), "pajlada");
+/// assert!(ChannelLogin::new("#pajlada").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChannelLogin(String);
+
+impl ChannelLogin {
+    /// Validate the given channel login and convert it to lowercase.
+    pub fn new(channel_login: &str) -> Result<ChannelLogin, InvalidChannelLoginError> {
+        validate_channel_login(channel_login)?;
+        Ok(ChannelLogin(channel_login.to_lowercase()))
+    }
+
+    /// Get the normalized channel login.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Convert this into the normalized channel login `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for ChannelLogin {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ChannelLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<ChannelLogin> for String {
+    fn from(channel_login: ChannelLogin) -> String {
+        channel_login.0
+    }
 }
 
 struct RawIRCDisplay<'a, T: AsRawIRC>(&'a T);
@@ -77,8 +194,11 @@ pub trait AsRawIRC {
     /// but due to protocol ambiguity it is not guaranteed to be identical to the input
     /// the value was parsed from (if it was parsed at all).
     ///
-    /// For example, the order of tags might differ, or the use of trailing parameters
-    /// might be different.
+    /// Tags are always written sorted by their key, and the last parameter is only written
+    /// as a trailing parameter (with a leading `:`) if it is required, i.e. if the parameter is
+    /// empty, contains a space or starts with a `:`. Apart from these normalizations (and invalid
+    /// escape sequences in tag values, which are dropped while parsing), the output for a message
+    /// received from Twitch is identical to the line that was received.
     fn as_raw_irc(&self) -> String
     where
         Self: Sized,
@@ -150,9 +270,57 @@ macro_rules! irc {
     };
 }
 
+/// Allows quick creation of outgoing `PRIVMSG` messages, given the channel and the message text.
+///
+/// This is a shorthand for `IRCMessage::new_privmsg()`, so the channel login is validated in
+/// the same way and the macro evaluates to a `Result<IRCMessage, InvalidChannelLoginError>`.
+/// Both the channel login and the message text have to implement `From<T> for String` if they
+/// are not already of type `String`. The message text is the last parameter, so it may contain
+/// spaces or start with `:` (it is prefixed with `:` as needed once the message is formatted to
+/// be sent).
+///
+/// Like `TwitchIRCClient::privmsg()`, the message text is sent as-is, so e.g. a text starting
+/// with `/` is executed as a chat command.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::privmsg;
+/// use twitch_irc::message::AsRawIRC;
+///
+/// # fn main() {
+/// let channel_login = "sodapoppin";
+/// let msg = privmsg!(channel_login, format!("Hello {}!", "guys")).unwrap();
+///
+/// assert_eq!(msg.as_raw_irc(), "PRIVMSG #sodapoppin :Hello guys!");
+///
+/// assert!(privmsg!("sodapoppin pajlada", "Hello guys!").is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! privmsg {
+    ($channel_login:expr, $message:expr $(,)?) => {{
+        let channel_login = ::std::string::String::from($channel_login);
+        let message = ::std::string::String::from($message);
+        $crate::message::IRCMessage::new_privmsg(&channel_login, &message)
+    }};
+}
+
 impl IRCMessage {
     /// Create a new `IRCMessage` with just a command and parameters, similar to the
     /// `irc!` macro.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{AsRawIRC, IRCMessage};
+    ///
+    /// let message = IRCMessage::new_simple(
+    ///     "PRIVMSG".to_owned(),
+    ///     vec!["#pajlada".to_owned(), "Hello world!".to_owned()],
+    /// );
+    /// assert_eq!(message.as_raw_irc(), "PRIVMSG #pajlada :Hello world!");
+    /// ```
     pub fn new_simple(command: String, params: Vec<String>) -> IRCMessage {
         IRCMessage {
             tags: IRCTags::new(),
@@ -163,6 +331,66 @@ impl IRCMessage {
     }
 
     /// Create a new `IRCMessage` by specifying all fields.
+    ///
+    /// This is useful to synthesize messages e.g. in tests, which can then be serialized
+    /// using `as_raw_irc()` or converted into one of the typed messages, just like a message
+    /// received from Twitch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use twitch_irc::message::{AsRawIRC, IRCMessage, IRCPrefix, IRCTags, JoinMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let message = IRCMessage::new(
+    ///     IRCTags::new(),
+    ///     Some(IRCPrefix::Full {
+    ///         nick: "randers".to_owned(),
+    ///         user: Some("randers".to_owned()),
+    ///         host: Some("randers.tmi.twitch.tv".to_owned()),
+    ///     }),
+    ///     "JOIN".to_owned(),
+    ///     vec!["#pajlada".to_owned()],
+    /// );
+    /// assert_eq!(
+    ///     message.as_raw_irc(),
+    ///     ":randers!randers@randers.tmi.twitch.tv JOIN #pajlada"
+    /// );
+    ///
+    /// let join = JoinMessage::try_from(message).unwrap();
+    /// assert_eq!(join.channel_login, "pajlada");
+    /// assert_eq!(join.user_login, "randers");
+    /// ```
+    ///
+    /// With tags:
+    ///
+    /// ```
+    /// use twitch_irc::message::{AsRawIRC, IRCMessage, IRCPrefix, IRCTags, ServerMessage};
+    /// use std::convert::TryFrom;
+    ///
+    /// let mut tags = IRCTags::new();
+    /// tags.0.insert("msg-id".to_owned(), Some("msg_banned".to_owned()));
+    ///
+    /// let message = IRCMessage::new(
+    ///     tags,
+    ///     Some(IRCPrefix::HostOnly {
+    ///         host: "tmi.twitch.tv".to_owned(),
+    ///     }),
+    ///     "NOTICE".to_owned(),
+    ///     vec!["#forsen".to_owned(), "You are permanently banned from talking in forsen.".to_owned()],
+    /// );
+    /// assert_eq!(
+    ///     message.as_raw_irc(),
+    ///     "@msg-id=msg_banned :tmi.twitch.tv NOTICE #forsen :You are permanently banned from talking in forsen."
+    /// );
+    ///
+    /// match ServerMessage::try_from(message).unwrap() {
+    ///     ServerMessage::Notice(notice) => {
+    ///         assert_eq!(notice.message_id.as_deref(), Some("msg_banned"));
+    ///     }
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
     pub fn new(
         tags: IRCTags,
         prefix: Option<IRCPrefix>,
@@ -177,9 +405,155 @@ impl IRCMessage {
         }
     }
 
+    /// Create a new `PRIVMSG` message sending `message_text` to the channel `channel_login`.
+    ///
+    /// The `channel_login` is given without the leading `#`, which is added automatically.
+    /// An error is returned if the `channel_login` is empty, already has a leading `#` or
+    /// contains whitespace.
+    ///
+    /// The message text is always sent as the trailing parameter, so messages beginning with a
+    /// `:` or containing spaces are transmitted exactly as given.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{AsRawIRC, IRCMessage};
+    ///
+    /// let msg = IRCMessage::new_privmsg("pajlada", ":) hi").unwrap();
+    /// assert_eq!(msg.as_raw_irc(), "PRIVMSG #pajlada ::) hi");
+    ///
+    /// assert!(IRCMessage::new_privmsg("#pajlada", "hi").is_err());
+    /// ```
+    pub fn new_privmsg(
+        channel_login: &str,
+        message_text: &str,
+    ) -> Result<IRCMessage, InvalidChannelLoginError> {
+        validate_channel_login(channel_login)?;
+
+        Ok(IRCMessage::new_simple(
+            "PRIVMSG".to_owned(),
+            vec![format!("#{}", channel_login), message_text.to_owned()],
+        ))
+    }
+
+    /// Get the value of the tag `key`, including tags that this crate does not otherwise
+    /// know about (e.g. tags that Twitch added after this version was released).
+    ///
+    /// A tag can be in one of three states, which this method maps as follows:
+    ///
+    /// * The tag is not present at all (`...;other=1`): `None`
+    /// * The tag is present without a value (`...;key;...`) or with an empty value
+    ///   (`...;key=;...`): `Some("")`. These two forms are
+    ///   [equivalent according to the IRCv3 specification](https://ircv3.net/specs/extensions/message-tags.html#format).
+    /// * The tag is present with a value (`...;key=value;...`): `Some("value")`,
+    ///   with escape sequences already decoded.
+    ///
+    /// Use `self.tags` directly if you need to tell the first two forms of the second state apart.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::IRCMessage;
+    ///
+    /// let msg = IRCMessage::parse("@returning-chatter=0;emotes=;flags :tmi.twitch.tv PING").unwrap();
+    /// assert_eq!(msg.get_tag("returning-chatter"), Some("0"));
+    /// assert_eq!(msg.get_tag("emotes"), Some(""));
+    /// assert_eq!(msg.get_tag("flags"), Some(""));
+    /// assert_eq!(msg.get_tag("some-future-tag"), None);
+    /// ```
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .0
+            .get(key)
+            .map(|value| value.as_deref().unwrap_or(""))
+    }
+
+    /// Get the value of the tag `key` parsed as a `T`, e.g. for tags this crate does not
+    /// otherwise parse.
+    ///
+    /// Returns `Ok(None)` if the tag is not present, and an error if the value (see `get_tag`
+    /// for how tags without a value are treated) could not be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::IRCMessage;
+    ///
+    /// let msg = IRCMessage::parse("@msg-param-viewerCount=430;msg-param-color=PRIMARY :tmi.twitch.tv PING").unwrap();
+    /// assert_eq!(msg.get_tag_as::<u32>("msg-param-viewerCount"), Ok(Some(430)));
+    /// assert!(msg.get_tag_as::<u32>("msg-param-color").is_err());
+    /// assert_eq!(msg.get_tag_as::<u32>("some-future-tag"), Ok(None));
+    /// ```
+    pub fn get_tag_as<T: FromStr>(&self, key: &str) -> Result<Option<T>, T::Err> {
+        self.get_tag(key).map(T::from_str).transpose()
+    }
+
+    /// Get the value of the tag `key` as a number, see `get_tag_as`.
+    pub fn get_u64_tag(&self, key: &str) -> Result<Option<u64>, ParseIntError> {
+        self.get_tag_as(key)
+    }
+
+    /// Get the value of a tag that Twitch uses as a flag, like `mod=1` or `first-msg=0`.
+    ///
+    /// Returns `true` if the value is a number other than `0`, and `false` if it is `0`,
+    /// if the tag has no value or if the tag is not present at all. Returns an error if the
+    /// value is not a number.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::IRCMessage;
+    ///
+    /// let msg = IRCMessage::parse("@mod=1;first-msg=0;turbo=yes :tmi.twitch.tv PING").unwrap();
+    /// assert_eq!(msg.get_bool_tag("mod"), Ok(true));
+    /// assert_eq!(msg.get_bool_tag("first-msg"), Ok(false));
+    /// assert_eq!(msg.get_bool_tag("returning-chatter"), Ok(false));
+    /// assert!(msg.get_bool_tag("turbo").is_err());
+    /// ```
+    pub fn get_bool_tag(&self, key: &str) -> Result<bool, ParseIntError> {
+        match self.get_tag(key) {
+            None | Some("") => Ok(false),
+            Some(value) => Ok(u64::from_str(value)? > 0),
+        }
+    }
+
+    /// Iterate over all tags of this message as `(key, value)` pairs, without cloning
+    /// any of them. The value is `None` for tags that are present without a value.
+    ///
+    /// Tags are returned in no particular order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::IRCMessage;
+    ///
+    /// let msg = IRCMessage::parse("@mod=1;flags :tmi.twitch.tv PING").unwrap();
+    /// let mut tags = msg.tags_iter().collect::<Vec<_>>();
+    /// tags.sort();
+    /// assert_eq!(tags, vec![("flags", None), ("mod", Some("1"))]);
+    /// ```
+    pub fn tags_iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.tags
+            .0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_deref()))
+    }
+
     /// Parse a raw IRC wire-format message into an `IRCMessage`. `source` should be specified
     /// without trailing newline character(s).
-    pub fn parse(mut source: &str) -> Result<IRCMessage, IRCParseError> {
+    pub fn parse(source: &str) -> Result<IRCMessage, IRCParseError> {
+        IRCMessage::parse_with_options(source, &IRCParseOptions::default())
+    }
+
+    /// Parse a raw IRC wire-format message into an `IRCMessage`, like `parse`, but enforce
+    /// the limits given in `options`.
+    ///
+    /// The limits are checked before the tags are decoded, so a message with a pathological
+    /// amount of tags is rejected without allocating storage for them.
+    pub fn parse_with_options(
+        mut source: &str,
+        options: &IRCParseOptions,
+    ) -> Result<IRCMessage, IRCParseError> {
         if source.chars().any(|c| c == '\r' || c == '\n') {
             return Err(IRCParseError::NewlinesInMessage);
         }
@@ -195,6 +569,17 @@ impl IRCMessage {
             if tags_part.is_empty() {
                 return Err(IRCParseError::EmptyTagsDeclaration);
             }
+            if let Some(max_tags_len) = options.max_tags_len {
+                if tags_part.len() > max_tags_len {
+                    return Err(IRCParseError::TagsTooLong);
+                }
+            }
+            if let Some(max_tags) = options.max_tags {
+                // one more tag than there are separators
+                if tags_part.split(';').nth(max_tags).is_some() {
+                    return Err(IRCParseError::TooManyTags);
+                }
+            }
 
             IRCTags::parse(tags_part)
         } else {
@@ -841,6 +1226,65 @@ mod tests {
         assert_eq!(IRCMessage::parse("ping").unwrap().command, "PING")
     }
 
+    #[test]
+    fn test_new_privmsg() {
+        let message = IRCMessage::new_privmsg("pajlada", "LUL xD").unwrap();
+        assert_eq!(message, irc!["PRIVMSG", "#pajlada", "LUL xD"]);
+        assert_eq!(message.as_raw_irc(), "PRIVMSG #pajlada :LUL xD");
+    }
+
+    #[test]
+    fn test_new_privmsg_leading_colon() {
+        let message = IRCMessage::new_privmsg("pajlada", ":tf:").unwrap();
+        assert_eq!(
+            message.params,
+            vec!["#pajlada".to_owned(), ":tf:".to_owned()]
+        );
+        assert_eq!(message.as_raw_irc(), "PRIVMSG #pajlada ::tf:");
+        assert_eq!(IRCMessage::parse(&message.as_raw_irc()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_new_privmsg_invalid_channel() {
+        assert_eq!(
+            IRCMessage::new_privmsg("paj lada", "test"),
+            Err(InvalidChannelLoginError("paj lada".to_owned()))
+        );
+        assert_eq!(
+            IRCMessage::new_privmsg("", "test"),
+            Err(InvalidChannelLoginError("".to_owned()))
+        );
+        assert_eq!(
+            IRCMessage::new_privmsg("#pajlada", "test"),
+            Err(InvalidChannelLoginError("#pajlada".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_channel_login() {
+        assert_eq!(ChannelLogin::new("pajlada").unwrap().as_str(), "pajlada");
+        assert_eq!(
+            ChannelLogin::new("PajLada_1").unwrap().as_str(),
+            "pajlada_1"
+        );
+        assert_eq!(
+            ChannelLogin::new("Pajlada").unwrap().to_string(),
+            "pajlada".to_owned()
+        );
+        assert_eq!(
+            ChannelLogin::new("#Pajlada"),
+            Err(InvalidChannelLoginError("#Pajlada".to_owned()))
+        );
+        assert_eq!(
+            ChannelLogin::new("paj lada"),
+            Err(InvalidChannelLoginError("paj lada".to_owned()))
+        );
+        assert_eq!(
+            ChannelLogin::new(""),
+            Err(InvalidChannelLoginError("".to_owned()))
+        );
+    }
+
     #[test]
     fn test_irc_macro() {
         assert_eq!(
@@ -871,4 +1315,143 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_privmsg_macro() {
+        let cases = [
+            (privmsg!("pajlada", "LUL xD"), "PRIVMSG #pajlada :LUL xD"),
+            (privmsg!("pajlada", "LUL"), "PRIVMSG #pajlada LUL"),
+            (privmsg!("pajlada", ":)"), "PRIVMSG #pajlada ::)"),
+            (privmsg!("pajlada", ""), "PRIVMSG #pajlada :"),
+            (
+                privmsg!(String::from("pajlada"), format!("{} {}", "/me", "waves"),),
+                "PRIVMSG #pajlada :/me waves",
+            ),
+        ];
+        for (message, raw) in &cases {
+            let message = message.as_ref().unwrap();
+            assert_eq!(message.as_raw_irc(), *raw);
+            assert_eq!(&IRCMessage::parse(raw).unwrap(), message);
+        }
+        assert_eq!(
+            privmsg!("pajlada", "LUL xD"),
+            Ok(irc!["PRIVMSG", "#pajlada", "LUL xD"])
+        );
+    }
+
+    #[test]
+    fn test_privmsg_macro_invalid_channel() {
+        for channel_login in &["", "#pajlada", "a b"] {
+            assert_eq!(
+                privmsg!(*channel_login, "LUL"),
+                Err(InvalidChannelLoginError((*channel_login).to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_options_too_many_tags() {
+        let tags = (0..100_000)
+            .map(|i| format!("key{}=value", i))
+            .collect::<Vec<_>>()
+            .join(";");
+        let source = format!("@{} :tmi.twitch.tv PING", tags);
+        let options = IRCParseOptions {
+            max_tags: Some(1000),
+            ..IRCParseOptions::default()
+        };
+        assert_eq!(
+            IRCMessage::parse_with_options(&source, &options),
+            Err(IRCParseError::TooManyTags)
+        );
+
+        // no limits by default
+        assert_eq!(IRCMessage::parse(&source).unwrap().tags.0.len(), 100_000);
+    }
+
+    #[test]
+    fn test_parse_options_tags_too_long() {
+        let source = format!("@key={} :tmi.twitch.tv PING", "a".repeat(100_000));
+        let options = IRCParseOptions {
+            max_tags_len: Some(8192),
+            ..IRCParseOptions::default()
+        };
+        assert_eq!(
+            IRCMessage::parse_with_options(&source, &options),
+            Err(IRCParseError::TagsTooLong)
+        );
+    }
+
+    #[test]
+    fn test_parse_options_within_limits() {
+        let options = IRCParseOptions {
+            max_tags: Some(3),
+            max_tags_len: Some(11),
+        };
+        // exactly 3 tags and 11 bytes
+        let message =
+            IRCMessage::parse_with_options("@a=1;b=2;c=3 :tmi.twitch.tv PING", &options).unwrap();
+        assert_eq!(message.tags.0.len(), 3);
+
+        assert_eq!(
+            IRCMessage::parse_with_options("@a;b;c;d :tmi.twitch.tv PING", &options),
+            Err(IRCParseError::TooManyTags)
+        );
+        assert_eq!(
+            IRCMessage::parse_with_options("@a=1;b=2;c=34 :tmi.twitch.tv PING", &options),
+            Err(IRCParseError::TagsTooLong)
+        );
+        // messages without tags are not affected
+        assert!(IRCMessage::parse_with_options(":tmi.twitch.tv PING", &options).is_ok());
+    }
+
+    #[test]
+    fn test_tags_iter() {
+        let message = IRCMessage::parse("@badge-info=;badges=moderator/1;display-name=Test\\sUser;flags :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :test").unwrap();
+        let mut tags = message.tags_iter().collect::<Vec<_>>();
+        tags.sort();
+        assert_eq!(
+            tags,
+            vec![
+                ("badge-info", Some("")),
+                ("badges", Some("moderator/1")),
+                ("display-name", Some("Test User")),
+                ("flags", None),
+            ]
+        );
+
+        let message = IRCMessage::parse(":tmi.twitch.tv PING").unwrap();
+        assert_eq!(message.tags_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_typed_tags() {
+        let message = IRCMessage::parse("@bits=100;color=#19E6E6;emotes=;flags;mod=1;subscriber=0;turbo=true;big=99999999999999999999;neg=-1 :tmi.twitch.tv PING").unwrap();
+
+        assert_eq!(message.get_tag_as::<u32>("bits"), Ok(Some(100)));
+        assert_eq!(
+            message.get_tag_as::<String>("color"),
+            Ok(Some("#19E6E6".to_owned()))
+        );
+        assert_eq!(message.get_tag_as::<u32>("missing"), Ok(None));
+        assert!(message.get_tag_as::<u32>("color").is_err());
+        // present without a value
+        assert!(message.get_tag_as::<u32>("emotes").is_err());
+        assert!(message.get_tag_as::<u32>("flags").is_err());
+
+        assert_eq!(message.get_u64_tag("bits"), Ok(Some(100)));
+        assert_eq!(message.get_u64_tag("missing"), Ok(None));
+        assert!(message.get_u64_tag("big").is_err());
+        assert!(message.get_u64_tag("neg").is_err());
+        assert!(message.get_u64_tag("color").is_err());
+
+        assert_eq!(message.get_bool_tag("mod"), Ok(true));
+        assert_eq!(message.get_bool_tag("bits"), Ok(true));
+        assert_eq!(message.get_bool_tag("subscriber"), Ok(false));
+        assert_eq!(message.get_bool_tag("emotes"), Ok(false));
+        assert_eq!(message.get_bool_tag("flags"), Ok(false));
+        assert_eq!(message.get_bool_tag("missing"), Ok(false));
+        assert!(message.get_bool_tag("turbo").is_err());
+        assert!(message.get_bool_tag("neg").is_err());
+    }
 }
@@ -1,6 +1,11 @@
 //! Twitch-specifica that only appear on Twitch-specific messages/tags.
 
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
 use std::ops::Range;
+use std::str::FromStr;
 
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
@@ -67,6 +72,91 @@ pub struct Emote {
     pub code: String,
 }
 
+impl Emote {
+    /// Returns the URL of the image of this emote on the Twitch CDN, e.g.
+    /// `https://static-cdn.jtvnw.net/emoticons/v2/25/default/dark/1.0` for `Kappa`.
+    ///
+    /// The `default` format is used, which is animated for animated emotes and static otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use twitch_irc::message::{Emote, EmoteScale, EmoteTheme};
+    ///
+    /// let emote = Emote {
+    ///     id: "25".to_owned(),
+    ///     char_range: 0..5,
+    ///     code: "Kappa".to_owned(),
+    /// };
+    /// assert_eq!(
+    ///     emote.image_url(EmoteTheme::Light, EmoteScale::Large),
+    ///     "https://static-cdn.jtvnw.net/emoticons/v2/25/default/light/3.0"
+    /// );
+    /// ```
+    pub fn image_url(&self, theme: EmoteTheme, scale: EmoteScale) -> String {
+        let theme = match theme {
+            EmoteTheme::Light => "light",
+            EmoteTheme::Dark => "dark",
+        };
+        let scale = match scale {
+            EmoteScale::Small => "1.0",
+            EmoteScale::Medium => "2.0",
+            EmoteScale::Large => "3.0",
+        };
+        format!(
+            "https://static-cdn.jtvnw.net/emoticons/v2/{}/default/{}/{}",
+            self.id, theme, scale
+        )
+    }
+}
+
+/// The background an emote image is made for, see `Emote::image_url()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum EmoteTheme {
+    /// For light backgrounds (`light`)
+    Light,
+    /// For dark backgrounds (`dark`)
+    Dark,
+}
+
+/// The size of an emote image, see `Emote::image_url()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum EmoteScale {
+    /// The smallest size (`1.0`), 28x28 pixels for most emotes
+    Small,
+    /// Twice the smallest size (`2.0`)
+    Medium,
+    /// Four times the smallest size (`3.0`)
+    Large,
+}
+
+/// Reads the exact number of months a user has been subscribed from the `badge-info` of a
+/// message, i.e. the version of the `subscriber` (or `founder`) entry, if the user is subscribed.
+pub(crate) fn subscriber_months(badge_info: &[Badge]) -> Option<u64> {
+    badge_info
+        .iter()
+        .find(|badge| badge.name == "subscriber" || badge.name == "founder")
+        .and_then(|badge| badge.version.parse().ok())
+}
+
+/// Groups the given emotes by their ID, mapping each emote ID to the `char_range`s of all
+/// occurrences of that emote (in the order they appear in `emotes`).
+///
+/// This is useful e.g. to count how many times each emote was used in a message.
+/// See also `PrivmsgMessage::emotes_by_id()`.
+pub fn group_emotes_by_id(emotes: &[Emote]) -> HashMap<String, Vec<Range<usize>>> {
+    let mut emotes_by_id: HashMap<String, Vec<Range<usize>>> = HashMap::new();
+    for emote in emotes {
+        emotes_by_id
+            .entry(emote.id.clone())
+            .or_default()
+            .push(emote.char_range.clone());
+    }
+    emotes_by_id
+}
+
 /// A single Twitch "badge" to be shown next to the user's name in chat.
 ///
 /// The combination of `name` and `version` fully describes the exact badge to display.
@@ -80,3 +170,263 @@ pub struct Badge {
     /// to differentiate between levels, or lengths, or similar, depending on the badge.
     pub version: String,
 }
+
+/// How privileged a user is in a channel, e.g. to check who may use a bot command. See
+/// `PrivmsgMessage::permission_level()`.
+///
+/// Levels are ordered from least to most privileged, so they can be compared:
+/// `Everyone < Subscriber < Vip < Moderator < Broadcaster`.
+///
+/// On Twitch, a user cannot be VIP and moderator of the same channel at once (making a VIP
+/// a moderator removes the VIP role), so VIPs are placed below moderators here because
+/// moderators can do everything VIPs can. Use `PrivmsgMessage::is_vip()` to treat VIPs
+/// differently from moderators instead.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::PermissionLevel;
+///
+/// assert!(PermissionLevel::Broadcaster > PermissionLevel::Moderator);
+/// assert!(PermissionLevel::Vip >= PermissionLevel::Subscriber);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum PermissionLevel {
+    /// Any user.
+    Everyone,
+    /// A subscriber of the channel (`subscriber` or `founder` badge).
+    Subscriber,
+    /// A VIP of the channel (`vip` badge or `vip` tag, see `PrivmsgMessage::is_vip()`).
+    Vip,
+    /// A moderator of the channel (`moderator` or `lead_moderator` badge).
+    Moderator,
+    /// The owner of the channel (`broadcaster` badge).
+    Broadcaster,
+}
+
+/// The plan (tier) of a subscription, e.g. in a `UserNoticeEvent::SubOrResub`.
+///
+/// Twitch sends the plan as `Prime`, `1000`, `2000` or `3000`. `Display` formats the plan
+/// back into this raw value.
+///
+/// ```
+/// use twitch_irc::message::SubPlan;
+///
+/// assert_eq!(SubPlan::from("2000"), SubPlan::Tier2);
+/// assert_eq!(SubPlan::Tier2.to_string(), "2000");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum SubPlan {
+    /// A subscription using Prime Gaming (`Prime`). This is equivalent to a tier 1 subscription.
+    Prime,
+    /// A tier 1 subscription (`1000`)
+    Tier1,
+    /// A tier 2 subscription (`2000`)
+    Tier2,
+    /// A tier 3 subscription (`3000`)
+    Tier3,
+    /// Any other value, in case Twitch introduces new plans.
+    Other(String),
+}
+
+impl From<&str> for SubPlan {
+    fn from(raw: &str) -> SubPlan {
+        match raw {
+            "Prime" => SubPlan::Prime,
+            "1000" => SubPlan::Tier1,
+            "2000" => SubPlan::Tier2,
+            "3000" => SubPlan::Tier3,
+            other => SubPlan::Other(other.to_owned()),
+        }
+    }
+}
+
+impl FromStr for SubPlan {
+    type Err = Infallible;
+
+    fn from_str(raw: &str) -> Result<SubPlan, Infallible> {
+        Ok(SubPlan::from(raw))
+    }
+}
+
+impl fmt::Display for SubPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SubPlan::Prime => "Prime",
+            SubPlan::Tier1 => "1000",
+            SubPlan::Tier2 => "2000",
+            SubPlan::Tier3 => "3000",
+            SubPlan::Other(raw) => raw,
+        })
+    }
+}
+
+/// ID of an emote set, as listed in the `emote-sets` tag of `USERSTATE` and `GLOBALUSERSTATE`.
+///
+/// Most emote set IDs are numeric, but some are not (e.g. UUIDs), these are kept as strings.
+/// Numeric IDs sort before all other IDs, see `parse_emote_sets()`.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::EmoteSetId;
+///
+/// assert_eq!(EmoteSetId::from("19194"), EmoteSetId::Numeric(19194));
+/// assert_eq!(EmoteSetId::Numeric(19194).to_string(), "19194");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum EmoteSetId {
+    /// A numeric emote set ID, e.g. `0` or `19194`
+    Numeric(u64),
+    /// Any emote set ID that is not a number
+    Other(String),
+}
+
+impl From<&str> for EmoteSetId {
+    fn from(raw: &str) -> EmoteSetId {
+        match u64::from_str(raw) {
+            Ok(id) => EmoteSetId::Numeric(id),
+            Err(_) => EmoteSetId::Other(raw.to_owned()),
+        }
+    }
+}
+
+impl FromStr for EmoteSetId {
+    type Err = Infallible;
+
+    fn from_str(raw: &str) -> Result<EmoteSetId, Infallible> {
+        Ok(EmoteSetId::from(raw))
+    }
+}
+
+impl fmt::Display for EmoteSetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmoteSetId::Numeric(id) => write!(f, "{}", id),
+            EmoteSetId::Other(raw) => f.write_str(raw),
+        }
+    }
+}
+
+/// Parse the value of an `emote-sets` tag (e.g. `0,33,19194`) into a sorted list without
+/// duplicates. Numeric IDs come first in ascending order, followed by all other IDs. Since the
+/// list is sorted, `binary_search()` can be used to check whether it contains an emote set.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::{parse_emote_sets, EmoteSetId};
+///
+/// let emote_sets = parse_emote_sets("19194,0,33,0");
+/// assert_eq!(
+///     emote_sets,
+///     vec![
+///         EmoteSetId::Numeric(0),
+///         EmoteSetId::Numeric(33),
+///         EmoteSetId::Numeric(19194)
+///     ]
+/// );
+/// assert!(emote_sets.binary_search(&EmoteSetId::Numeric(33)).is_ok());
+/// ```
+pub fn parse_emote_sets(src: &str) -> Vec<EmoteSetId> {
+    let mut emote_sets = src
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(EmoteSetId::from)
+        .collect::<Vec<_>>();
+    emote_sets.sort();
+    emote_sets.dedup();
+    emote_sets
+}
+
+/// Format a list of emote set IDs the same way as the `emote-sets` tag, e.g. `0,33,19194`.
+pub fn format_emote_sets(emote_sets: &[EmoteSetId]) -> String {
+    emote_sets.iter().map(|id| id.to_string()).join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{
+        format_emote_sets, parse_emote_sets, Emote, EmoteScale, EmoteSetId, EmoteTheme, SubPlan,
+    };
+
+    #[test]
+    fn test_sub_plan() {
+        for (raw, sub_plan) in [
+            ("Prime", SubPlan::Prime),
+            ("1000", SubPlan::Tier1),
+            ("2000", SubPlan::Tier2),
+            ("3000", SubPlan::Tier3),
+            ("4000", SubPlan::Other("4000".to_owned())),
+        ]
+        .iter()
+        {
+            assert_eq!(&raw.parse::<SubPlan>().unwrap(), sub_plan);
+            assert_eq!(&sub_plan.to_string(), raw);
+        }
+    }
+
+    #[test]
+    fn test_emote_image_url() {
+        let emote = |id: &str| Emote {
+            id: id.to_owned(),
+            char_range: 0..5,
+            code: "emote".to_owned(),
+        };
+        assert_eq!(
+            emote("25").image_url(EmoteTheme::Dark, EmoteScale::Small),
+            "https://static-cdn.jtvnw.net/emoticons/v2/25/default/dark/1.0"
+        );
+        assert_eq!(
+            emote("25").image_url(EmoteTheme::Light, EmoteScale::Medium),
+            "https://static-cdn.jtvnw.net/emoticons/v2/25/default/light/2.0"
+        );
+        assert_eq!(
+            emote("emotesv2_dcd06b30a5c24f6eb871e8f5edbd44f7")
+                .image_url(EmoteTheme::Dark, EmoteScale::Large),
+            "https://static-cdn.jtvnw.net/emoticons/v2/emotesv2_dcd06b30a5c24f6eb871e8f5edbd44f7/default/dark/3.0"
+        );
+        // modified emotes
+        assert_eq!(
+            emote("301512758_TK").image_url(EmoteTheme::Light, EmoteScale::Small),
+            "https://static-cdn.jtvnw.net/emoticons/v2/301512758_TK/default/light/1.0"
+        );
+    }
+
+    #[test]
+    fn test_emote_sets() {
+        let src = "0,33,42,19194,300374282,477339272,488737509,537206155,564265402,592920959,610186276,b4f5d3b1-8d04-4f8a-9a6b-1e1f2e3d4c5b,42,19194";
+        let emote_sets = parse_emote_sets(src);
+        assert_eq!(
+            emote_sets,
+            vec![
+                EmoteSetId::Numeric(0),
+                EmoteSetId::Numeric(33),
+                EmoteSetId::Numeric(42),
+                EmoteSetId::Numeric(19194),
+                EmoteSetId::Numeric(300374282),
+                EmoteSetId::Numeric(477339272),
+                EmoteSetId::Numeric(488737509),
+                EmoteSetId::Numeric(537206155),
+                EmoteSetId::Numeric(564265402),
+                EmoteSetId::Numeric(592920959),
+                EmoteSetId::Numeric(610186276),
+                EmoteSetId::Other("b4f5d3b1-8d04-4f8a-9a6b-1e1f2e3d4c5b".to_owned()),
+            ]
+        );
+        assert!(emote_sets
+            .binary_search(&EmoteSetId::Numeric(19194))
+            .is_ok());
+        assert!(emote_sets.binary_search(&EmoteSetId::Numeric(1)).is_err());
+        assert_eq!(
+            format_emote_sets(&emote_sets),
+            "0,33,42,19194,300374282,477339272,488737509,537206155,564265402,592920959,610186276,b4f5d3b1-8d04-4f8a-9a6b-1e1f2e3d4c5b"
+        );
+
+        assert_eq!(parse_emote_sets(""), vec![]);
+        assert_eq!(format_emote_sets(&[]), "");
+    }
+}
@@ -0,0 +1,115 @@
+//! Helpers to convert between `IRCMessage`s and the line-based text format of IRC.
+//!
+//! Twitch sends one IRC message per line, and each line is terminated by `\r\n`. These helpers
+//! can be used by `Transport` implementations to do this conversion, so they only have to
+//! take care of moving the text over the wire.
+
+use crate::message::{AsRawIRC, IRCMessage, IRCParseError};
+
+/// Parse a single line received from the server. The line may still include its trailing
+/// `\r\n` (or `\n`).
+///
+/// Returns `None` if the line is empty, since servers may send empty lines that should
+/// be ignored.
+pub fn decode_line(line: &str) -> Option<Result<IRCMessage, IRCParseError>> {
+    let line = line.trim_end_matches(&['\r', '\n'][..]);
+    if line.is_empty() {
+        None
+    } else {
+        Some(IRCMessage::parse(line))
+    }
+}
+
+/// Parse a chunk of text received from the server that can contain multiple lines, e.g. a
+/// single WebSocket message. Lines can be separated by `\r\n` or `\n`, and empty lines are
+/// skipped (see `decode_line`).
+pub fn decode_lines(text: &str) -> impl Iterator<Item = Result<IRCMessage, IRCParseError>> + '_ {
+    text.lines().filter_map(decode_line)
+}
+
+/// Format the message as a line to send to the server, including the terminating `\r\n`.
+pub fn encode_line(message: &IRCMessage) -> String {
+    let mut line = message.as_raw_irc();
+    line.push_str("\r\n");
+    line
+}
+
+/// Get the command of a raw line without fully parsing it, e.g. `PRIVMSG` for
+/// `@id=abc :a!a@a.tmi.twitch.tv PRIVMSG #a :hi`. Returns `None` if the line has no command.
+#[cfg(feature = "metrics-collection")]
+pub(crate) fn line_command(line: &str) -> Option<&str> {
+    let mut rest = line;
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ')?.1;
+    }
+    if rest.starts_with(':') {
+        rest = rest.split_once(' ')?.1;
+    }
+    rest.split(' ').next().filter(|command| !command.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_line, decode_lines, encode_line};
+    use crate::irc;
+    use crate::message::{IRCMessage, IRCParseError};
+
+    #[test]
+    fn test_decode_line() {
+        assert_eq!(
+            decode_line("PING :tmi.twitch.tv\r\n"),
+            Some(Ok(irc!["PING", "tmi.twitch.tv"]))
+        );
+        assert_eq!(
+            decode_line("PING :tmi.twitch.tv"),
+            Some(Ok(irc!["PING", "tmi.twitch.tv"]))
+        );
+        assert_eq!(decode_line(""), None);
+        assert_eq!(decode_line("\r\n"), None);
+        assert_eq!(
+            decode_line("@ PING"),
+            Some(Err(IRCParseError::EmptyTagsDeclaration))
+        );
+    }
+
+    #[test]
+    fn test_decode_lines() {
+        let text = "PING :tmi.twitch.tv\r\n\r\n:tmi.twitch.tv RECONNECT\nPONG :abc";
+        let messages: Vec<_> = decode_lines(text).collect();
+        assert_eq!(
+            messages,
+            vec![
+                Ok(irc!["PING", "tmi.twitch.tv"]),
+                IRCMessage::parse(":tmi.twitch.tv RECONNECT"),
+                Ok(irc!["PONG", "abc"]),
+            ]
+        );
+
+        assert_eq!(decode_lines("").count(), 0);
+    }
+
+    #[test]
+    fn test_encode_line() {
+        assert_eq!(
+            encode_line(&irc!["PRIVMSG", "#pajlada", "Kappa Keepo"]),
+            "PRIVMSG #pajlada :Kappa Keepo\r\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "metrics-collection")]
+    fn test_line_command() {
+        use super::line_command;
+
+        assert_eq!(line_command("@badge-info=;badges=;color=;display-name=Test\\sUser;emotes=;user-type= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Pajapains 👉 test"), Some("PRIVMSG"));
+        assert_eq!(
+            line_command(":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!"),
+            Some("001")
+        );
+        assert_eq!(line_command("PING :tmi.twitch.tv"), Some("PING"));
+        assert_eq!(line_command("RECONNECT"), Some("RECONNECT"));
+        assert_eq!(line_command(""), None);
+        assert_eq!(line_command("@id=abc"), None);
+        assert_eq!(line_command(":tmi.twitch.tv"), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! In-memory `Transport` implementation used to test the client and connection logic
+//! without connecting to Twitch.
+//!
+//! Every time a `MockTransport` is connected, the server side of that connection is stored
+//! as a `MockConnection`, which the test can then pick up using `wait_for_connections()`.
+//! These are stored thread-locally: `#[tokio::test]` uses a single-threaded runtime, so
+//! all tasks spawned by a test run on the thread of that test, and tests running in
+//! parallel don't see each other's connections.
+
+use crate::config::RawLineHook;
+use crate::message::{IRCMessage, IRCParseError};
+use crate::transport::framing;
+use crate::transport::{Transport, TransportOptions};
+use async_trait::async_trait;
+use futures_util::sink::Sink;
+use futures_util::stream::{Fuse, Stream, StreamExt};
+use itertools::Either;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+type MockIncomingItem = Result<IRCMessage, Either<MockTransportError, IRCParseError>>;
+
+thread_local! {
+    static CONNECTIONS: RefCell<VecDeque<MockConnection>> = const { RefCell::new(VecDeque::new()) };
+    static CONNECT_FAILING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Error type for all of the `MockTransport` error types. Only emitted by the transport itself
+/// when connecting while `set_connect_failing(true)`, but can be fed into the incoming stream
+/// by a test.
+#[derive(Debug, Error)]
+#[error("mock transport error")]
+pub struct MockTransportError;
+
+/// The "server side" of a connected `MockTransport`.
+pub struct MockConnection {
+    /// When the transport was connected.
+    pub connected_at: Instant,
+    /// Feed messages (or errors) to the client. Dropping this simulates the server
+    /// closing the connection.
+    pub incoming_tx: mpsc::UnboundedSender<MockIncomingItem>,
+    /// Feed raw lines to the client, which are parsed by the transport like lines received
+    /// over a real connection.
+    pub raw_incoming_tx: mpsc::UnboundedSender<String>,
+    /// Messages that the client sent out over this connection.
+    pub outgoing_rx: mpsc::UnboundedReceiver<IRCMessage>,
+}
+
+/// Waits (for at most 5 seconds) until at least `count` connections have been made,
+/// then removes them from the list of pending connections and returns them, oldest first.
+pub async fn wait_for_connections(count: usize) -> Vec<MockConnection> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let connections = CONNECTIONS.with(|connections| {
+            let mut connections = connections.borrow_mut();
+            if connections.len() >= count {
+                Some(connections.drain(..count).collect::<Vec<_>>())
+            } else {
+                None
+            }
+        });
+        if let Some(connections) = connections {
+            return connections;
+        }
+        if Instant::now() > deadline {
+            panic!("timed out waiting for {} mock connection(s)", count);
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+/// While set to `true`, connecting a `MockTransport` fails with a `MockTransportError`
+/// (and no `MockConnection` is created).
+pub fn set_connect_failing(failing: bool) {
+    CONNECT_FAILING.with(|connect_failing| connect_failing.set(failing));
+}
+
+/// In-memory transport, see the module documentation.
+pub struct MockTransport {
+    incoming_messages: <Self as Transport>::Incoming,
+    outgoing_messages: <Self as Transport>::Outgoing,
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    type ConnectError = MockTransportError;
+    type IncomingError = MockTransportError;
+    type OutgoingError = MockTransportError;
+
+    type Incoming = Fuse<MockStream>;
+    type Outgoing = MockSink;
+
+    async fn new() -> Result<MockTransport, MockTransportError> {
+        MockTransport::connect(None)
+    }
+
+    async fn new_with_options(
+        options: TransportOptions,
+    ) -> Result<MockTransport, MockTransportError> {
+        MockTransport::connect(options.raw_line_hook)
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming_messages, self.outgoing_messages)
+    }
+}
+
+impl MockTransport {
+    fn connect(raw_line_hook: Option<RawLineHook>) -> Result<MockTransport, MockTransportError> {
+        if CONNECT_FAILING.with(Cell::get) {
+            return Err(MockTransportError);
+        }
+
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let (raw_incoming_tx, raw_incoming_rx) = mpsc::unbounded_channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+
+        CONNECTIONS.with(|connections| {
+            connections.borrow_mut().push_back(MockConnection {
+                connected_at: Instant::now(),
+                incoming_tx,
+                raw_incoming_tx,
+                outgoing_rx,
+            })
+        });
+
+        Ok(MockTransport {
+            incoming_messages: MockStream {
+                incoming_rx,
+                raw_incoming_rx,
+                raw_line_hook,
+            }
+            .fuse(),
+            outgoing_messages: MockSink(outgoing_tx),
+        })
+    }
+}
+
+impl std::fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockTransport").finish()
+    }
+}
+
+/// Incoming half of a `MockTransport`, yields everything sent to `MockConnection::incoming_tx`,
+/// and the parsed lines sent to `MockConnection::raw_incoming_tx`. The stream ends once
+/// `incoming_tx` is dropped.
+pub struct MockStream {
+    incoming_rx: mpsc::UnboundedReceiver<MockIncomingItem>,
+    raw_incoming_rx: mpsc::UnboundedReceiver<String>,
+    raw_line_hook: Option<RawLineHook>,
+}
+
+impl Stream for MockStream {
+    type Item = MockIncomingItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Poll::Ready(Some(line)) = this.raw_incoming_rx.poll_recv(cx) {
+            if let Some(raw_line_hook) = &this.raw_line_hook {
+                raw_line_hook.observe(&line);
+            }
+            // empty lines are skipped
+            if let Some(result) = framing::decode_line(&line) {
+                return Poll::Ready(Some(result.map_err(Either::Right)));
+            }
+        }
+        this.incoming_rx.poll_recv(cx)
+    }
+}
+
+/// Outgoing half of a `MockTransport`, forwards all messages to `MockConnection::outgoing_rx`.
+pub struct MockSink(mpsc::UnboundedSender<IRCMessage>);
+
+impl Sink<IRCMessage> for MockSink {
+    type Error = MockTransportError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: IRCMessage) -> Result<(), Self::Error> {
+        self.0.send(item).map_err(|_| MockTransportError)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,10 +1,14 @@
 //! Implements the different protocols for connecting to Twitch services.
 
+pub mod framing;
+#[cfg(test)]
+pub(crate) mod mock;
 #[cfg(feature = "transport-tcp")]
 pub mod tcp;
 #[cfg(feature = "transport-ws")]
 pub mod websocket;
 
+use crate::config::{AddressFamily, RawLineHook};
 use crate::message::{IRCMessage, IRCParseError};
 use async_trait::async_trait;
 use futures_util::{sink::Sink, stream::FusedStream};
@@ -33,6 +37,27 @@ pub trait Transport: Sized + Send + Sync + Debug + 'static {
     /// Try to create and connect a new `Transport` of this type. Returns `Ok(Self)` after
     /// the connection was established successfully.
     async fn new() -> Result<Self, Self::ConnectError>;
+    /// Same as `new()`, but connects according to the given `options`, which the client
+    /// takes from its `ClientConfig`. The client always creates its transports using this
+    /// method.
+    ///
+    /// The default implementation ignores all options and calls `new()`. Transports that
+    /// support any of the options should override it.
+    async fn new_with_options(options: TransportOptions) -> Result<Self, Self::ConnectError> {
+        drop(options); // not supported by this transport
+        Self::new().await
+    }
     /// Split this transport into its incoming and outgoing halves (streams).
     fn split(self) -> (Self::Incoming, Self::Outgoing);
 }
+
+/// Options for connecting a new transport, see `Transport::new_with_options`.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct TransportOptions {
+    /// If set, the transport calls this with every line received from the server, before the
+    /// line is parsed (see `ClientConfig::on_raw_line`).
+    pub raw_line_hook: Option<RawLineHook>,
+    /// Which IP versions may be used to connect, see `ClientConfig::address_family`.
+    pub address_family: AddressFamily,
+}
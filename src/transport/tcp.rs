@@ -1,13 +1,21 @@
 //! Implements connecting to Twitch services using the plain or secure standard IRC protocol.
 
-use crate::message::IRCMessage;
-use crate::message::{AsRawIRC, IRCParseError};
-use crate::transport::Transport;
+use crate::config::{AddressFamily, RawLineHook};
+use crate::message::{IRCMessage, IRCParseError};
+use crate::transport::framing;
+use crate::transport::{Transport, TransportOptions};
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures_util::{future, sink::Sink, stream::FusedStream, SinkExt, StreamExt, TryStreamExt};
-use itertools::Either;
+use futures_util::{
+    future,
+    sink::Sink,
+    stream::{FusedStream, FuturesUnordered},
+    SinkExt, StreamExt, TryStreamExt,
+};
+use itertools::{Either, Itertools};
 use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::io::BufReader;
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite};
@@ -18,6 +26,9 @@ use tokio_util::codec::{BytesCodec, FramedWrite};
 const TWITCH_SERVER_HOSTNAME: &'static str = "irc.chat.twitch.tv";
 const TWITCH_SERVER_PORT_NO_TLS: u16 = 6667;
 const TWITCH_SERVER_PORT_TLS: u16 = 6697;
+/// How long a connection attempt gets before the attempt to the next address is started in
+/// parallel, as recommended by RFC 8305 ("Happy Eyeballs").
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
 
 /// Implements connecting to Twitch chat via secured or unsecured plain IRC connection.
 pub struct TCPTransport<C: MakeConnection> {
@@ -28,18 +39,124 @@ pub struct TCPTransport<C: MakeConnection> {
 /// Error types that can occur while attempting to make a new connection with
 /// [`TCPTransport`](TCPTransport).
 ///
-/// Note that this enum has a different number of variants based on whether the
-/// `transport-tcp-native-tls` feature flag is enabled.
+/// The variants allow telling apart failures that are usually transient (e.g. `DNSError` or
+/// `TCPConnectError`) from failures that likely need intervention (e.g. a failed TLS handshake).
+/// They are preserved through `Error::ConnectError`.
+///
+/// Note that this enum has a different number of variants based on which TLS feature flag
+/// is enabled.
 #[derive(Debug, Error)]
 pub enum TCPTransportConnectError {
-    /// Any type of OS-specific I/O error occurred.
+    /// The hostname of the Twitch server could not be resolved.
+    #[error("Failed to resolve hostname: {0}")]
+    DNSError(std::io::Error),
+
+    /// The TCP connection could not be established, e.g. because it was refused by the server,
+    /// the network is unreachable, or the operating system's connect timeout expired.
+    #[error("Failed to connect: {0}")]
+    TCPConnectError(std::io::Error),
+
+    /// Any other type of OS-specific I/O error occurred.
     #[error("{0}")]
     IOError(#[from] std::io::Error),
 
-    /// OS-specific error types when using native TLS.
+    /// OS-specific error types when using native TLS, e.g. when the TLS handshake failed.
     #[cfg(feature = "transport-tcp-native-tls")]
     #[error("{0}")]
     TLSError(#[from] tokio_native_tls::native_tls::Error),
+
+    /// The TLS handshake failed, e.g. because the certificate of the server could not be
+    /// verified.
+    #[cfg(any(
+        feature = "transport-tcp-rustls-native-roots",
+        feature = "transport-tcp-rustls-webpki-roots"
+    ))]
+    #[error("TLS handshake failed: {0}")]
+    TLSHandshakeError(std::io::Error),
+}
+
+/// Resolves the hostname and opens a TCP connection to it, reporting failures of the two
+/// steps as different `TCPTransportConnectError`s.
+async fn connect_tcp(
+    host: &str,
+    port: u16,
+    address_family: AddressFamily,
+) -> Result<TcpStream, TCPTransportConnectError> {
+    let addresses = resolve(host, port, address_family).await?;
+    connect_happy_eyeballs(addresses)
+        .await
+        .map_err(TCPTransportConnectError::TCPConnectError)
+}
+
+/// Resolves all addresses of the hostname that belong to the given `address_family`.
+/// Fails if there are none.
+async fn resolve(
+    host: &str,
+    port: u16,
+    address_family: AddressFamily,
+) -> Result<Vec<SocketAddr>, TCPTransportConnectError> {
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(TCPTransportConnectError::DNSError)?
+        .filter(|address| match address_family {
+            AddressFamily::Any => true,
+            AddressFamily::IPv4Only => address.is_ipv4(),
+            AddressFamily::IPv6Only => address.is_ipv6(),
+        })
+        .collect::<Vec<_>>();
+    if addresses.is_empty() {
+        return Err(TCPTransportConnectError::DNSError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("hostname resolved to no {:?} addresses", address_family),
+        )));
+    }
+    Ok(addresses)
+}
+
+/// Orders the addresses so that IPv6 and IPv4 addresses alternate, starting with the IP
+/// version of the first address (the one the resolver prefers).
+fn interleave_address_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_ipv6 = addresses.first().is_some_and(SocketAddr::is_ipv6);
+    let (ipv6, ipv4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    let (preferred, other) = if prefer_ipv6 {
+        (ipv6, ipv4)
+    } else {
+        (ipv4, ipv6)
+    };
+    preferred.into_iter().interleave(other).collect()
+}
+
+/// Connects to the first address that accepts the connection ("Happy Eyeballs", RFC 8305).
+///
+/// The addresses are tried in the order of `interleave_address_families`. Every attempt gets
+/// `CONNECTION_ATTEMPT_DELAY` before the next one is started in parallel (or less, if it fails
+/// earlier). If all attempts fail, the error of the last one is returned.
+async fn connect_happy_eyeballs(addresses: Vec<SocketAddr>) -> std::io::Result<TcpStream> {
+    let mut remaining_addresses = interleave_address_families(addresses).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        match remaining_addresses.next() {
+            Some(address) => attempts.push(TcpStream::connect(address)),
+            None if attempts.is_empty() => {
+                return Err(last_error.unwrap_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses to connect to")
+                }))
+            }
+            None => {}
+        }
+
+        // wait until an attempt fails (then the next one is started right away), or until
+        // the newest attempt has had its head start
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            },
+            _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if remaining_addresses.len() > 0 => {}
+        }
+    }
 }
 
 /// Trait to parameterize [`TCPTransport`](TCPTransport) as secure or plain-text connection.
@@ -50,7 +167,11 @@ pub trait MakeConnection: 'static {
 
     /// Connect to Twitch servers and return the created socket. Depending on the implementation,
     /// the returned socket is either plain-text or wrapped using a TLS implementation.
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError>;
+    ///
+    /// Only addresses of the given `address_family` are connected to.
+    async fn new_socket(
+        address_family: AddressFamily,
+    ) -> Result<Self::Socket, TCPTransportConnectError>;
 }
 
 #[cfg(any(
@@ -77,11 +198,17 @@ pub struct TLS;
 impl MakeConnection for TLS {
     type Socket = tokio_native_tls::TlsStream<TcpStream>;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+    async fn new_socket(
+        address_family: AddressFamily,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
         use tokio_native_tls::native_tls;
 
-        let tcp_socket =
-            TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_TLS)).await?;
+        let tcp_socket = connect_tcp(
+            TWITCH_SERVER_HOSTNAME,
+            TWITCH_SERVER_PORT_TLS,
+            address_family,
+        )
+        .await?;
 
         let cx = native_tls::TlsConnector::new()?;
         let cx = tokio_native_tls::TlsConnector::from(cx);
@@ -98,7 +225,9 @@ impl MakeConnection for TLS {
 impl MakeConnection for TLS {
     type Socket = tokio_rustls::client::TlsStream<TcpStream>;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+    async fn new_socket(
+        address_family: AddressFamily,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
         use std::sync::Arc;
         use tokio_rustls::{rustls::ClientConfig, webpki::DNSNameRef, TlsConnector};
 
@@ -119,8 +248,16 @@ impl MakeConnection for TLS {
         let config = TlsConnector::from(Arc::new(config));
         let dnsname = DNSNameRef::try_from_ascii_str(TWITCH_SERVER_HOSTNAME).unwrap();
 
-        let stream = TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_TLS)).await?;
-        Ok(config.connect(dnsname, stream).await?)
+        let stream = connect_tcp(
+            TWITCH_SERVER_HOSTNAME,
+            TWITCH_SERVER_PORT_TLS,
+            address_family,
+        )
+        .await?;
+        config
+            .connect(dnsname, stream)
+            .await
+            .map_err(TCPTransportConnectError::TLSHandshakeError)
     }
 }
 
@@ -131,8 +268,15 @@ pub struct NoTLS;
 impl MakeConnection for NoTLS {
     type Socket = TcpStream;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
-        Ok(TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_NO_TLS)).await?)
+    async fn new_socket(
+        address_family: AddressFamily,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
+        connect_tcp(
+            TWITCH_SERVER_HOSTNAME,
+            TWITCH_SERVER_PORT_NO_TLS,
+            address_family,
+        )
+        .await
     }
 }
 
@@ -166,37 +310,79 @@ impl<C: MakeConnection> Transport for TCPTransport<C> {
     type Outgoing = Box<dyn Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync>;
 
     async fn new() -> Result<TCPTransport<C>, TCPTransportConnectError> {
-        let socket = C::new_socket().await?;
+        TCPTransport::connect(TransportOptions::default()).await
+    }
+
+    async fn new_with_options(
+        options: TransportOptions,
+    ) -> Result<TCPTransport<C>, TCPTransportConnectError> {
+        TCPTransport::connect(options).await
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming_messages, self.outgoing_messages)
+    }
+}
+
+impl<C: MakeConnection> TCPTransport<C> {
+    async fn connect(
+        options: TransportOptions,
+    ) -> Result<TCPTransport<C>, TCPTransportConnectError> {
+        let socket = C::new_socket(options.address_family).await?;
         let (read_half, write_half) = tokio::io::split(socket);
 
-        // TODO if tokio re-adds stream support revert to:
-        // let message_stream = BufReader::new(read_half)
-        //     .lines()
-        // then continue with .try_filter() from below
-        let lines = BufReader::new(read_half).lines();
-        let message_stream = LinesStream::new(lines)
-            // ignore empty lines
-            .try_filter(|line| future::ready(!line.is_empty()))
-            .map_err(Either::Left)
-            .and_then(|s| future::ready(IRCMessage::parse(&s).map_err(Either::Right)))
-            .fuse();
+        let message_stream = decode_incoming(read_half, options.raw_line_hook);
 
         let message_sink =
             FramedWrite::new(write_half, BytesCodec::new()).with(move |msg: IRCMessage| {
-                let mut s = msg.as_raw_irc();
-                s.push_str("\r\n");
-                future::ready(Ok(Bytes::from(s)))
+                future::ready(Ok(Bytes::from(framing::encode_line(&msg))))
             });
 
         Ok(TCPTransport {
-            incoming_messages: Box::new(message_stream),
+            incoming_messages: message_stream,
             outgoing_messages: Box::new(message_sink),
         })
     }
+}
 
-    fn split(self) -> (Self::Incoming, Self::Outgoing) {
-        (self.incoming_messages, self.outgoing_messages)
-    }
+/// Splits the data read from the socket into lines, and parses them.
+///
+/// A message can arrive split over multiple reads (e.g. TCP segments), `lines()` buffers
+/// the partial line until its terminating newline is received. The line itself is not limited
+/// by the capacity of the `BufReader` (that only limits how much is read at once),
+/// so very long lines (e.g. messages with many emotes) are not truncated either.
+fn decode_incoming<R>(
+    read_half: R,
+    raw_line_hook: Option<RawLineHook>,
+) -> Box<
+    dyn FusedStream<Item = Result<IRCMessage, Either<std::io::Error, IRCParseError>>>
+        + Unpin
+        + Send
+        + Sync,
+>
+where
+    R: AsyncRead + Unpin + Send + Sync + 'static,
+{
+    // TODO if tokio re-adds stream support revert to:
+    // let message_stream = BufReader::new(read_half)
+    //     .lines()
+    // then continue with .try_filter() from below
+    let lines = BufReader::new(read_half).lines();
+    let message_stream = LinesStream::new(lines)
+        .map_err(Either::Left)
+        // empty lines are ignored
+        .try_filter_map(move |line| {
+            if let Some(raw_line_hook) = &raw_line_hook {
+                raw_line_hook.observe(&line);
+            }
+            future::ready(
+                framing::decode_line(&line)
+                    .transpose()
+                    .map_err(Either::Right),
+            )
+        })
+        .fuse();
+    Box::new(message_stream)
 }
 
 impl<C: MakeConnection> std::fmt::Debug for TCPTransport<C> {
@@ -204,3 +390,153 @@ impl<C: MakeConnection> std::fmt::Debug for TCPTransport<C> {
         f.debug_struct("TCPTransport").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        connect_happy_eyeballs, connect_tcp, decode_incoming, interleave_address_families, resolve,
+        TCPTransportConnectError,
+    };
+    use crate::config::AddressFamily;
+    use crate::message::IRCMessage;
+    use futures_util::StreamExt;
+    use std::collections::VecDeque;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    /// Returns the given chunks one by one, one chunk per read at most, as if each chunk
+    /// was received in a separate TCP segment.
+    struct ChunkedReader(VecDeque<Vec<u8>>);
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let chunks = &mut self.get_mut().0;
+            if let Some(mut chunk) = chunks.pop_front() {
+                if chunk.len() > buf.remaining() {
+                    chunks.push_front(chunk.split_off(buf.remaining()));
+                }
+                buf.put_slice(&chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_incoming_reassembles_lines() {
+        // well over the 8 KiB default capacity of the BufReader
+        let emotes = (0..3000)
+            .map(|i| format!("{}-{}", i * 6, i * 6 + 4))
+            .collect::<Vec<_>>()
+            .join(",");
+        let long_line = format!(
+            "@emotes=25:{};id=1 :a!a@a.tmi.twitch.tv PRIVMSG #pajlada :{}",
+            emotes,
+            "Kappa ".repeat(3000).trim_end()
+        );
+        let input = format!("{}\r\nPING :tmi.twitch.tv\r\n", long_line).into_bytes();
+
+        // split in the middle of the long line, and between the \r and \n
+        let split_at = [long_line.len() / 2, long_line.len() + 1];
+        let chunks = VecDeque::from(vec![
+            input[..split_at[0]].to_vec(),
+            input[split_at[0]..split_at[1]].to_vec(),
+            input[split_at[1]..].to_vec(),
+        ]);
+
+        let messages = decode_incoming(ChunkedReader(chunks), None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            messages,
+            vec![
+                IRCMessage::parse(&long_line).unwrap(),
+                IRCMessage::parse("PING :tmi.twitch.tv").unwrap(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_tcp_dns_error() {
+        // the .invalid top-level domain is guaranteed to never resolve
+        let result = connect_tcp("irc.chat.twitch.invalid", 6667, AddressFamily::Any).await;
+        assert!(matches!(result, Err(TCPTransportConnectError::DNSError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connect_tcp_refused() {
+        // find a free port, and make sure nothing listens on it anymore
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let result = connect_tcp("127.0.0.1", port, AddressFamily::Any).await;
+        assert!(matches!(
+            result,
+            Err(TCPTransportConnectError::TCPConnectError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_connect_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(connect_tcp("127.0.0.1", port, AddressFamily::Any)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_connect_tcp_ipv4_only() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // "localhost" usually resolves to ::1 as well, which nothing listens on
+        let addresses = resolve("localhost", port, AddressFamily::IPv4Only)
+            .await
+            .unwrap();
+        assert!(addresses.iter().all(SocketAddr::is_ipv4));
+        assert!(connect_tcp("localhost", port, AddressFamily::IPv4Only)
+            .await
+            .is_ok());
+
+        let result = resolve("127.0.0.1", port, AddressFamily::IPv6Only).await;
+        assert!(matches!(result, Err(TCPTransportConnectError::DNSError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connect_happy_eyeballs_skips_failing_address() {
+        let refused = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused_address = refused.local_addr().unwrap();
+        drop(refused);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listening_address = listener.local_addr().unwrap();
+
+        let stream = connect_happy_eyeballs(vec![refused_address, listening_address])
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listening_address);
+
+        let result = connect_happy_eyeballs(vec![refused_address]).await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_interleave_address_families() {
+        let addresses = ["[::1]:1", "[::2]:1", "[::3]:1", "1.1.1.1:1", "2.2.2.2:1"]
+            .iter()
+            .map(|address| address.parse::<SocketAddr>().unwrap())
+            .collect::<Vec<_>>();
+        let expected = ["[::1]:1", "1.1.1.1:1", "[::2]:1", "2.2.2.2:1", "[::3]:1"]
+            .iter()
+            .map(|address| address.parse::<SocketAddr>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(interleave_address_families(addresses), expected);
+    }
+}
@@ -1,8 +1,10 @@
 //! Implements connecting to Twitch services using the plain or secure IRC-over-WebSocket protocol.
 
+use crate::config::RawLineHook;
 use crate::message::IRCMessage;
 use crate::message::{AsRawIRC, IRCParseError};
-use crate::transport::Transport;
+use crate::transport::framing;
+use crate::transport::{Transport, TransportOptions};
 use async_trait::async_trait;
 use async_tungstenite::tokio::connect_async;
 use async_tungstenite::tungstenite::Error as WSError;
@@ -82,21 +84,42 @@ impl<C: ConnectionUri> Transport for WSTransport<C> {
     type Outgoing = Box<dyn Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync>;
 
     async fn new() -> Result<WSTransport<C>, WSError> {
+        WSTransport::connect(None).await
+    }
+
+    async fn new_with_options(options: TransportOptions) -> Result<WSTransport<C>, WSError> {
+        WSTransport::connect(options.raw_line_hook).await
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming_messages, self.outgoing_messages)
+    }
+}
+
+impl<C: ConnectionUri> WSTransport<C> {
+    async fn connect(raw_line_hook: Option<RawLineHook>) -> Result<WSTransport<C>, WSError> {
         let (ws_stream, _response) = connect_async(C::get_server_uri()).await?;
 
         let (write_half, read_half) = ws_stream.split();
 
         let message_stream = read_half
             .map_err(Either::Left)
-            .try_filter_map(|ws_message| {
+            .try_filter_map(move |ws_message| {
                 future::ready(Ok::<_, Either<WSError, IRCParseError>>(
                     if let WSMessage::Text(text) = ws_message {
                         // the server can send multiple IRC messages in one websocket message,
                         // separated by newlines
+                        // (empty lines are ignored)
                         Some(stream::iter(
                             text.lines()
-                                .map(|l| Ok(String::from(l)))
-                                .collect::<SmallVec<[Result<String, _>; 1]>>(),
+                                .inspect(|line| {
+                                    if let Some(raw_line_hook) = &raw_line_hook {
+                                        raw_line_hook.observe(line);
+                                    }
+                                })
+                                .filter_map(framing::decode_line)
+                                .map(|result| result.map_err(Either::Right))
+                                .collect::<SmallVec<[_; 1]>>(),
                         ))
                     } else {
                         None
@@ -104,9 +127,6 @@ impl<C: ConnectionUri> Transport for WSTransport<C> {
                 ))
             })
             .try_flatten()
-            // filter empty lines
-            .try_filter(|line| future::ready(!line.is_empty()))
-            .and_then(|s| future::ready(IRCMessage::parse(&s).map_err(Either::Right)))
             .fuse();
 
         let message_sink = write_half
@@ -117,10 +137,6 @@ impl<C: ConnectionUri> Transport for WSTransport<C> {
             outgoing_messages: Box::new(message_sink),
         })
     }
-
-    fn split(self) -> (Self::Incoming, Self::Outgoing) {
-        (self.incoming_messages, self.outgoing_messages)
-    }
 }
 
 impl<C: ConnectionUri> std::fmt::Debug for WSTransport<C> {